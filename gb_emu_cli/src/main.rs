@@ -1,6 +1,19 @@
-use std::fs;
 use clap::Parser;
-use gb_emu_common::GameBoy;
+use gb_emu_common::apu::APU_CLOCK;
+use gb_emu_common::audio_recorder::{stem_path, AudioRecorder, WavOutput};
+use gb_emu_common::checksum::crc32;
+use gb_emu_common::gbs::{is_gbs, Gbs};
+use gb_emu_common::model::Model;
+use gb_emu_common::movie::bk2::import_bk2;
+use gb_emu_common::movie::Movie;
+use gb_emu_common::network_link::NetworkLink;
+use gb_emu_common::printer::Printer;
+use gb_emu_common::serial::SerialCapture;
+use gb_emu_common::{GameBoy, CYCLES_PER_FRAME};
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
@@ -11,16 +24,111 @@ struct Args {
 
     #[clap(long)]
     log: bool,
+
+    /// Plays a movie (.gbm or BizHawk .bk2) and prints a hash of the final state
+    #[clap(long)]
+    play_movie: Option<String>,
+
+    /// Hardware to emulate: DMG0, DMG, MGB, SGB, SGB2, CGB or AGB
+    #[clap(long, default_value = "DMG")]
+    model: String,
+
+    /// Runs a DMG or CGB boot ROM before the game
+    #[clap(long)]
+    boot_rom: Option<String>,
+
+    /// Waits for another emulator to connect its link cable on this address (e.g. 127.0.0.1:8765)
+    #[clap(long, conflicts_with = "link-join")]
+    link_host: Option<String>,
+
+    /// Connects the link cable to an emulator hosting on this address
+    #[clap(long, conflicts_with = "printer")]
+    link_join: Option<String>,
+
+    /// Connects a Game Boy Printer, printed pages are saved as PNG files in this directory
+    #[clap(long, conflicts_with = "link-host")]
+    printer: Option<String>,
+
+    /// Records the audio to this WAV file
+    #[clap(long)]
+    record_audio: Option<String>,
+
+    /// Also records each sound channel to its own WAV file, next to the one of --record-audio
+    #[clap(long, requires = "record-audio")]
+    audio_stems: bool,
+
+    /// Logs the writes to the sound registers to this VGM file
+    #[clap(long)]
+    record_vgm: Option<String>,
+
+    /// Stops after running this amount of frames
+    #[clap(long)]
+    frames: Option<u64>,
+
+    /// Stops after this amount of emulated seconds, to render a GBS track with --record-audio
+    #[clap(long, conflicts_with = "frames")]
+    seconds: Option<u64>,
+
+    /// Track of a GBS file to play, counting from 1. Defaults to the file's first track.
+    #[clap(long)]
+    track: Option<u8>,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let rom_path = args.input_file;
-    let rom = fs::read(rom_path)?;
+    let rom = fs::read(&args.input_file)?;
     let mut gb = GameBoy::new();
-    gb.load_rom(rom)?;
+    gb.set_model(Model::from_name(&args.model).ok_or("Unknown model")?);
+    if let Some(boot_rom_path) = &args.boot_rom {
+        gb.set_boot_rom(Some(fs::read(boot_rom_path)?))?;
+    }
+    if is_gbs(&rom) {
+        let gbs = Gbs::parse(&rom)?;
+        let track = args.track.unwrap_or(gbs.first_song + 1);
+        if track == 0 || track > gbs.song_count {
+            return Err(format!("The GBS file only has {} tracks", gbs.song_count).into());
+        }
+        println!(
+            "Track {track}/{}: {} - {}",
+            gbs.song_count, gbs.title, gbs.author
+        );
+        gb.load_gbs(&gbs, track - 1)?;
+    } else {
+        gb.load_rom(rom)?;
+    }
+    if let Some(address) = &args.link_host {
+        println!("Waiting for the other emulator on {address}");
+        gb.connect_serial_device(Some(Box::new(NetworkLink::host(address)?)));
+    } else if let Some(address) = &args.link_join {
+        gb.connect_serial_device(Some(Box::new(NetworkLink::join(address)?)));
+    } else if args.printer.is_some() {
+        gb.connect_serial_device(Some(Box::new(Printer::new())));
+    } else {
+        // test ROMs print their results through the link port
+        gb.connect_serial_device(Some(Box::new(SerialCapture::new())));
+    }
+
+    if let Some(path) = &args.record_audio {
+        let recorder = create_audio_recorder(path, args.audio_stems, gb.sample_rate())?;
+        gb.start_audio_recording(recorder);
+    }
+
+    if args.record_vgm.is_some() {
+        gb.start_vgm_logging();
+    }
+
+    if let Some(movie_path) = &args.play_movie {
+        let movie = read_movie(movie_path, gb.rom_hash)?;
+        gb.play_movie(movie)?;
+    }
 
+    let frame_limit = args
+        .seconds
+        .map(|seconds| seconds * APU_CLOCK as u64 / CYCLES_PER_FRAME as u64)
+        .or(args.frames);
+
+    let mut page_number = 1;
     let mut i = 1;
     loop {
         let pc = gb.cpu.pc;
@@ -43,9 +151,109 @@ fn main() -> Result<()> {
         let result = gb.step();
         if let Err(err) = result {
             println!("{err}");
+            stop_recordings(&mut gb, &args, &mut page_number)?;
             std::process::exit(1);
         }
 
+        if let Some(capture) = gb.serial_device_mut::<SerialCapture>() {
+            let output = capture.take_output();
+            if !output.is_empty() {
+                print!("{}", String::from_utf8_lossy(&output));
+            }
+        }
+
+        if let Some(directory) = &args.printer {
+            save_printed_pages(&mut gb, Path::new(directory), &mut page_number)?;
+        }
+
+        if let Some(movie) = &gb.movie {
+            if movie.is_finished() {
+                let frames = movie.movie.frames.len();
+                let state_hash = crc32(&gb.save_state()?);
+                println!("Movie finished after {frames} frames, state hash: {state_hash:08X}");
+                stop_recordings(&mut gb, &args, &mut page_number)?;
+                return Ok(());
+            }
+        }
+
+        if frame_limit.is_some_and(|frames| gb.frame_count() >= frames) {
+            stop_recordings(&mut gb, &args, &mut page_number)?;
+            return Ok(());
+        }
+
         i += 1;
     }
 }
+
+fn stop_recordings(gb: &mut GameBoy, args: &Args, page_number: &mut u32) -> Result<()> {
+    gb.stop_audio_recording()?;
+    if let (Some(path), Some(vgm)) = (&args.record_vgm, gb.stop_vgm_logging()) {
+        fs::write(path, vgm)?;
+    }
+
+    if let Some(directory) = &args.printer {
+        if let Some(printer) = gb.serial_device_mut::<Printer>() {
+            printer.finish_page();
+        }
+        save_printed_pages(gb, Path::new(directory), page_number)?;
+    }
+
+    Ok(())
+}
+
+/// Saves the pages that came out of the printer, numbered after the ones already there
+fn save_printed_pages(gb: &mut GameBoy, directory: &Path, page_number: &mut u32) -> Result<()> {
+    let Some(printer) = gb.serial_device_mut::<Printer>() else {
+        return Ok(());
+    };
+
+    for page in printer.take_pages() {
+        // earlier runs start counting from 1 too
+        let path = loop {
+            let path = directory.join(format!("page_{page_number}.png"));
+            *page_number += 1;
+            if !path.exists() {
+                break path;
+            }
+        };
+        fs::write(&path, page.to_png())?;
+        println!("Printed {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn create_audio_recorder(path: &str, with_stems: bool, sample_rate: u32) -> Result<AudioRecorder> {
+    let path = Path::new(path);
+    let create = |path: &Path| -> Result<Box<dyn WavOutput>> {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    };
+
+    let stems = if with_stems {
+        Some([
+            create(&stem_path(path, 0))?,
+            create(&stem_path(path, 1))?,
+            create(&stem_path(path, 2))?,
+            create(&stem_path(path, 3))?,
+        ])
+    } else {
+        None
+    };
+
+    Ok(AudioRecorder::new(create(path)?, stems, sample_rate)?)
+}
+
+fn read_movie(path: &str, rom_hash: u32) -> Result<Movie> {
+    let data = fs::read(path)?;
+    let is_bk2 = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("bk2"));
+
+    let movie = if is_bk2 {
+        import_bk2(&data, rom_hash)?
+    } else {
+        Movie::from_bytes(&data)?
+    };
+
+    Ok(movie)
+}
@@ -0,0 +1,171 @@
+use crate::error::Result;
+use crate::save_state::{StateReader, StateWriter};
+
+/// Turns the channel off once the sound has played for the given duration
+#[derive(Clone, Copy)]
+pub struct LengthCounter {
+    pub is_enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+/// Fades the volume of a channel in or out
+#[derive(Clone, Copy)]
+pub struct Envelope {
+    /// NRx2 - Initial volume, direction and period
+    register: u8,
+    pub volume: u8,
+    timer: u8,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            is_enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    /// Loads the length bits of NRx1
+    pub fn load(&mut self, length: u8) {
+        self.counter = self.max - length as u16;
+    }
+
+    /// Length clocks left until the channel is turned off
+    pub const fn remaining(&self) -> u16 {
+        self.counter
+    }
+
+    /// Returns true when the channel has to be turned off
+    pub fn clock(&mut self) -> bool {
+        if self.is_enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+
+        false
+    }
+
+    /// Handles a write to NRx4. Enabling the counter during a step of the frame sequencer
+    /// that doesn't clock it still clocks it once. Returns true when the channel has to
+    /// be turned off.
+    pub fn write_control(
+        &mut self,
+        is_enabled: bool,
+        trigger: bool,
+        is_length_step_next: bool,
+    ) -> bool {
+        let was_enabled = self.is_enabled;
+        self.is_enabled = is_enabled;
+
+        let mut turn_off = false;
+        if !was_enabled && is_enabled && !is_length_step_next && self.counter > 0 {
+            self.counter -= 1;
+            turn_off = self.counter == 0 && !trigger;
+        }
+
+        if trigger && self.counter == 0 {
+            self.counter = if is_enabled && !is_length_step_next {
+                self.max - 1
+            } else {
+                self.max
+            };
+        }
+
+        turn_off
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        writer.write_u16(self.counter);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.is_enabled = reader.read_bool()?;
+        self.counter = reader.read_u16()?.min(self.max);
+        Ok(())
+    }
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn write_register(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    /// The upper 5 bits of NRx2 power the DAC of the channel
+    pub const fn is_dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    /// Volume loaded on trigger
+    pub const fn initial_volume(&self) -> u8 {
+        self.register >> 4
+    }
+
+    pub const fn is_increasing(&self) -> bool {
+        self.register & 0x08 != 0
+    }
+
+    /// Frame sequencer steps between volume changes, 0 keeps the volume fixed
+    pub const fn pace(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    pub fn clock(&mut self) {
+        if self.register & 0x07 == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period();
+            let is_increasing = self.register & 0x08 != 0;
+            if is_increasing && self.volume < 15 {
+                self.volume += 1;
+            } else if !is_increasing && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    /// A period of 0 is treated as 8
+    fn period(&self) -> u8 {
+        match self.register & 0x07 {
+            0 => 8,
+            period => period,
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.timer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.register = reader.read_u8()?;
+        self.volume = reader.read_u8()? & 0x0F;
+        self.timer = reader.read_u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,614 @@
+pub mod envelope;
+pub mod noise;
+pub mod oscilloscope;
+pub mod square;
+pub mod synth;
+pub mod wave;
+
+use crate::audio_recorder::AudioRecorder;
+use crate::error::Result;
+use crate::save_state::{StateReader, StateWriter};
+use crate::vgm::VgmLogger;
+use noise::NoiseChannel;
+use oscilloscope::Oscilloscope;
+use square::SquareChannel;
+use synth::Mixer;
+use wave::{WaveChannel, WAVE_RAM_SIZE};
+
+/// Audio processing unit. Mixes the 4 sound channels into stereo samples at the
+/// configured sample rate, for the frontend to play. The amplitude changes of the
+/// channels are synthesized as band-limited steps at the exact T-cycle they happen.
+pub struct Apu {
+    /// NR52 bit 7 - All the registers are cleared and ignore writes while off
+    is_powered: bool,
+    /// FF10-FF26, as last written
+    registers: [u8; REGISTER_AMOUNT],
+    pub square1: SquareChannel,
+    pub square2: SquareChannel,
+    pub wave: WaveChannel,
+    pub noise: NoiseChannel,
+    /// Next step of the frame sequencer, which clocks the length counters, the sweep
+    /// and the envelopes
+    frame_sequencer_step: u8,
+    /// The length counters keep working while the APU is off on DMG
+    pub is_cgb: bool,
+    /// T-cycles since the samples were last generated
+    clock: u32,
+    /// Contribution of each channel to the left and right outputs
+    levels: [[f32; 2]; 4],
+    /// Mixes the samples that are played, at a rate the frontend can adjust
+    mixer: Mixer,
+    /// Audio recording, with a mixer of its own that stays at the rate of the files
+    recording: Option<(AudioRecorder, Mixer)>,
+    pub vgm_logger: Option<VgmLogger>,
+    /// Recent outputs of the channels, only kept while a debugger shows them
+    pub oscilloscope: Option<Oscilloscope>,
+    /// Channels left out of the mix. The recorded stems still have them.
+    pub muted_channels: [bool; 4],
+    /// Channel that is heard alone, over the muted ones
+    pub solo_channel: Option<usize>,
+    /// Interleaved left and right samples, from -1 to 1
+    samples: Vec<f32>,
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu {
+            is_powered: false,
+            registers: [0; REGISTER_AMOUNT],
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            frame_sequencer_step: 0,
+            is_cgb: false,
+            clock: 0,
+            levels: [[0.0; 2]; 4],
+            mixer: Mixer::new(APU_CLOCK, DEFAULT_SAMPLE_RATE),
+            recording: None,
+            vgm_logger: None,
+            oscilloscope: None,
+            muted_channels: [false; 4],
+            solo_channel: None,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Turns the APU off and clears the wave RAM, keeping the sample rate and the
+    /// frontend settings
+    pub fn reset(&mut self) {
+        self.generate_samples();
+        let apu = std::mem::take(self);
+        self.mixer = apu.mixer;
+        self.recording = apu.recording;
+        self.samples = apu.samples;
+        self.vgm_logger = apu.vgm_logger;
+        self.oscilloscope = apu.oscilloscope;
+        self.muted_channels = apu.muted_channels;
+        self.solo_channel = apu.solo_channel;
+        // the outputs fall from where they were
+        self.levels = apu.levels;
+        self.update_levels();
+    }
+
+    /// Leaves the registers like the boot ROM does after playing its sound
+    pub fn apply_post_boot_state(&mut self, is_cgb: bool, has_played_sound: bool) {
+        self.is_cgb = is_cgb;
+        self.write_register(SOUND_ON_REGISTER, 0x80);
+        self.write_register(NR11, 0x80);
+        self.write_register(NR12, 0xF3);
+        self.write_register(NR50, 0x77);
+        self.write_register(NR51, 0xF3);
+        // the sound has faded out, but the channel stays on
+        self.square1.is_enabled = has_played_sound;
+    }
+
+    pub const fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    /// Sets the amount of samples per second and per side. Can be changed smoothly while
+    /// playing, to keep the audio in sync with the frontend.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let sample_rate = sample_rate.clamp(1, APU_CLOCK);
+        if sample_rate == self.sample_rate() {
+            return;
+        }
+
+        // the steps so far are resampled at the old rate
+        self.generate_samples();
+        self.mixer.set_sample_rate(sample_rate);
+    }
+
+    /// Records the audio from now on at the sample rate of the recorder, replacing the
+    /// current recording without finishing it
+    pub fn start_recording(&mut self, recorder: AudioRecorder) {
+        self.generate_samples();
+        let mut mixer = Mixer::new(APU_CLOCK, recorder.sample_rate());
+        for (channel, sides) in self.levels.iter().enumerate() {
+            for (side, &level) in sides.iter().enumerate() {
+                mixer.add_delta(channel, side, 0, level);
+            }
+        }
+        self.recording = Some((recorder, mixer));
+    }
+
+    /// Ends the recording with the samples generated so far
+    pub fn stop_recording(&mut self) -> Option<AudioRecorder> {
+        self.generate_samples();
+        self.recording.take().map(|(recorder, _)| recorder)
+    }
+
+    pub const fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Takes the samples generated since the last call, left and right interleaved
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.generate_samples();
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Runs for the given amount of T-cycles at normal speed
+    pub fn run(&mut self, mut cycles: u32) {
+        if let Some(logger) = &mut self.vgm_logger {
+            logger.run(cycles);
+        }
+
+        while cycles > 0 {
+            // run until the next time a channel output can change
+            let step = cycles
+                .min(self.square1.cycles_until_step())
+                .min(self.square2.cycles_until_step())
+                .min(self.wave.cycles_until_step())
+                .min(self.noise.cycles_until_step());
+            if self.oscilloscope.is_some() {
+                let outputs = self.channel_outputs();
+                if let Some(oscilloscope) = &mut self.oscilloscope {
+                    oscilloscope.run(step, outputs);
+                }
+            }
+            self.square1.run(step);
+            self.square2.run(step);
+            self.wave.run(step);
+            self.noise.run(step);
+            cycles -= step;
+            self.clock += step;
+            self.update_levels();
+        }
+
+        if self.clock >= SYNTHESIS_CYCLES {
+            self.generate_samples();
+        }
+    }
+
+    /// Whether the channel is part of the mix, following the mute and solo settings
+    pub fn is_channel_audible(&self, channel: usize) -> bool {
+        match self.solo_channel {
+            Some(solo_channel) => channel == solo_channel,
+            None => !self.muted_channels[channel],
+        }
+    }
+
+    /// Adds a step to the buffers of the channels whose level changed
+    fn update_levels(&mut self) {
+        let levels = self.channel_levels();
+        for (channel, sides) in levels.iter().enumerate() {
+            for (side, &level) in sides.iter().enumerate() {
+                let delta = level - self.levels[channel][side];
+                if delta != 0.0 {
+                    self.mixer.add_delta(channel, side, self.clock, delta);
+                    if let Some((_, mixer)) = &mut self.recording {
+                        mixer.add_delta(channel, side, self.clock, delta);
+                    }
+                }
+            }
+        }
+        self.levels = levels;
+    }
+
+    /// Converts the channel outputs to analog and pans them with NR50 and NR51
+    fn channel_levels(&self) -> [[f32; 2]; 4] {
+        let outputs = self.channel_outputs();
+        let dacs = [
+            self.square1.envelope.is_dac_enabled(),
+            self.square2.envelope.is_dac_enabled(),
+            self.wave.is_dac_enabled,
+            self.noise.envelope.is_dac_enabled(),
+        ];
+        let panning = self.registers[NR51 - REGISTERS_START];
+        let volume = self.registers[NR50 - REGISTERS_START];
+        let left_volume = ((volume >> 4) & 0x07) as f32 + 1.0;
+        let right_volume = (volume & 0x07) as f32 + 1.0;
+
+        let mut levels = [[0.0; 2]; 4];
+        for channel in 0..4 {
+            if !dacs[channel] {
+                continue;
+            }
+
+            // the DACs map 0 to 15 to a voltage between -1 and 1
+            let analog = outputs[channel] as f32 / 7.5 - 1.0;
+            if panning & (0x10 << channel) != 0 {
+                levels[channel][0] = analog * left_volume / 32.0;
+            }
+            if panning & (0x01 << channel) != 0 {
+                levels[channel][1] = analog * right_volume / 32.0;
+            }
+        }
+
+        levels
+    }
+
+    /// Resamples the steps so far and mixes the channels, done regularly while running
+    pub fn generate_samples(&mut self) {
+        let audible_channels: [bool; 4] = std::array::from_fn(|i| self.is_channel_audible(i));
+        let (mix, _) = self
+            .mixer
+            .mix(self.clock, self.is_cgb, audible_channels, false);
+        if let Some((recorder, mixer)) = &mut self.recording {
+            let (mix, stems) = mixer.mix(
+                self.clock,
+                self.is_cgb,
+                audible_channels,
+                recorder.has_stems(),
+            );
+            recorder.write(&mix, &stems);
+        }
+        self.clock = 0;
+
+        // nobody is playing the samples, only keep the last second
+        let max_samples = self.sample_rate() as usize * 2 * MAX_BUFFERED_SECONDS;
+        if self.samples.len() >= max_samples {
+            self.samples.drain(..max_samples / 2);
+        }
+        self.samples.extend(mix);
+    }
+
+    /// Clears the synthesis buffers, the current levels are added back as new steps
+    fn restart_synthesis(&mut self) {
+        self.mixer.clear();
+        if let Some((_, mixer)) = &mut self.recording {
+            mixer.clear();
+        }
+        self.clock = 0;
+        self.levels = [[0.0; 2]; 4];
+        self.update_levels();
+    }
+
+    /// Digital outputs of the 4 channels, from 0 to 15
+    pub fn channel_outputs(&self) -> [u8; 4] {
+        [
+            self.square1.output(),
+            self.square2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ]
+    }
+
+    /// Clocked at 512 Hz by DIV
+    pub fn step_frame_sequencer(&mut self) {
+        if !self.is_powered {
+            return;
+        }
+
+        let step = self.frame_sequencer_step;
+        if step.is_multiple_of(2) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if step == 2 || step == 6 {
+            self.square1.clock_sweep();
+        }
+        if step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+
+        self.frame_sequencer_step = (step + 1) % 8;
+        self.update_levels();
+    }
+
+    pub fn read_register(&self, address: usize) -> u8 {
+        match address {
+            WAVE_RAM_START..=WAVE_RAM_END => self.wave.read_ram(address - WAVE_RAM_START),
+            SOUND_ON_REGISTER => {
+                let channels = [
+                    self.square1.is_enabled,
+                    self.square2.is_enabled,
+                    self.wave.is_enabled,
+                    self.noise.is_enabled,
+                ];
+                let status = channels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |status, (i, &is_enabled)| {
+                        status | (is_enabled as u8) << i
+                    });
+                (self.is_powered as u8) << 7 | 0x70 | status
+            }
+            REGISTERS_START..=NR51 => {
+                let index = address - REGISTERS_START;
+                self.registers[index] | READ_MASKS[index]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: usize, value: u8) {
+        if let Some(logger) = &mut self.vgm_logger {
+            logger.log_write(address, value);
+        }
+
+        match address {
+            WAVE_RAM_START..=WAVE_RAM_END => self.wave.write_ram(address - WAVE_RAM_START, value),
+            SOUND_ON_REGISTER => {
+                let is_powered = value & 0x80 != 0;
+                if self.is_powered && !is_powered {
+                    self.power_off();
+                } else if !self.is_powered && is_powered {
+                    self.frame_sequencer_step = 0;
+                }
+                self.is_powered = is_powered;
+            }
+            // on DMG the length counters can still be loaded while off
+            _ if !self.is_powered && !self.is_cgb => match address {
+                NR11 => self.square1.write_length_only(value),
+                NR21 => self.square2.write_length_only(value),
+                NR31 => self.wave.write_length(value),
+                NR41 => self.noise.write_length(value),
+                _ => {}
+            },
+            _ if !self.is_powered => {}
+            REGISTERS_START..=NR51 => {
+                self.registers[address - REGISTERS_START] = value;
+                self.write_channel_register(address, value);
+            }
+            _ => {}
+        }
+        self.update_levels();
+    }
+
+    fn write_channel_register(&mut self, address: usize, value: u8) {
+        let is_length_step_next = self.frame_sequencer_step.is_multiple_of(2);
+        match address {
+            NR10 => self.square1.write_sweep(value),
+            NR11 => self.square1.write_length(value),
+            NR12 => self.square1.write_envelope(value),
+            NR13 => self.square1.write_frequency_low(value),
+            NR14 => self.square1.write_control(value, is_length_step_next),
+            NR21 => self.square2.write_length(value),
+            NR22 => self.square2.write_envelope(value),
+            NR23 => self.square2.write_frequency_low(value),
+            NR24 => self.square2.write_control(value, is_length_step_next),
+            NR30 => self.wave.write_dac(value),
+            NR31 => self.wave.write_length(value),
+            NR32 => self.wave.write_volume(value),
+            NR33 => self.wave.write_frequency_low(value),
+            NR34 => self.wave.write_control(value, is_length_step_next),
+            NR41 => self.noise.write_length(value),
+            NR42 => self.noise.write_envelope(value),
+            NR43 => self.noise.write_polynomial(value),
+            NR44 => self.noise.write_control(value, is_length_step_next),
+            _ => {}
+        }
+    }
+
+    /// Starts logging the register writes to VGM. The log starts with the current
+    /// registers, without restarting the channels that are playing.
+    pub fn start_vgm_logging(&mut self) {
+        let mut logger = VgmLogger::new();
+        logger.log_write(SOUND_ON_REGISTER, (self.is_powered as u8) << 7);
+        for (i, &byte) in self.wave.ram.iter().enumerate() {
+            logger.log_write(WAVE_RAM_START + i, byte);
+        }
+        if self.is_powered {
+            for address in REGISTERS_START..SOUND_ON_REGISTER {
+                let value = self.registers[address - REGISTERS_START];
+                let value = match address {
+                    NR14 | NR24 | NR34 | NR44 => value & 0x7F,
+                    _ => value,
+                };
+                logger.log_write(address, value);
+            }
+        }
+
+        self.vgm_logger = Some(logger);
+    }
+
+    /// Clears every register but the wave RAM
+    fn power_off(&mut self) {
+        let wave_ram = self.wave.ram;
+        let lengths = [
+            self.square1.length,
+            self.square2.length,
+            self.wave.length,
+            self.noise.length,
+        ];
+
+        self.registers = [0; REGISTER_AMOUNT];
+        self.square1 = SquareChannel::new(true);
+        self.square2 = SquareChannel::new(false);
+        self.wave = WaveChannel::new();
+        self.wave.ram = wave_ram;
+        self.noise = NoiseChannel::new();
+
+        if !self.is_cgb {
+            let [square1, square2, wave, noise] = lengths.map(|mut length| {
+                length.is_enabled = false;
+                length
+            });
+            self.square1.length = square1;
+            self.square2.length = square2;
+            self.wave.length = wave;
+            self.noise.length = noise;
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_powered);
+        writer.write_bytes(&self.registers);
+        self.square1.save_state(writer);
+        self.square2.save_state(writer);
+        self.wave.save_state(writer);
+        self.noise.save_state(writer);
+        writer.write_u8(self.frame_sequencer_step);
+        writer.write_bool(self.is_cgb);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.is_powered = reader.read_bool()?;
+        reader.read_bytes(&mut self.registers)?;
+        self.square1.load_state(reader)?;
+        self.square2.load_state(reader)?;
+        self.wave.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.frame_sequencer_step = reader.read_u8()? % 8;
+        self.is_cgb = reader.read_bool()?;
+        self.restart_synthesis();
+        Ok(())
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Names of the channels, in the order they're numbered
+pub const CHANNEL_NAMES: [&str; 4] = ["square1", "square2", "wave", "noise"];
+
+/// T-cycles per second at normal speed
+pub const APU_CLOCK: u32 = 4_194_304;
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+const MAX_BUFFERED_SECONDS: usize = 1;
+/// Samples are generated about every millisecond
+const SYNTHESIS_CYCLES: u32 = 4096;
+
+pub const NR10: usize = 0xFF10;
+pub const NR11: usize = 0xFF11;
+pub const NR12: usize = 0xFF12;
+pub const NR13: usize = 0xFF13;
+pub const NR14: usize = 0xFF14;
+pub const NR21: usize = 0xFF16;
+pub const NR22: usize = 0xFF17;
+pub const NR23: usize = 0xFF18;
+pub const NR24: usize = 0xFF19;
+pub const NR30: usize = 0xFF1A;
+pub const NR31: usize = 0xFF1B;
+pub const NR32: usize = 0xFF1C;
+pub const NR33: usize = 0xFF1D;
+pub const NR34: usize = 0xFF1E;
+pub const NR41: usize = 0xFF20;
+pub const NR42: usize = 0xFF21;
+pub const NR43: usize = 0xFF22;
+pub const NR44: usize = 0xFF23;
+/// Master volume and VIN panning
+pub const NR50: usize = 0xFF24;
+/// Sound panning
+pub const NR51: usize = 0xFF25;
+/// NR52 - Sound on/off
+pub const SOUND_ON_REGISTER: usize = 0xFF26;
+
+pub const REGISTERS_START: usize = 0xFF10;
+pub const REGISTERS_END: usize = 0xFF2F;
+const REGISTER_AMOUNT: usize = SOUND_ON_REGISTER - REGISTERS_START + 1;
+pub const WAVE_RAM_START: usize = 0xFF30;
+pub const WAVE_RAM_END: usize = WAVE_RAM_START + WAVE_RAM_SIZE - 1;
+
+/// Bits that always read as 1, write-only and unused bits included
+const READ_MASKS: [u8; REGISTER_AMOUNT] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_read_masks() {
+        let mut apu = Apu::new();
+        apu.write_register(SOUND_ON_REGISTER, 0x80);
+        for address in REGISTERS_START..SOUND_ON_REGISTER {
+            apu.write_register(address, 0x00);
+        }
+        let values: Vec<u8> = (NR10..=NR14)
+            .map(|address| apu.read_register(address))
+            .collect();
+        assert_eq!(values, [0x80, 0x3F, 0x00, 0xFF, 0xBF]);
+        assert_eq!(apu.read_register(0xFF27), 0xFF);
+
+        // powering off clears the registers and ignores writes
+        apu.write_register(NR50, 0x77);
+        apu.write_register(SOUND_ON_REGISTER, 0x00);
+        apu.write_register(NR50, 0x77);
+        assert_eq!(apu.read_register(NR50), 0x00);
+        assert_eq!(apu.read_register(SOUND_ON_REGISTER), 0x70);
+    }
+
+    #[test]
+    fn muted_channels_leave_the_mix() {
+        let mut apu = Apu::new();
+        apu.write_register(SOUND_ON_REGISTER, 0x80);
+        apu.write_register(NR50, 0x77);
+        apu.write_register(NR51, 0xFF);
+        apu.write_register(NR22, 0xF0);
+        apu.write_register(NR24, 0x87);
+        apu.muted_channels[1] = true;
+        apu.oscilloscope = Some(Oscilloscope::new());
+        apu.run(APU_CLOCK / 100);
+        apu.generate_samples();
+        assert!(apu.take_samples().iter().all(|&sample| sample == 0.0));
+        let trace = &apu.oscilloscope.as_ref().unwrap().traces[1];
+        assert!(trace.contains(&15));
+
+        // a solo channel is heard even if it's muted
+        apu.solo_channel = Some(1);
+        apu.run(APU_CLOCK / 100);
+        apu.generate_samples();
+        assert!(apu.take_samples().iter().any(|&sample| sample != 0.0));
+    }
+
+    #[test]
+    fn length_counter_turns_the_channel_off() {
+        let mut apu = Apu::new();
+        apu.write_register(SOUND_ON_REGISTER, 0x80);
+        apu.write_register(NR22, 0xF0);
+        // a length of 62 lasts 2 length clocks
+        apu.write_register(NR21, 62);
+        apu.write_register(NR24, 0xC0);
+        assert_eq!(apu.read_register(SOUND_ON_REGISTER) & 0x02, 0x02);
+
+        apu.step_frame_sequencer();
+        apu.step_frame_sequencer();
+        assert_eq!(apu.read_register(SOUND_ON_REGISTER) & 0x02, 0x02);
+        apu.step_frame_sequencer();
+        assert_eq!(apu.read_register(SOUND_ON_REGISTER) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn generates_samples_at_the_sample_rate() {
+        let mut apu = Apu::new();
+        apu.set_sample_rate(32_768);
+        apu.write_register(SOUND_ON_REGISTER, 0x80);
+        apu.write_register(NR50, 0x77);
+        apu.write_register(NR51, 0x22);
+        apu.write_register(NR22, 0xF0);
+        apu.write_register(NR21, 0x80);
+        apu.write_register(NR24, 0x87);
+        apu.run(APU_CLOCK / 8);
+
+        let samples = apu.take_samples();
+        assert_eq!(samples.len(), 32_768 / 8 * 2);
+        assert!(samples.iter().any(|&sample| sample > 0.0));
+        assert!(samples.iter().all(|&sample| (-1.0..=1.0).contains(&sample)));
+    }
+}
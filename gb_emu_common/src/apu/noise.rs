@@ -0,0 +1,148 @@
+use super::envelope::{Envelope, LengthCounter};
+use super::APU_CLOCK;
+use crate::error::Result;
+use crate::save_state::{StateReader, StateWriter};
+
+/// Channel 4, white noise from a linear feedback shift register
+pub struct NoiseChannel {
+    pub is_enabled: bool,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    /// NR43 - Clock shift, LFSR width and clock divider
+    register: u8,
+    lfsr: u16,
+    /// T-cycles until the next LFSR shift
+    timer: u32,
+}
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+impl NoiseChannel {
+    pub fn new() -> NoiseChannel {
+        NoiseChannel {
+            is_enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            register: 0,
+            lfsr: 0,
+            timer: period(0),
+        }
+    }
+
+    /// Digital output, from 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.is_enabled || self.lfsr & 1 != 0 {
+            return 0;
+        }
+
+        self.envelope.volume
+    }
+
+    pub fn run(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = period(self.register);
+            // shifts 14 and 15 don't clock the LFSR at all
+            if self.register >> 4 < 14 {
+                self.shift_lfsr();
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    /// T-cycles until the output can change
+    pub const fn cycles_until_step(&self) -> u32 {
+        self.timer
+    }
+
+    /// Times per second the LFSR is shifted
+    pub fn frequency_hz(&self) -> f32 {
+        if self.register >> 4 >= 14 {
+            return 0.0;
+        }
+
+        APU_CLOCK as f32 / period(self.register) as f32
+    }
+
+    /// The LFSR is 7 bits long instead of 15, which sounds more metallic
+    pub const fn is_short_mode(&self) -> bool {
+        self.register & 0x08 != 0
+    }
+
+    fn shift_lfsr(&mut self) {
+        let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+        // the 7-bit mode also puts the new bit in bit 6
+        if self.register & 0x08 != 0 {
+            self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+        }
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    pub fn write_envelope(&mut self, value: u8) {
+        self.envelope.write_register(value);
+        if !self.envelope.is_dac_enabled() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn write_polynomial(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    pub fn write_control(&mut self, value: u8, is_length_step_next: bool) {
+        let trigger = value & 0x80 != 0;
+        if self
+            .length
+            .write_control(value & 0x40 != 0, trigger, is_length_step_next)
+        {
+            self.is_enabled = false;
+        }
+
+        if trigger {
+            self.is_enabled = self.envelope.is_dac_enabled();
+            self.lfsr = 0x7FFF;
+            self.timer = period(self.register);
+            self.envelope.trigger();
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u8(self.register);
+        writer.write_u16(self.lfsr);
+        writer.write_u32(self.timer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.is_enabled = reader.read_bool()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.register = reader.read_u8()?;
+        self.lfsr = reader.read_u16()? & 0x7FFF;
+        self.timer = reader.read_u32()?.clamp(1, period(self.register));
+        Ok(())
+    }
+}
+
+impl Default for NoiseChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// T-cycles between LFSR shifts
+const fn period(register: u8) -> u32 {
+    DIVISORS[(register & 0x07) as usize] << (register >> 4)
+}
@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+
+/// T-cycles between the points of the traces
+pub const OSCILLOSCOPE_PERIOD: u32 = 64;
+/// Points kept for each channel, about a frame
+pub const OSCILLOSCOPE_LENGTH: usize = 1024;
+
+/// Keeps the recent digital outputs of the channels, for debuggers to draw them
+pub struct Oscilloscope {
+    /// Outputs of each channel from 0 to 15, oldest first
+    pub traces: [VecDeque<u8>; 4],
+    cycles_until_point: u32,
+}
+
+impl Oscilloscope {
+    pub fn new() -> Oscilloscope {
+        Oscilloscope {
+            traces: std::array::from_fn(|_| VecDeque::from(vec![0; OSCILLOSCOPE_LENGTH])),
+            cycles_until_point: OSCILLOSCOPE_PERIOD,
+        }
+    }
+
+    /// Adds the points of a stretch of T-cycles in which the outputs didn't change
+    pub fn run(&mut self, mut cycles: u32, outputs: [u8; 4]) {
+        while cycles >= self.cycles_until_point {
+            cycles -= self.cycles_until_point;
+            self.cycles_until_point = OSCILLOSCOPE_PERIOD;
+            for (trace, output) in self.traces.iter_mut().zip(outputs) {
+                trace.pop_front();
+                trace.push_back(output);
+            }
+        }
+        self.cycles_until_point -= cycles;
+    }
+}
+
+impl Default for Oscilloscope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,282 @@
+use super::envelope::{Envelope, LengthCounter};
+use super::APU_CLOCK;
+use crate::error::Result;
+use crate::save_state::{StateReader, StateWriter};
+
+/// Channels 1 and 2, a square wave with a selectable duty cycle. Channel 1 can also
+/// sweep its frequency.
+pub struct SquareChannel {
+    pub is_enabled: bool,
+    pub sweep: Option<Sweep>,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    /// T-cycles until the next duty step
+    timer: u32,
+}
+
+/// NR10 - Channel 1 frequency sweep
+#[derive(Clone, Copy)]
+pub struct Sweep {
+    register: u8,
+    is_enabled: bool,
+    shadow_frequency: u16,
+    timer: u8,
+    /// A negative sweep was calculated since the last trigger
+    has_negated: bool,
+}
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+impl SquareChannel {
+    pub fn new(has_sweep: bool) -> SquareChannel {
+        SquareChannel {
+            is_enabled: false,
+            sweep: has_sweep.then(Sweep::new),
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: period(0),
+        }
+    }
+
+    /// Digital output, from 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.is_enabled {
+            return 0;
+        }
+
+        DUTY_CYCLES[self.duty as usize][self.duty_step as usize] * self.envelope.volume
+    }
+
+    pub fn run(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = period(self.frequency);
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    /// T-cycles until the output can change
+    pub const fn cycles_until_step(&self) -> u32 {
+        self.timer
+    }
+
+    /// Duty cycle from NRx1, 0 to 3 for 12.5%, 25%, 50% and 75%
+    pub const fn duty(&self) -> u8 {
+        self.duty
+    }
+
+    /// Frequency of the square wave in Hz
+    pub fn frequency_hz(&self) -> f32 {
+        APU_CLOCK as f32 / (period(self.frequency) * 8) as f32
+    }
+
+    pub fn write_sweep(&mut self, value: u8) {
+        if let Some(sweep) = &mut self.sweep {
+            if sweep.write_register(value) {
+                self.is_enabled = false;
+            }
+        }
+    }
+
+    /// NRx1 - Duty cycle and length
+    pub fn write_length(&mut self, value: u8) {
+        self.duty = value >> 6;
+        self.length.load(value & 0x3F);
+    }
+
+    /// Only the length can be written while the APU is off on DMG
+    pub fn write_length_only(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    pub fn write_envelope(&mut self, value: u8) {
+        self.envelope.write_register(value);
+        if !self.envelope.is_dac_enabled() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x0700) | value as u16;
+    }
+
+    /// NRx4 - Trigger, length enable and the upper frequency bits
+    pub fn write_control(&mut self, value: u8, is_length_step_next: bool) {
+        self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x07) << 8);
+        let trigger = value & 0x80 != 0;
+        if self
+            .length
+            .write_control(value & 0x40 != 0, trigger, is_length_step_next)
+        {
+            self.is_enabled = false;
+        }
+
+        if trigger {
+            self.is_enabled = self.envelope.is_dac_enabled();
+            self.timer = period(self.frequency);
+            self.envelope.trigger();
+            if let Some(sweep) = &mut self.sweep {
+                if sweep.trigger(self.frequency) {
+                    self.is_enabled = false;
+                }
+            }
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if let Some(sweep) = &mut self.sweep {
+            if sweep.clock(&mut self.frequency) {
+                self.is_enabled = false;
+            }
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(writer);
+        }
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.duty_step);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.is_enabled = reader.read_bool()?;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(reader)?;
+        }
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.duty = reader.read_u8()? & 0x03;
+        self.duty_step = reader.read_u8()? & 0x07;
+        self.frequency = reader.read_u16()? & 0x07FF;
+        self.timer = reader.read_u32()?.clamp(1, period(self.frequency));
+        Ok(())
+    }
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep {
+            register: 0,
+            is_enabled: false,
+            shadow_frequency: 0,
+            timer: 0,
+            has_negated: false,
+        }
+    }
+
+    /// Returns true when the channel has to be turned off, which happens when leaving
+    /// the negative mode after it was used
+    fn write_register(&mut self, value: u8) -> bool {
+        let was_negative = self.register & 0x08 != 0;
+        self.register = value;
+        was_negative && value & 0x08 == 0 && self.has_negated
+    }
+
+    /// Returns true when the channel has to be turned off because of an overflow
+    fn trigger(&mut self, frequency: u16) -> bool {
+        self.shadow_frequency = frequency;
+        self.timer = self.period();
+        self.has_negated = false;
+        self.is_enabled = self.register & 0x77 != 0;
+        self.shift() != 0 && self.calculate() > 2047
+    }
+
+    fn clock(&mut self, frequency: &mut u16) -> bool {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer != 0 {
+            return false;
+        }
+
+        self.timer = self.period();
+        if !self.is_enabled || self.register & 0x70 == 0 {
+            return false;
+        }
+
+        let new_frequency = self.calculate();
+        if new_frequency > 2047 {
+            return true;
+        }
+        if self.shift() != 0 {
+            self.shadow_frequency = new_frequency;
+            *frequency = new_frequency;
+            // the overflow check runs again with the new frequency
+            return self.calculate() > 2047;
+        }
+
+        false
+    }
+
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift();
+        if self.register & 0x08 != 0 {
+            self.has_negated = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+
+    const fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    /// A period of 0 is treated as 8
+    fn period(&self) -> u8 {
+        match (self.register >> 4) & 0x07 {
+            0 => 8,
+            period => period,
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_bool(self.is_enabled);
+        writer.write_u16(self.shadow_frequency);
+        writer.write_u8(self.timer);
+        writer.write_bool(self.has_negated);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.register = reader.read_u8()?;
+        self.is_enabled = reader.read_bool()?;
+        self.shadow_frequency = reader.read_u16()? & 0x07FF;
+        self.timer = reader.read_u8()? & 0x0F;
+        self.has_negated = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Sweep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// T-cycles between duty steps
+const fn period(frequency: u16) -> u32 {
+    (2048 - frequency as u32) * 4
+}
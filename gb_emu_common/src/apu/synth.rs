@@ -0,0 +1,271 @@
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+/// Band-limited synthesis buffer. Amplitude changes are added at the time they happen in
+/// the APU clock, as a band-limited step placed with sub-sample precision, which also
+/// resamples them to the output rate without aliasing.
+pub struct BlipBuffer {
+    /// Output samples per APU clock, in fixed point
+    factor: u64,
+    /// Position of the start of the current frame, in fixed point output samples
+    offset: u64,
+    /// Derivative of the output, integrated when read
+    buffer: Vec<f32>,
+    integrator: f32,
+}
+
+/// Resamples the steps of the four channels at a sample rate and mixes them
+pub struct Mixer {
+    clock_rate: u32,
+    sample_rate: u32,
+    /// Steps of each channel, for the left and right outputs
+    buffers: [[BlipBuffer; 2]; 4],
+    /// Output capacitors of the left and right sides
+    high_pass_filters: [HighPassFilter; 2],
+    /// Capacitors for the channels heard on their own
+    stem_filters: [[HighPassFilter; 2]; 4],
+}
+
+/// DC-blocking filter, like the capacitor on the audio output of the real hardware
+#[derive(Clone, Copy, Default)]
+pub struct HighPassFilter {
+    capacitor: f32,
+}
+
+const FRACTION_BITS: u32 = 32;
+const PHASE_BITS: u32 = 8;
+const PHASES: usize = 1 << PHASE_BITS;
+/// Samples on each side of a step, the output is delayed by this amount
+const HALF_WIDTH: usize = 16;
+const TAPS: usize = HALF_WIDTH * 2;
+/// Cutoff of the low-pass filter relative to the output rate, a bit below Nyquist
+const CUTOFF: f64 = 0.45;
+
+impl BlipBuffer {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> BlipBuffer {
+        BlipBuffer {
+            factor: ((sample_rate as u64) << FRACTION_BITS) / clock_rate as u64,
+            offset: 0,
+            buffer: vec![0.0; TAPS],
+            integrator: 0.0,
+        }
+    }
+
+    /// Changes the output rate, the steps already added keep their position
+    pub fn set_rates(&mut self, clock_rate: u32, sample_rate: u32) {
+        self.factor = ((sample_rate as u64) << FRACTION_BITS) / clock_rate as u64;
+    }
+
+    /// Adds an amplitude change at the given clock of the current frame
+    pub fn add_delta(&mut self, time: u32, delta: f32) {
+        let position = self.offset + time as u64 * self.factor;
+        let index = (position >> FRACTION_BITS) as usize;
+        let phase = (position >> (FRACTION_BITS - PHASE_BITS)) as usize & (PHASES - 1);
+
+        if self.buffer.len() < index + TAPS {
+            self.buffer.resize(index + TAPS, 0.0);
+        }
+        let kernel = &kernel()[phase];
+        for (sample, &tap) in self.buffer[index..index + TAPS].iter_mut().zip(kernel) {
+            *sample += delta * tap;
+        }
+    }
+
+    /// Ends the current frame after the given amount of clocks, its samples can be read
+    pub fn end_frame(&mut self, time: u32) {
+        self.offset += time as u64 * self.factor;
+    }
+
+    pub fn samples_available(&self) -> usize {
+        (self.offset >> FRACTION_BITS) as usize
+    }
+
+    /// Reads the given amount of samples, which have to be available. They're removed
+    /// from the buffer.
+    pub fn read_samples(&mut self, output: &mut [f32]) {
+        let count = output.len();
+        if self.buffer.len() < count + TAPS {
+            self.buffer.resize(count + TAPS, 0.0);
+        }
+        for (output, &delta) in output.iter_mut().zip(&self.buffer) {
+            self.integrator += delta;
+            *output = self.integrator;
+        }
+
+        self.buffer.drain(..count);
+        self.offset -= (count as u64) << FRACTION_BITS;
+    }
+
+    pub fn clear(&mut self) {
+        self.offset = 0;
+        self.buffer = vec![0.0; TAPS];
+        self.integrator = 0.0;
+    }
+}
+
+impl Mixer {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> Mixer {
+        Mixer {
+            clock_rate,
+            sample_rate,
+            buffers: std::array::from_fn(|_| {
+                std::array::from_fn(|_| BlipBuffer::new(clock_rate, sample_rate))
+            }),
+            high_pass_filters: [HighPassFilter::new(); 2],
+            stem_filters: [[HighPassFilter::new(); 2]; 4],
+        }
+    }
+
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Changes the output rate, the steps already added keep their position
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for buffer in self.buffers.iter_mut().flatten() {
+            buffer.set_rates(self.clock_rate, sample_rate);
+        }
+    }
+
+    /// Adds an amplitude change of a channel on the left (0) or right (1) side
+    pub fn add_delta(&mut self, channel: usize, side: usize, time: u32, delta: f32) {
+        self.buffers[channel][side].add_delta(time, delta);
+    }
+
+    /// Ends the frame after the given amount of clocks. Returns the interleaved samples
+    /// of the audible channels mixed, and of each channel on its own if `with_stems` is set.
+    pub fn mix(
+        &mut self,
+        time: u32,
+        is_cgb: bool,
+        audible_channels: [bool; 4],
+        with_stems: bool,
+    ) -> (Vec<f32>, [Vec<f32>; 4]) {
+        for buffer in self.buffers.iter_mut().flatten() {
+            buffer.end_frame(time);
+        }
+
+        let count = self.buffers[0][0].samples_available();
+        let charge_factor = charge_factor(is_cgb, self.clock_rate, self.sample_rate);
+        let mut mix = vec![0.0; count * 2];
+        let mut stems: [Vec<f32>; 4] = Default::default();
+        let mut channel_samples = vec![0.0; count];
+        for (channel, buffers) in self.buffers.iter_mut().enumerate() {
+            if with_stems {
+                stems[channel] = vec![0.0; count * 2];
+            }
+
+            for (side, buffer) in buffers.iter_mut().enumerate() {
+                buffer.read_samples(&mut channel_samples);
+                if audible_channels[channel] {
+                    for (i, &sample) in channel_samples.iter().enumerate() {
+                        mix[i * 2 + side] += sample;
+                    }
+                }
+
+                let filter = &mut self.stem_filters[channel][side];
+                for (i, &sample) in channel_samples
+                    .iter()
+                    .enumerate()
+                    .take(stems[channel].len() / 2)
+                {
+                    stems[channel][i * 2 + side] = filter.apply(sample, charge_factor);
+                }
+            }
+        }
+
+        for (i, sample) in mix.iter_mut().enumerate() {
+            *sample = self.high_pass_filters[i % 2].apply(*sample, charge_factor);
+        }
+
+        (mix, stems)
+    }
+
+    pub fn clear(&mut self) {
+        for buffer in self.buffers.iter_mut().flatten() {
+            buffer.clear();
+        }
+    }
+}
+
+impl HighPassFilter {
+    pub fn new() -> HighPassFilter {
+        HighPassFilter { capacitor: 0.0 }
+    }
+
+    /// `charge_factor` is how much charge the capacitor keeps between samples
+    pub fn apply(&mut self, input: f32, charge_factor: f32) -> f32 {
+        let output = input - self.capacitor;
+        self.capacitor = input - output * charge_factor;
+        output
+    }
+}
+
+/// Charge the output capacitor keeps per sample. The one of the CGB discharges faster.
+pub fn charge_factor(is_cgb: bool, clock_rate: u32, sample_rate: u32) -> f32 {
+    let per_clock: f64 = if is_cgb { 0.998943 } else { 0.999958 };
+    per_clock.powf(clock_rate as f64 / sample_rate as f64) as f32
+}
+
+/// Impulse response of the low-pass filter for every sub-sample position of a step,
+/// a Blackman-windowed sinc
+fn kernel() -> &'static [[f32; TAPS]] {
+    static KERNEL: OnceLock<Vec<[f32; TAPS]>> = OnceLock::new();
+    KERNEL.get_or_init(|| {
+        (0..PHASES)
+            .map(|phase| {
+                let fraction = phase as f64 / PHASES as f64;
+                let mut taps = [0.0; TAPS];
+                for (tap, value) in taps.iter_mut().enumerate() {
+                    let x = tap as f64 - HALF_WIDTH as f64 - fraction;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x)
+                    };
+                    let window = 0.42
+                        + 0.5 * (PI * x / HALF_WIDTH as f64).cos()
+                        + 0.08 * (2.0 * PI * x / HALF_WIDTH as f64).cos();
+                    *value = sinc * window.max(0.0);
+                }
+
+                // every step has to end at exactly the height of its delta
+                let sum: f64 = taps.iter().sum();
+                taps.map(|value| (value / sum) as f32)
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_settle_at_their_height() {
+        let mut buffer = BlipBuffer::new(4_194_304, 48_000);
+        buffer.add_delta(1000, 0.5);
+        buffer.end_frame(70_224);
+
+        let mut samples = vec![0.0; buffer.samples_available()];
+        buffer.read_samples(&mut samples);
+        assert!(samples[..5].iter().all(|&sample| sample == 0.0));
+        assert!(samples[100..]
+            .iter()
+            .all(|&sample| (sample - 0.5).abs() < 1e-4));
+        // the step overshoots a little, but doesn't ring for long
+        assert!(samples.iter().all(|&sample| sample < 0.6));
+    }
+
+    #[test]
+    fn high_pass_removes_dc() {
+        let mut filter = HighPassFilter::new();
+        let charge_factor = charge_factor(true, 4_194_304, 48_000);
+        let mut output = 0.0;
+        for _ in 0..48_000 {
+            output = filter.apply(0.5, charge_factor);
+        }
+        assert!(output.abs() < 1e-3);
+    }
+}
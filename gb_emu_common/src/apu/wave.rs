@@ -0,0 +1,185 @@
+use super::envelope::LengthCounter;
+use super::APU_CLOCK;
+use crate::error::Result;
+use crate::save_state::{StateReader, StateWriter};
+
+/// Channel 3, plays the 32 4-bit samples of the wave RAM
+pub struct WaveChannel {
+    pub is_enabled: bool,
+    /// NR30 bit 7 - DAC power
+    pub is_dac_enabled: bool,
+    pub length: LengthCounter,
+    /// NR32 - Output level, 0 is muted and 1 to 3 shift the samples by 0 to 2
+    volume_code: u8,
+    frequency: u16,
+    /// T-cycles until the next sample
+    timer: u32,
+    /// Index of the sample being played
+    position: u8,
+    /// The last sample read from the wave RAM
+    sample_buffer: u8,
+    /// FF30-FF3F - Wave pattern RAM
+    pub ram: [u8; WAVE_RAM_SIZE],
+}
+
+pub const WAVE_RAM_SIZE: usize = 16;
+
+/// The first sample is only read 6 T-cycles after the trigger
+const TRIGGER_DELAY: u32 = 6;
+
+impl WaveChannel {
+    pub fn new() -> WaveChannel {
+        WaveChannel {
+            is_enabled: false,
+            is_dac_enabled: false,
+            length: LengthCounter::new(256),
+            volume_code: 0,
+            frequency: 0,
+            timer: period(0),
+            position: 0,
+            sample_buffer: 0,
+            ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    /// Digital output, from 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.is_enabled || self.volume_code == 0 {
+            return 0;
+        }
+
+        self.sample_buffer >> (self.volume_code - 1)
+    }
+
+    pub fn run(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = period(self.frequency);
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            self.sample_buffer = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+        self.timer -= cycles;
+    }
+
+    /// T-cycles until the output can change
+    pub const fn cycles_until_step(&self) -> u32 {
+        self.timer
+    }
+
+    /// NR32 - 0 mutes the channel, 1 to 3 shift the samples right by 0 to 2
+    pub const fn volume_code(&self) -> u8 {
+        self.volume_code
+    }
+
+    /// Sample of the wave RAM being played, from 0 to 31
+    pub const fn position(&self) -> u8 {
+        self.position
+    }
+
+    /// Frequency of the whole wave in Hz
+    pub fn frequency_hz(&self) -> f32 {
+        APU_CLOCK as f32 / (period(self.frequency) * 32) as f32
+    }
+
+    /// While playing, the wave RAM can only see the byte the channel is reading
+    pub fn read_ram(&self, index: usize) -> u8 {
+        if self.is_enabled {
+            self.ram[self.position as usize / 2]
+        } else {
+            self.ram[index]
+        }
+    }
+
+    pub fn write_ram(&mut self, index: usize, value: u8) {
+        if self.is_enabled {
+            self.ram[self.position as usize / 2] = value;
+        } else {
+            self.ram[index] = value;
+        }
+    }
+
+    pub fn write_dac(&mut self, value: u8) {
+        self.is_dac_enabled = value & 0x80 != 0;
+        if !self.is_dac_enabled {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value);
+    }
+
+    pub fn write_volume(&mut self, value: u8) {
+        self.volume_code = (value >> 5) & 0x03;
+    }
+
+    pub fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x0700) | value as u16;
+    }
+
+    pub fn write_control(&mut self, value: u8, is_length_step_next: bool) {
+        self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x07) << 8);
+        let trigger = value & 0x80 != 0;
+        if self
+            .length
+            .write_control(value & 0x40 != 0, trigger, is_length_step_next)
+        {
+            self.is_enabled = false;
+        }
+
+        if trigger {
+            self.is_enabled = self.is_dac_enabled;
+            self.position = 0;
+            self.timer = period(self.frequency) + TRIGGER_DELAY;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.is_enabled = false;
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        writer.write_bool(self.is_dac_enabled);
+        self.length.save_state(writer);
+        writer.write_u8(self.volume_code);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+        writer.write_u8(self.position);
+        writer.write_u8(self.sample_buffer);
+        writer.write_bytes(&self.ram);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.is_enabled = reader.read_bool()?;
+        self.is_dac_enabled = reader.read_bool()?;
+        self.length.load_state(reader)?;
+        self.volume_code = reader.read_u8()? & 0x03;
+        self.frequency = reader.read_u16()? & 0x07FF;
+        self.timer = reader
+            .read_u32()?
+            .clamp(1, period(self.frequency) + TRIGGER_DELAY);
+        self.position = reader.read_u8()? % 32;
+        self.sample_buffer = reader.read_u8()? & 0x0F;
+        reader.read_bytes(&mut self.ram)?;
+        Ok(())
+    }
+}
+
+impl Default for WaveChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// T-cycles between samples
+const fn period(frequency: u16) -> u32 {
+    (2048 - frequency as u32) * 2
+}
@@ -0,0 +1,242 @@
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::apu::CHANNEL_NAMES;
+
+/// Where a WAV file is written, the header is filled in when the recording stops
+pub trait WavOutput: Write + Seek {}
+
+impl<T: Write + Seek> WavOutput for T {}
+
+/// Writes 16-bit PCM WAV files
+pub struct WavWriter {
+    output: Box<dyn WavOutput>,
+    channels: u16,
+    sample_rate: u32,
+    /// Bytes of samples written so far
+    data_size: u32,
+}
+
+/// Records the audio of the emulator to WAV, with the option to also record each
+/// sound channel on its own
+pub struct AudioRecorder {
+    mix: WavWriter,
+    /// One file for each channel, in the order of `CHANNEL_NAMES`
+    stems: Option<[WavWriter; 4]>,
+    /// The first write that failed, reported when the recording stops
+    error: Option<io::Error>,
+}
+
+const WAV_HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+impl WavWriter {
+    pub fn new(
+        mut output: Box<dyn WavOutput>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> io::Result<WavWriter> {
+        output.write_all(&[0; WAV_HEADER_SIZE as usize])?;
+        let mut writer = WavWriter {
+            output,
+            channels,
+            sample_rate,
+            data_size: 0,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    /// Writes interleaved samples from -1 to 1
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|&sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect();
+        self.output.write_all(&bytes)?;
+        self.data_size = self.data_size.saturating_add(bytes.len() as u32);
+        Ok(())
+    }
+
+    /// Fills in the sizes in the header
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.output.flush()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = self.channels * BITS_PER_SAMPLE / 8;
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_SIZE - 8 + self.data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        // PCM
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_size.to_le_bytes());
+
+        let position = self.output.stream_position()?;
+        self.output.seek(SeekFrom::Start(0))?;
+        self.output.write_all(&header)?;
+        self.output.seek(SeekFrom::Start(position))?;
+        Ok(())
+    }
+}
+
+impl AudioRecorder {
+    /// Records the mixed stereo output, and each channel too if `stems` is given.
+    /// The stems are in the order of `CHANNEL_NAMES`.
+    pub fn new(
+        mix: Box<dyn WavOutput>,
+        stems: Option<[Box<dyn WavOutput>; 4]>,
+        sample_rate: u32,
+    ) -> io::Result<AudioRecorder> {
+        let mix = WavWriter::new(mix, 2, sample_rate)?;
+        let stems = match stems {
+            Some(stems) => {
+                let [square1, square2, wave, noise] = stems;
+                Some([
+                    WavWriter::new(square1, 2, sample_rate)?,
+                    WavWriter::new(square2, 2, sample_rate)?,
+                    WavWriter::new(wave, 2, sample_rate)?,
+                    WavWriter::new(noise, 2, sample_rate)?,
+                ])
+            }
+            None => None,
+        };
+
+        Ok(AudioRecorder {
+            mix,
+            stems,
+            error: None,
+        })
+    }
+
+    pub const fn sample_rate(&self) -> u32 {
+        self.mix.sample_rate
+    }
+
+    pub const fn has_stems(&self) -> bool {
+        self.stems.is_some()
+    }
+
+    /// Writes interleaved stereo samples of the mix and of each channel
+    pub fn write(&mut self, mix: &[f32], channels: &[Vec<f32>; 4]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let mut result = self.mix.write_samples(mix);
+        if let Some(stems) = &mut self.stems {
+            for (stem, samples) in stems.iter_mut().zip(channels) {
+                result = result.and_then(|_| stem.write_samples(samples));
+            }
+        }
+        self.error = result.err();
+    }
+
+    /// Ends the recording, returning the error of the first write that failed
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        self.mix.finish()?;
+        for stem in self.stems.into_iter().flatten() {
+            stem.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Path of the file of a stem, next to the file of the mix: `music.wav` gives
+/// `music_square1.wav`
+pub fn stem_path(mix_path: &Path, channel: usize) -> PathBuf {
+    let name = mix_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = mix_path.extension().unwrap_or(OsStr::new("wav"));
+    let mut file_name = OsString::from(format!("{name}_{}.", CHANNEL_NAMES[channel]));
+    file_name.push(extension);
+    mix_path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::{Apu, APU_CLOCK};
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    /// Output that can still be read once the recorder is done with it
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for SharedOutput {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.borrow_mut().seek(pos)
+        }
+    }
+
+    #[test]
+    fn writes_wav_files() {
+        let output = SharedOutput::default();
+        let mut recorder = AudioRecorder::new(Box::new(output.clone()), None, 48_000).unwrap();
+        recorder.write(&[0.0, 1.0, -1.0, 0.5], &Default::default());
+        recorder.finish().unwrap();
+
+        let bytes = output.0.borrow().get_ref().clone();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(
+            u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            48_000
+        );
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        assert_eq!(&bytes[44..], &[0, 0, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x3F]);
+    }
+
+    #[test]
+    fn keeps_its_sample_rate_when_the_played_one_changes() {
+        let output = SharedOutput::default();
+        let recorder = AudioRecorder::new(Box::new(output.clone()), None, 32_768).unwrap();
+        let mut apu = Apu::new();
+        apu.start_recording(recorder);
+        apu.run(APU_CLOCK / 16);
+        apu.set_sample_rate(48_240);
+        apu.run(APU_CLOCK / 16);
+        apu.stop_recording().unwrap().finish().unwrap();
+
+        let bytes = output.0.borrow().get_ref().clone();
+        let data_size = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+        assert_eq!(data_size, 32_768 / 8 * 2 * 2);
+    }
+
+    #[test]
+    fn stems_are_next_to_the_mix() {
+        assert_eq!(
+            stem_path(Path::new("music.wav"), 2),
+            Path::new("music_wave.wav")
+        );
+        assert_eq!(
+            stem_path(Path::new("songs.v2/music"), 0),
+            Path::new("songs.v2/music_square1.wav")
+        );
+    }
+}
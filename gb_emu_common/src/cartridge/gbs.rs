@@ -0,0 +1,97 @@
+use self::header::*;
+use crate::cartridge::*;
+use crate::error::{EmulationError, Result};
+use crate::reset::{RamPattern, ResetKind};
+use crate::save_state::{StateReader, StateWriter};
+
+const ROM_BANK_SELECT_START: usize = 0x2000;
+const ROM_BANK_SELECT_END: usize = 0x3FFF;
+
+/// Cartridge that plays a GBS file. The code switches the ROM bank at 0x4000 by
+/// writing to 0x2000-0x3FFF and always has 8 KiB of RAM.
+pub struct GbsCartridge {
+    rom: Vec<u8>,
+    ram: RamBank,
+    rom_bank: usize,
+    header: Header,
+}
+
+impl GbsCartridge {
+    /// Takes a ROM built by `Gbs::rom`
+    pub fn new(rom: Vec<u8>) -> Result<GbsCartridge> {
+        let header = Header::read_rom_header(&rom)?;
+        if rom.len() != header.rom_bank_amount * ROM_BANK_SIZE {
+            return Err(EmulationError::InvalidRom);
+        }
+
+        Ok(GbsCartridge {
+            rom,
+            ram: [0; RAM_BANK_SIZE],
+            rom_bank: 1,
+            header,
+        })
+    }
+}
+
+impl Cartridge for GbsCartridge {
+    fn read_byte_rom(&self, address: usize) -> Result<u8> {
+        match address {
+            ROM_BANK_0_START..=ROM_BANK_0_END => Ok(self.rom[address]),
+            ROM_BANK_N_START..=ROM_BANK_N_END => {
+                let offset = self.rom_bank * ROM_BANK_SIZE + address - ROM_BANK_N_START;
+                Ok(self.rom[offset])
+            }
+
+            _ => Err(EmulationError::InvalidMemoryRead { address }),
+        }
+    }
+
+    fn write_byte_rom(&mut self, address: usize, value: u8) -> Result<()> {
+        if let ROM_BANK_SELECT_START..=ROM_BANK_SELECT_END = address {
+            // bank 0 can't be selected, like on MBC1
+            let bank = (value as usize).max(1);
+            self.rom_bank = bank % self.header.rom_bank_amount;
+        }
+
+        Ok(())
+    }
+
+    fn read_byte_external_ram(&self, address: usize) -> Result<u8> {
+        Ok(self.ram[address - EXTERNAL_RAM_START])
+    }
+
+    fn write_byte_external_ram(&mut self, address: usize, value: u8) -> Result<()> {
+        self.ram[address - EXTERNAL_RAM_START] = value;
+        Ok(())
+    }
+
+    fn get_header(&self) -> Header {
+        self.header.clone()
+    }
+
+    fn get_ram_banks(&self) -> Vec<RamBank> {
+        vec![self.ram]
+    }
+
+    fn has_battery(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self, kind: ResetKind, pattern: RamPattern) {
+        self.rom_bank = 1;
+        if kind == ResetKind::PowerCycle {
+            pattern.fill(&mut self.ram, EXTERNAL_RAM_START);
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.rom_bank as u16);
+        writer.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.rom_bank = reader.read_u16()? as usize % self.header.rom_bank_amount;
+        reader.read_bytes(&mut self.ram)?;
+        Ok(())
+    }
+}
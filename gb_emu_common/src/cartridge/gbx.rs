@@ -0,0 +1,228 @@
+use crate::cartridge::cartridge_type::CartridgeType;
+use crate::cartridge::{RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::error::{EmulationError, Result};
+
+/// Footer appended to the end of GBX files. It describes the real cartridge hardware,
+/// so it takes precedence over whatever the ROM header says.
+///
+/// All multi-byte values in the footer are stored in big-endian.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GbxFooter {
+    pub mapper: GbxMapper,
+    pub has_battery: bool,
+    pub has_rumble: bool,
+    pub has_timer: bool,
+    /// ROM size in bytes
+    pub rom_size: usize,
+    /// RAM size in bytes
+    pub ram_size: usize,
+    /// Mapper specific variables, their meaning depend on the mapper
+    pub mapper_variables: [u8; 32],
+    /// Size of the footer in bytes, it has to be removed from the end of the ROM
+    pub footer_size: usize,
+    pub major_version: u32,
+    pub minor_version: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GbxMapper {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    Mmm01,
+    PocketCamera,
+    BandaiTama5,
+    HuC1,
+    HuC3,
+}
+
+impl GbxFooter {
+    /// Reads the GBX footer at the end of `rom`, returns `None` if this isn't a GBX file.
+    pub fn read(rom: &[u8]) -> Result<Option<GbxFooter>> {
+        if rom.len() < GBX_FOOTER_SIZE {
+            return Ok(None);
+        }
+
+        let footer = &rom[rom.len() - GBX_FOOTER_SIZE..];
+        if &footer[0x3C..0x40] != GBX_SIGNATURE {
+            return Ok(None);
+        }
+
+        let footer_size = read_u32_be(footer, 0x30) as usize;
+        let major_version = read_u32_be(footer, 0x34);
+        let minor_version = read_u32_be(footer, 0x38);
+        if major_version != 1 || footer_size != GBX_FOOTER_SIZE {
+            return Err(EmulationError::UnsupportedGbxVersion {
+                major: major_version,
+                minor: minor_version,
+            });
+        }
+
+        let mapper = GbxMapper::from_identifier(&footer[0x00..0x04])?;
+        let rom_size = read_u32_be(footer, 0x08) as usize;
+        let ram_size = read_u32_be(footer, 0x0C) as usize;
+        if rom_size > rom.len() - footer_size {
+            // the footer claims a ROM bigger than the file
+            return Err(EmulationError::InvalidRom);
+        }
+
+        let mut mapper_variables = [0; 32];
+        mapper_variables.copy_from_slice(&footer[0x10..0x30]);
+
+        Ok(Some(GbxFooter {
+            mapper,
+            has_battery: footer[0x04] == 1,
+            has_rumble: footer[0x05] == 1,
+            has_timer: footer[0x06] == 1,
+            rom_size,
+            ram_size,
+            mapper_variables,
+            footer_size,
+            major_version,
+            minor_version,
+        }))
+    }
+
+    /// The cartridge type described by this footer
+    pub fn cartridge_type(&self) -> CartridgeType {
+        let has_ram = self.ram_size > 0;
+        let battery = self.has_battery;
+        let rumble = self.has_rumble;
+        let timer = self.has_timer;
+
+        match self.mapper {
+            GbxMapper::RomOnly if has_ram && battery => CartridgeType::RomRamBattery,
+            GbxMapper::RomOnly if has_ram => CartridgeType::RomRam,
+            GbxMapper::RomOnly => CartridgeType::RomOnly,
+
+            GbxMapper::Mbc1 if has_ram && battery => CartridgeType::Mbc1RamBattery,
+            GbxMapper::Mbc1 if has_ram => CartridgeType::Mbc1Ram,
+            GbxMapper::Mbc1 => CartridgeType::Mbc1,
+
+            // MBC2 has built-in RAM, so only the battery matters
+            GbxMapper::Mbc2 if battery => CartridgeType::Mbc2Battery,
+            GbxMapper::Mbc2 => CartridgeType::Mbc2,
+
+            GbxMapper::Mbc3 if timer && has_ram => CartridgeType::Mbc3TimerRamBattery,
+            GbxMapper::Mbc3 if timer => CartridgeType::Mbc3TimerBattery,
+            GbxMapper::Mbc3 if has_ram && battery => CartridgeType::Mbc3RamBattery,
+            GbxMapper::Mbc3 if has_ram => CartridgeType::Mbc3Ram,
+            GbxMapper::Mbc3 => CartridgeType::Mbc3,
+
+            GbxMapper::Mbc5 if rumble && has_ram && battery => CartridgeType::Mbc5RumbleRamBattery,
+            GbxMapper::Mbc5 if rumble && has_ram => CartridgeType::Mbc5RumbleRam,
+            GbxMapper::Mbc5 if rumble => CartridgeType::Mbc5Rumble,
+            GbxMapper::Mbc5 if has_ram && battery => CartridgeType::Mbc5RamBattery,
+            GbxMapper::Mbc5 if has_ram => CartridgeType::Mbc5Ram,
+            GbxMapper::Mbc5 => CartridgeType::Mbc5,
+
+            GbxMapper::Mbc6 => CartridgeType::Mbc6,
+            GbxMapper::Mbc7 => CartridgeType::Mbc7SensorRumbleRamBattery,
+
+            GbxMapper::Mmm01 if has_ram && battery => CartridgeType::Mmm01RamBattery,
+            GbxMapper::Mmm01 if has_ram => CartridgeType::Mmm01Ram,
+            GbxMapper::Mmm01 => CartridgeType::Mmm01,
+
+            GbxMapper::PocketCamera => CartridgeType::PocketCamera,
+            GbxMapper::BandaiTama5 => CartridgeType::BandaiTama5,
+            GbxMapper::HuC1 => CartridgeType::HuC1RamBattery,
+            GbxMapper::HuC3 => CartridgeType::HuC3,
+        }
+    }
+
+    pub const fn rom_bank_amount(&self) -> usize {
+        self.rom_size / ROM_BANK_SIZE
+    }
+
+    pub const fn ram_bank_amount(&self) -> usize {
+        // Round up, some mappers (like MBC2) have less than a whole bank of RAM
+        self.ram_size.div_ceil(RAM_BANK_SIZE)
+    }
+}
+
+impl GbxMapper {
+    fn from_identifier(identifier: &[u8]) -> Result<GbxMapper> {
+        // identifiers shorter than 4 characters are padded with zeroes
+        let mapper = match identifier {
+            b"ROM\0" => GbxMapper::RomOnly,
+            b"MBC1" => GbxMapper::Mbc1,
+            b"MBC2" => GbxMapper::Mbc2,
+            b"MBC3" => GbxMapper::Mbc3,
+            b"MBC5" => GbxMapper::Mbc5,
+            b"MBC6" => GbxMapper::Mbc6,
+            b"MBC7" => GbxMapper::Mbc7,
+            b"MMM1" => GbxMapper::Mmm01,
+            b"CAMR" => GbxMapper::PocketCamera,
+            b"TAM5" => GbxMapper::BandaiTama5,
+            b"HUC1" => GbxMapper::HuC1,
+            b"HUC3" => GbxMapper::HuC3,
+            _ => {
+                let mapper = String::from_utf8_lossy(identifier)
+                    .trim_end_matches('\0')
+                    .to_string();
+                return Err(EmulationError::UnknownGbxMapper { mapper });
+            }
+        };
+
+        Ok(mapper)
+    }
+}
+
+fn read_u32_be(buffer: &[u8], pos: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[pos..pos + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+pub const GBX_FOOTER_SIZE: usize = 0x40;
+pub const GBX_SIGNATURE: &[u8] = b"GBX!";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::header::Header;
+
+    fn gbx_rom(mapper: &[u8; 4], cartridge_type_code: u8) -> Vec<u8> {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        rom[0x0147] = cartridge_type_code;
+
+        let mut footer = vec![0; GBX_FOOTER_SIZE];
+        footer[0x00..0x04].copy_from_slice(mapper);
+        footer[0x04] = 1; // battery
+        footer[0x08..0x0C].copy_from_slice(&(rom.len() as u32).to_be_bytes());
+        footer[0x0C..0x10].copy_from_slice(&(RAM_BANK_SIZE as u32).to_be_bytes());
+        footer[0x30..0x34].copy_from_slice(&(GBX_FOOTER_SIZE as u32).to_be_bytes());
+        footer[0x34..0x38].copy_from_slice(&1u32.to_be_bytes());
+        footer[0x3C..0x40].copy_from_slice(GBX_SIGNATURE);
+
+        rom.extend(footer);
+        rom
+    }
+
+    #[test]
+    fn footer_overrides_header() {
+        // 0x42 isn't a valid cartridge type, the footer must be used instead
+        let rom = gbx_rom(b"ROM\0", 0x42);
+        let header = Header::read_rom_header(&rom).unwrap();
+
+        assert_eq!(header.cartridge_type, CartridgeType::RomRamBattery);
+        assert_eq!(header.rom_bank_amount, 2);
+        assert_eq!(header.ram_bank_amount, 1);
+        assert!(crate::cartridge::create_cartridge(rom).is_ok());
+    }
+
+    #[test]
+    fn unknown_mapper() {
+        let rom = gbx_rom(b"ABCD", 0x00);
+        let result = GbxFooter::read(&rom);
+
+        assert!(matches!(
+            result,
+            Err(EmulationError::UnknownGbxMapper { .. })
+        ));
+    }
+}
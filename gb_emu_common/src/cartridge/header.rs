@@ -1,6 +1,7 @@
 use std::convert::TryFrom;
 
 use crate::cartridge::cartridge_type::*;
+use crate::cartridge::gbx::GbxFooter;
 use crate::error::{EmulationError, Result};
 
 #[derive(Clone)]
@@ -9,6 +10,21 @@ pub struct Header {
     pub cartridge_type: CartridgeType,
     pub rom_bank_amount: usize,
     pub ram_bank_amount: usize,
+    pub gbx_footer: Option<GbxFooter>,
+    /// 0143 - CGB Flag
+    pub cgb_flag: u8,
+    /// 0144-0145 - New Licensee Code
+    pub new_licensee_code: [u8; 2],
+    /// 0146 - SGB Flag
+    pub sgb_flag: u8,
+    /// 014B - Old Licensee Code
+    pub old_licensee_code: u8,
+    /// 014D - Header Checksum
+    pub header_checksum: u8,
+    /// Sum of the 16 title bytes, used by the CGB boot ROM to pick a palette for DMG games
+    pub title_checksum: u8,
+    /// 0137 - 4th letter of the title, tells apart games with the same title checksum
+    pub title_fourth_letter: u8,
 }
 
 impl Header {
@@ -28,24 +44,62 @@ impl Header {
             &rom[0x0134..=0x0143]
         };
         let title = decode_rom_title(title_buffer);
-        let cartridge_type = decode_cartridge_type(rom[0x0147])?;
-        let rom_bank_amount = get_amount_of_rom_banks(rom[0x0148])?;
-        let ram_bank_amount = get_amount_of_ram_banks(rom[0x0149])?;
+
+        // GBX files describe the real cartridge hardware on a footer, which takes
+        // precedence over the header since some headers lie about it
+        let gbx_footer = GbxFooter::read(rom)?;
+        let (cartridge_type, rom_bank_amount, ram_bank_amount) = match &gbx_footer {
+            Some(footer) => (
+                footer.cartridge_type(),
+                footer.rom_bank_amount(),
+                footer.ram_bank_amount(),
+            ),
+
+            None => (
+                decode_cartridge_type(rom[0x0147])?,
+                get_amount_of_rom_banks(rom[0x0148])?,
+                get_amount_of_ram_banks(rom[0x0149])?,
+            ),
+        };
 
         Ok(Header {
             title,
             cartridge_type,
             rom_bank_amount,
             ram_bank_amount,
+            gbx_footer,
+            cgb_flag,
+            new_licensee_code: [rom[0x0144], rom[0x0145]],
+            sgb_flag: rom[0x0146],
+            old_licensee_code: rom[0x014B],
+            header_checksum: rom[0x014D],
+            title_checksum: rom[0x0134..=0x0143]
+                .iter()
+                .fold(0, |sum: u8, &byte| sum.wrapping_add(byte)),
+            title_fourth_letter: rom[0x0137],
         })
     }
+
+    /// The game can use the CGB features
+    pub const fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    /// The game can use the SGB functions. The SGB ignores the flag unless the old
+    /// licensee code says to look at the new one.
+    pub const fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03 && self.old_licensee_code == 0x33
+    }
+
+    /// Whether the game was published by Nintendo
+    pub fn is_nintendo_licensee(&self) -> bool {
+        self.old_licensee_code == 0x01
+            || (self.old_licensee_code == 0x33 && &self.new_licensee_code == b"01")
+    }
 }
 
 fn decode_rom_title(title_buffer: &[u8]) -> Option<String> {
-    match String::from_utf8(title_buffer.to_vec()) {
-        Ok(title) => Some(title),
-        Err(_) => None,
-    }
+    String::from_utf8(title_buffer.to_vec()).ok()
 }
 
 fn decode_cartridge_type(code: u8) -> Result<CartridgeType> {
@@ -1,4 +1,6 @@
 pub mod cartridge_type;
+pub mod gbs;
+pub mod gbx;
 pub mod header;
 pub mod rom_only;
 
@@ -6,6 +8,8 @@ use self::cartridge_type::*;
 use self::header::Header;
 use self::rom_only::RomOnlyCartridge;
 use crate::error::{EmulationError, Result};
+use crate::reset::{RamPattern, ResetKind};
+use crate::save_state::{StateReader, StateWriter};
 
 // each RAM bank has KiB of RAM
 type RamBank = [u8; RAM_BANK_SIZE];
@@ -20,10 +24,27 @@ pub trait Cartridge {
     fn get_header(&self) -> Header;
     fn get_ram_banks(&self) -> Vec<RamBank>;
     fn has_battery(&self) -> bool;
+
+    /// Puts the banking registers back to their initial values. A power cycle also
+    /// fills RAM without a battery with the given pattern.
+    fn reset(&mut self, kind: ResetKind, pattern: RamPattern);
+
+    /// Sets the real time clock of the cartridges that have one to a Unix time, so
+    /// movies get the same clock on every playback
+    fn set_rtc_time(&mut self, _seconds: u64) {}
+
+    /// Serializes the banking registers and RAM contents
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()>;
 }
 
-pub fn create_cartridge(rom: Vec<u8>) -> Result<impl Cartridge> {
+pub fn create_cartridge(mut rom: Vec<u8>) -> Result<impl Cartridge> {
     let header = Header::read_rom_header(&rom)?;
+    if let Some(footer) = &header.gbx_footer {
+        // remove the footer, the cartridges only care about the ROM data
+        rom.truncate(footer.rom_size);
+    }
+
     match header.cartridge_type {
         CartridgeType::RomOnly | CartridgeType::RomRam | CartridgeType::RomRamBattery => {
             RomOnlyCartridge::new(rom, header)
@@ -1,6 +1,8 @@
 use self::header::*;
 use crate::cartridge::*;
 use crate::error::{EmulationError, Result};
+use crate::reset::{RamPattern, ResetKind};
+use crate::save_state::{StateReader, StateWriter};
 
 pub struct RomOnlyCartridge {
     rom: Vec<u8>,
@@ -78,4 +80,28 @@ impl Cartridge for RomOnlyCartridge {
     fn has_battery(&self) -> bool {
         self.header.cartridge_type == CartridgeType::RomRamBattery
     }
+
+    fn reset(&mut self, kind: ResetKind, pattern: RamPattern) {
+        // there are no banking registers, only the RAM can change
+        if kind == ResetKind::PowerCycle && !self.has_battery() {
+            if let Some(ram) = &mut self.ram {
+                pattern.fill(ram, EXTERNAL_RAM_START);
+            }
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        // there's no banking, so the RAM is all we need
+        if let Some(ram) = &self.ram {
+            writer.write_bytes(ram);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        if let Some(ram) = &mut self.ram {
+            reader.read_bytes(ram)?;
+        }
+
+        Ok(())
+    }
 }
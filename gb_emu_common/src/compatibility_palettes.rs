@@ -0,0 +1,287 @@
+//! Palettes the CGB boot ROM gives to DMG games. Games published by Nintendo are
+//! recognized by the checksum of their title, and the player can pick one of 12 palettes
+//! by holding a button combination while the logo is shown.
+
+use crate::cartridge::header::Header;
+use crate::joypad::Buttons;
+use crate::palette::Rgb555;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompatibilityPalette {
+    pub bg: [Rgb555; 4],
+    pub obj0: [Rgb555; 4],
+    pub obj1: [Rgb555; 4],
+}
+
+/// Combination used when the game isn't recognized
+const DEFAULT_COMBINATION: usize = 0;
+
+/// Button combinations that can be held during the boot animation, with their names
+pub const BUTTON_COMBINATIONS: [(&str, Buttons, usize); 12] = [
+    ("Up", buttons(false, false, true, false, false, false), 5),
+    (
+        "Up + A",
+        buttons(false, false, true, false, true, false),
+        43,
+    ),
+    (
+        "Up + B",
+        buttons(false, false, true, false, false, true),
+        28,
+    ),
+    ("Left", buttons(false, true, false, false, false, false), 48),
+    (
+        "Left + A",
+        buttons(false, true, false, false, true, false),
+        40,
+    ),
+    (
+        "Left + B",
+        buttons(false, true, false, false, false, true),
+        7,
+    ),
+    ("Down", buttons(false, false, false, true, false, false), 8),
+    (
+        "Down + A",
+        buttons(false, false, false, true, true, false),
+        3,
+    ),
+    (
+        "Down + B",
+        buttons(false, false, false, true, false, true),
+        49,
+    ),
+    ("Right", buttons(true, false, false, false, false, false), 1),
+    (
+        "Right + A",
+        buttons(true, false, false, false, true, false),
+        0,
+    ),
+    (
+        "Right + B",
+        buttons(true, false, false, false, false, true),
+        6,
+    ),
+];
+
+impl CompatibilityPalette {
+    /// The palette the boot ROM picks for a game when no buttons are held
+    pub fn for_header(header: &Header) -> CompatibilityPalette {
+        CompatibilityPalette::from_combination(combination_for_header(header))
+    }
+
+    /// The palette selected by holding the given buttons, if they're a valid combination
+    pub fn for_buttons(buttons: Buttons) -> Option<CompatibilityPalette> {
+        BUTTON_COMBINATIONS
+            .iter()
+            .find(|(_, combination, _)| *combination == buttons)
+            .map(|(_, _, index)| CompatibilityPalette::from_combination(*index))
+    }
+
+    /// One of the palettes selectable with the buttons, in the order of `BUTTON_COMBINATIONS`
+    pub fn from_button_combination(index: usize) -> Option<CompatibilityPalette> {
+        BUTTON_COMBINATIONS
+            .get(index)
+            .map(|(_, _, combination)| CompatibilityPalette::from_combination(*combination))
+    }
+
+    fn from_combination(index: usize) -> CompatibilityPalette {
+        let (obj0, obj1, bg) = COMBINATIONS[index];
+        CompatibilityPalette {
+            bg: read_colors(bg),
+            obj0: read_colors(obj0),
+            obj1: read_colors(obj1),
+        }
+    }
+}
+
+impl Default for CompatibilityPalette {
+    fn default() -> CompatibilityPalette {
+        CompatibilityPalette::from_combination(DEFAULT_COMBINATION)
+    }
+}
+
+const fn buttons(right: bool, left: bool, up: bool, down: bool, a: bool, b: bool) -> Buttons {
+    Buttons {
+        right,
+        left,
+        up,
+        down,
+        a,
+        b,
+        select: false,
+        start: false,
+    }
+}
+
+fn combination_for_header(header: &Header) -> usize {
+    // only games published by Nintendo are in the table
+    if !header.is_nintendo_licensee() {
+        return DEFAULT_COMBINATION;
+    }
+
+    let checksum = header.title_checksum;
+    let fourth_letter = header.title_fourth_letter;
+    let position = TITLE_CHECKSUMS.iter().enumerate().position(|(i, &entry)| {
+        if entry != checksum {
+            return false;
+        }
+
+        // some checksums are shared by several games, the 4th letter of the title
+        // tells them apart
+        match i.checked_sub(FIRST_DUPLICATED_CHECKSUM) {
+            Some(duplicate) => DUPLICATES_FOURTH_LETTER[duplicate] == fourth_letter,
+            None => true,
+        }
+    });
+
+    position
+        .map(|position| COMBINATION_PER_CHECKSUM[position])
+        .unwrap_or(DEFAULT_COMBINATION)
+}
+
+/// Reads 4 consecutive colors. Some combinations start in the middle of a palette.
+fn read_colors(color_offset: usize) -> [Rgb555; 4] {
+    let mut colors = [0; 4];
+    for (i, color) in colors.iter_mut().enumerate() {
+        let offset = color_offset + i;
+        *color = PALETTES[offset / 4][offset % 4];
+    }
+    colors
+}
+
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, // the next checksums are shared by several games
+    0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3, 0x46,
+    0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+const FIRST_DUPLICATED_CHECKSUM: usize = 65;
+const DUPLICATES_FOURTH_LETTER: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// Index in `COMBINATIONS` for each entry of `TITLE_CHECKSUMS`
+const COMBINATION_PER_CHECKSUM: [usize; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+/// Offsets in colors of the OBJ0, OBJ1 and BG palettes
+const COMBINATIONS: [(usize, usize, usize); 51] = [
+    combination(4, 4, 29),
+    combination(18, 18, 18),
+    combination(20, 20, 20),
+    combination(24, 24, 24),
+    combination(9, 9, 9),
+    combination(0, 0, 0),
+    combination(27, 27, 27),
+    combination(5, 5, 5),
+    combination(12, 12, 12),
+    combination(26, 26, 26),
+    combination(16, 8, 8),
+    combination(4, 28, 28),
+    combination(4, 2, 2),
+    combination(3, 4, 4),
+    combination(4, 29, 29),
+    combination(28, 4, 28),
+    combination(2, 17, 2),
+    combination(16, 16, 8),
+    combination(4, 4, 7),
+    combination(4, 4, 18),
+    combination(4, 4, 20),
+    combination(19, 19, 9),
+    (4 * 4 - 1, 4 * 4 - 1, 11 * 4),
+    combination(17, 17, 2),
+    combination(4, 4, 2),
+    combination(4, 4, 3),
+    combination(28, 28, 0),
+    combination(3, 3, 0),
+    combination(0, 0, 1),
+    combination(18, 22, 18),
+    combination(20, 22, 20),
+    combination(24, 22, 24),
+    combination(16, 22, 8),
+    combination(17, 4, 13),
+    (28 * 4 - 1, 0, 14 * 4),
+    (28 * 4 - 1, 4 * 4, 15 * 4),
+    combination(19, 22, 9),
+    combination(16, 28, 10),
+    combination(4, 23, 28),
+    combination(17, 22, 2),
+    combination(4, 0, 2),
+    combination(4, 28, 3),
+    combination(28, 3, 0),
+    combination(3, 28, 4),
+    combination(21, 28, 4),
+    combination(3, 28, 0),
+    combination(25, 3, 28),
+    combination(0, 28, 8),
+    combination(4, 3, 28),
+    combination(28, 3, 6),
+    combination(4, 28, 29),
+];
+
+const fn combination(obj0: usize, obj1: usize, bg: usize) -> (usize, usize, usize) {
+    (obj0 * 4, obj1 * 4, bg * 4)
+}
+
+const PALETTES: [[Rgb555; 4]; 30] = [
+    [0x7FFF, 0x32BF, 0x00D0, 0x0000],
+    [0x639F, 0x4279, 0x15B0, 0x04CB],
+    [0x7FFF, 0x6E31, 0x454A, 0x0000],
+    [0x7FFF, 0x1BEF, 0x0200, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    [0x7FFF, 0x5294, 0x294A, 0x0000],
+    [0x7FFF, 0x03FF, 0x012F, 0x0000],
+    [0x7FFF, 0x03EF, 0x01D6, 0x0000],
+    [0x7FFF, 0x42B5, 0x3DC8, 0x0000],
+    [0x7E74, 0x03FF, 0x0180, 0x0000],
+    [0x67FF, 0x77AC, 0x1A13, 0x2D6B],
+    [0x7ED6, 0x4BFF, 0x2175, 0x0000],
+    [0x53FF, 0x4A5F, 0x7E52, 0x0000],
+    [0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0],
+    [0x03ED, 0x7FFF, 0x255F, 0x0000],
+    [0x036A, 0x021F, 0x03FF, 0x7FFF],
+    [0x7FFF, 0x01DF, 0x0112, 0x0000],
+    [0x231F, 0x035F, 0x00F2, 0x0009],
+    [0x7FFF, 0x03EA, 0x011F, 0x0000],
+    [0x299F, 0x001A, 0x000C, 0x0000],
+    [0x7FFF, 0x027F, 0x001F, 0x0000],
+    [0x7FFF, 0x03E0, 0x0206, 0x0120],
+    [0x7FFF, 0x7EEB, 0x001F, 0x7C00],
+    [0x7FFF, 0x3FFF, 0x7E00, 0x001F],
+    [0x7FFF, 0x03FF, 0x001F, 0x0000],
+    [0x03FF, 0x001F, 0x000C, 0x0000],
+    [0x7FFF, 0x033F, 0x0193, 0x0000],
+    [0x0000, 0x4200, 0x037F, 0x7FFF],
+    [0x7FFF, 0x7E8C, 0x7C00, 0x0000],
+    [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::ROM_BANK_SIZE;
+
+    fn header(title: &[u8], old_licensee_code: u8) -> Header {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x014B] = old_licensee_code;
+        Header::read_rom_header(&rom).unwrap()
+    }
+
+    #[test]
+    fn recognizes_nintendo_games() {
+        let palette = CompatibilityPalette::for_header(&header(b"TETRIS", 0x01));
+        assert_eq!(palette, CompatibilityPalette::from_combination(3));
+
+        // other publishers always get the default palette
+        let palette = CompatibilityPalette::for_header(&header(b"TETRIS", 0x02));
+        assert_eq!(palette.bg, [0x7FFF, 0x1BEF, 0x6180, 0x0000]);
+    }
+}
@@ -1,7 +1,10 @@
 use crate::cpu_registers::*;
 use crate::error::{EmulationError, Result};
 use crate::instruction::*;
+use crate::interrupt::Interrupt;
 use crate::memory_bus::*;
+use crate::reset::{RamPattern, ResetKind};
+use crate::save_state::{StateReader, StateWriter};
 
 pub struct Cpu {
     pub bus: MemoryBus,
@@ -30,9 +33,63 @@ impl Cpu {
         }
     }
 
+    pub fn reset(&mut self, kind: ResetKind, pattern: RamPattern) {
+        self.bus.reset(kind, pattern);
+        self.pc = 0x0100;
+        self.registers = Registers::new();
+        self.sp = 0;
+        self.is_halted = false;
+        self.ime = false;
+        self.set_ime = false;
+        self.is_stopped = false;
+    }
+
+    /// Sets the registers to the values the boot ROM of the model leaves behind
+    pub fn apply_post_boot_state(&mut self) {
+        if let Some(cartridge) = &self.bus.cartridge {
+            self.registers = self.bus.model.post_boot_registers(&cartridge.get_header());
+        }
+
+        self.pc = 0x0100;
+        self.sp = 0xFFFE;
+        self.bus.apply_post_boot_state();
+    }
+
+    /// Starts executing the boot ROM from the beginning, it initializes the registers itself
+    pub fn start_boot_rom(&mut self) {
+        self.bus.map_boot_rom();
+        self.pc = 0x0000;
+        // the boot ROM turns the LCD on after setting up the logo
+        self.bus.gpu.lcdc = 0;
+    }
+
     pub fn step(&mut self) -> Result<u32> {
+        // the CPU doesn't run during VRAM DMA transfers
+        let stall_cycles = self.bus.take_stall_cycles();
+        if stall_cycles > 0 {
+            return Ok(stall_cycles);
+        }
+
+        if self.is_stopped {
+            // the CPU only leaves STOP mode when a button is pressed
+            if u8::from(self.bus.joypad.buttons) == 0 {
+                return Ok(4);
+            }
+
+            self.is_stopped = false;
+        }
+
+        if let Some(interrupt) = self.bus.pending_interrupt() {
+            // a pending interrupt wakes the CPU up even if IME is disabled
+            self.is_halted = false;
+
+            if self.ime {
+                return self.handle_interrupt(interrupt);
+            }
+        }
+
         if self.is_halted {
-            return Ok(1);
+            return Ok(4);
         }
 
         let mut instruction_byte = self.bus.read_byte(self.pc)?;
@@ -59,6 +116,54 @@ impl Cpu {
         }
     }
 
+    fn handle_interrupt(&mut self, interrupt: Interrupt) -> Result<u32> {
+        self.ime = false;
+        self.set_ime = false;
+        self.bus.acknowledge_interrupt(interrupt);
+
+        // call the interrupt handler
+        self.push(self.pc)?;
+        self.pc = interrupt.vector();
+
+        Ok(20)
+    }
+
+    pub const fn is_halted(&self) -> bool {
+        self.is_halted
+    }
+
+    pub const fn is_stopped(&self) -> bool {
+        self.is_stopped
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.registers.get_af());
+        writer.write_u16(self.registers.get_bc());
+        writer.write_u16(self.registers.get_de());
+        writer.write_u16(self.registers.get_hl());
+        writer.write_u16(self.pc);
+        writer.write_u16(self.sp);
+        writer.write_bool(self.is_halted);
+        writer.write_bool(self.ime);
+        writer.write_bool(self.set_ime);
+        writer.write_bool(self.is_stopped);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.registers.set_af(reader.read_u16()?);
+        self.registers.set_bc(reader.read_u16()?);
+        self.registers.set_de(reader.read_u16()?);
+        self.registers.set_hl(reader.read_u16()?);
+        self.pc = reader.read_u16()?;
+        self.sp = reader.read_u16()?;
+        self.is_halted = reader.read_bool()?;
+        self.ime = reader.read_bool()?;
+        self.set_ime = reader.read_bool()?;
+        self.is_stopped = reader.read_bool()?;
+
+        Ok(())
+    }
+
     fn execute(&mut self, instruction: &Instruction) -> Result<(u16, u32)> {
         if self.set_ime {
             self.ime = true;
@@ -118,7 +223,7 @@ impl Cpu {
                 // Set flags
                 self.registers.f.subtract = false;
                 self.registers.f.carry = did_overflow;
-                self.registers.f.half_carry = (target_value & 0xFFF).wrapping_add(source_value & 0xFFFF) > 0xFFF;
+                self.registers.f.half_carry = (target_value & 0xFFF).wrapping_add(source_value & 0xFFF) > 0xFFF;
 
                 self.set_word_target_value(target, new_value)?;
 
@@ -209,12 +314,10 @@ impl Cpu {
                         new_value = new_value.wrapping_add(0x60);
                         carry = true;
                     }
+                } else if self.registers.f.half_carry {
+                    new_value = a.wrapping_sub(0x06);
                 } else {
-                    if self.registers.f.half_carry {
-                        new_value = (a.wrapping_sub(0x06)) & 0xFF;
-                    } else {
-                        new_value = new_value.wrapping_sub(0x60);
-                    }
+                    new_value = new_value.wrapping_sub(0x60);
                 }
                 new_value &= 0xFF;
                 self.registers.a = new_value;
@@ -483,14 +586,20 @@ impl Cpu {
             }
 
             Instruction::Stop(data) => {
-                self.is_stopped = true;
-
                 // The DIV - Divider Register is reset when executing the stop instruction,
                 // and only begins ticking again once stop mode ends
                 self.bus.reset_divider_register();
 
                 let next_pc = self.pc.wrapping_add(data.bytes);
-                (next_pc, data.cycles)
+                if self.bus.is_speed_switch_prepared() {
+                    // on CGB, STOP is used to switch the speed instead, which
+                    // pauses the CPU for a while
+                    self.bus.switch_speed();
+                    (next_pc, SPEED_SWITCH_CYCLES)
+                } else {
+                    self.is_stopped = true;
+                    (next_pc, data.cycles)
+                }
             }
 
             Instruction::Sub(source, data) => {
@@ -981,3 +1090,37 @@ impl Cpu {
         Ok(next_word)
     }
 }
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// T-cycles the CPU is paused while switching speeds
+const SPEED_SWITCH_CYCLES: u32 = 8_200;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_hl_half_carry_comes_from_bit_11() {
+        let mut cpu = Cpu::new();
+        let add_hl_bc = Instruction::from_byte(0x09, false).unwrap();
+
+        // the upper nibble of BC doesn't take part in the half carry
+        cpu.registers.set_hl(0x0001);
+        cpu.registers.set_bc(0xF000);
+        cpu.execute(&add_hl_bc).unwrap();
+        assert_eq!(cpu.registers.get_hl(), 0xF001);
+        assert!(!cpu.registers.f.half_carry);
+
+        cpu.registers.set_hl(0x0FFF);
+        cpu.registers.set_bc(0x0001);
+        cpu.execute(&add_hl_bc).unwrap();
+        assert_eq!(cpu.registers.get_hl(), 0x1000);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+    }
+}
@@ -77,6 +77,12 @@ impl Registers {
     }
 }
 
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FlagsRegister {
     pub fn new() -> FlagsRegister {
         FlagsRegister {
@@ -88,6 +94,12 @@ impl FlagsRegister {
     }
 }
 
+impl Default for FlagsRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl convert::From<FlagsRegister> for u8 {
     fn from(flag: FlagsRegister) -> u8 {
         (if flag.zero { 1 } else { 0 }) << ZERO_FLAG_BYTE_POSITION
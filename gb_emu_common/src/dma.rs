@@ -0,0 +1,234 @@
+use crate::error::Result;
+use crate::save_state::{StateReader, StateWriter};
+
+/// Bytes copied by each HBlank of an HBlank DMA
+pub const HDMA_BLOCK_SIZE: u16 = 16;
+
+/// FF51-FF55 - CGB VRAM DMA. Copies blocks of 16 bytes from ROM or RAM to VRAM, either all
+/// at once (general purpose DMA) or one block at the start of each HBlank (HBlank DMA).
+pub struct Hdma {
+    /// FF51/FF52 - HDMA1/HDMA2 - Source address
+    pub source: u16,
+    /// FF53/FF54 - HDMA3/HDMA4 - Destination address, relative to the beginning of VRAM
+    pub destination: u16,
+    /// Blocks left to copy minus one, like HDMA5 reports it
+    remaining_blocks: u8,
+    is_hblank_active: bool,
+}
+
+/// What the bus has to do after a write to HDMA5
+#[derive(Debug, PartialEq, Eq)]
+pub enum HdmaStart {
+    /// Copy the given amount of blocks right away
+    GeneralPurpose {
+        blocks: u16,
+    },
+    HBlank,
+    Cancelled,
+}
+
+impl Hdma {
+    pub fn new() -> Hdma {
+        Hdma {
+            source: 0,
+            destination: 0,
+            remaining_blocks: 0x7F,
+            is_hblank_active: false,
+        }
+    }
+
+    pub fn read_register(&self, address: usize) -> u8 {
+        match address {
+            HDMA5_REGISTER => {
+                // bit 7 is cleared while an HBlank DMA is running
+                let inactive = if self.is_hblank_active { 0 } else { 0x80 };
+                inactive | self.remaining_blocks
+            }
+            // the address registers are write only
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: usize, value: u8) -> Option<HdmaStart> {
+        match address {
+            0xFF51 => self.source = (self.source & 0x00FF) | (value as u16) << 8,
+            // the lower 4 bits are ignored, the transfers are always aligned to 16 bytes
+            0xFF52 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            0xFF53 => self.destination = (self.destination & 0x00FF) | ((value & 0x1F) as u16) << 8,
+            0xFF54 => self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5_REGISTER => {
+                if self.is_hblank_active && value & 0x80 == 0 {
+                    self.is_hblank_active = false;
+                    return Some(HdmaStart::Cancelled);
+                }
+
+                self.remaining_blocks = value & 0x7F;
+                if value & 0x80 != 0 {
+                    self.is_hblank_active = true;
+                    return Some(HdmaStart::HBlank);
+                }
+
+                let blocks = self.remaining_blocks as u16 + 1;
+                self.remaining_blocks = 0x7F;
+                return Some(HdmaStart::GeneralPurpose { blocks });
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    pub const fn is_hblank_active(&self) -> bool {
+        self.is_hblank_active
+    }
+
+    /// Moves the addresses to the next block, returns the source and destination
+    /// of the block that has to be copied
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, self.destination);
+        self.source = self.source.wrapping_add(HDMA_BLOCK_SIZE);
+        self.destination = (self.destination + HDMA_BLOCK_SIZE) & 0x1FF0;
+        block
+    }
+
+    /// Called after each block of an HBlank DMA
+    pub fn hblank_block_done(&mut self) {
+        if self.remaining_blocks == 0 {
+            self.is_hblank_active = false;
+            self.remaining_blocks = 0x7F;
+        } else {
+            self.remaining_blocks -= 1;
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.source);
+        writer.write_u16(self.destination);
+        writer.write_u8(self.remaining_blocks);
+        writer.write_bool(self.is_hblank_active);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.source = reader.read_u16()?;
+        self.destination = reader.read_u16()?;
+        self.remaining_blocks = reader.read_u8()? & 0x7F;
+        self.is_hblank_active = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// FF46 - DMA - OAM DMA. Copies 160 bytes from `value << 8` to OAM, one byte per M-cycle.
+/// The CPU can only use HRAM and the IO registers while it runs.
+pub struct OamDma {
+    /// Last value written to FF46
+    pub register: u8,
+    /// Source of the running transfer and amount of bytes copied so far
+    transfer: Option<(u16, u8)>,
+    /// Transfer that starts after its setup M-cycle. A running transfer keeps going
+    /// until then when the DMA is restarted.
+    pending_source: Option<u16>,
+    /// Last byte read by the DMA, what the CPU sees when it reads from a busy bus
+    last_byte: u8,
+}
+
+impl OamDma {
+    pub fn new() -> OamDma {
+        OamDma {
+            register: 0xFF,
+            transfer: None,
+            pending_source: None,
+            last_byte: 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, value: u8) {
+        self.register = value;
+        self.pending_source = Some((value as u16) << 8);
+    }
+
+    /// Whether the DMA owns the bus
+    pub const fn is_active(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    pub const fn last_byte(&self) -> u8 {
+        self.last_byte
+    }
+
+    /// Advances the DMA by one M-cycle, returns the source address and the OAM offset
+    /// of the byte to copy in this M-cycle
+    pub fn tick(&mut self) -> Option<(u16, usize)> {
+        let copy = match &mut self.transfer {
+            Some((source, copied)) => {
+                let offset = *copied;
+                *copied += 1;
+                Some((source.wrapping_add(offset as u16), offset as usize))
+            }
+            None => None,
+        };
+
+        if matches!(self.transfer, Some((_, copied)) if copied as usize == OAM_DMA_LENGTH) {
+            self.transfer = None;
+        }
+
+        // the setup of a new transfer takes an M-cycle
+        if let Some(source) = self.pending_source.take() {
+            self.transfer = Some((source, 0));
+        }
+
+        copy
+    }
+
+    /// Called by the bus with the byte read for the current M-cycle
+    pub fn set_last_byte(&mut self, value: u8) {
+        self.last_byte = value;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_bool(self.transfer.is_some());
+        let (source, copied) = self.transfer.unwrap_or_default();
+        writer.write_u16(source);
+        writer.write_u8(copied);
+        writer.write_bool(self.pending_source.is_some());
+        writer.write_u16(self.pending_source.unwrap_or_default());
+        writer.write_u8(self.last_byte);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.register = reader.read_u8()?;
+        let is_active = reader.read_bool()?;
+        let source = reader.read_u16()?;
+        let copied = reader.read_u8()?;
+        self.transfer = if is_active && (copied as usize) < OAM_DMA_LENGTH {
+            Some((source, copied))
+        } else {
+            None
+        };
+        let is_pending = reader.read_bool()?;
+        let pending_source = reader.read_u16()?;
+        self.pending_source = is_pending.then_some(pending_source);
+        self.last_byte = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Default for OamDma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bytes copied by an OAM DMA, the whole OAM
+pub const OAM_DMA_LENGTH: usize = 160;
+pub const OAM_DMA_REGISTER: usize = 0xFF46;
+
+pub const HDMA_REGISTERS_START: usize = 0xFF51;
+pub const HDMA_REGISTERS_END: usize = 0xFF55;
+pub const HDMA5_REGISTER: usize = 0xFF55;
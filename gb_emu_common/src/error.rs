@@ -14,7 +14,18 @@ pub enum EmulationError {
     UnsupportedCartridgeType { cartridge_type: CartridgeType },
     InvalidRomSizeCode { code: u8 },
     InvalidRamSizeCode { code: u8 },
+    UnknownGbxMapper { mapper: String },
+    UnsupportedGbxVersion { major: u32, minor: u32 },
     NoRom,
+    InvalidSaveState,
+    UnsupportedSaveStateVersion { version: u32 },
+    SaveStateRomMismatch,
+    SaveStateNeedsBootRom,
+    InvalidMovie,
+    MovieRomMismatch,
+    InvalidBootRom { size: usize },
+    InvalidGbs,
+    InvalidGbsTrack { track_count: u8 },
 }
 
 impl std::error::Error for EmulationError {}
@@ -80,9 +91,59 @@ impl fmt::Display for EmulationError {
                 )
             }
 
+            Self::UnknownGbxMapper { ref mapper } => {
+                write!(f, "Unknown GBX mapper \"{mapper}\"")
+            }
+
+            Self::UnsupportedGbxVersion { major, minor } => {
+                write!(f, "GBX footer version {major}.{minor} is not supported")
+            }
+
             Self::NoRom => {
                 write!(f, "No ROM loaded")
             }
+
+            Self::InvalidSaveState => {
+                write!(f, "Invalid or corrupted save state")
+            }
+
+            Self::UnsupportedSaveStateVersion { version } => {
+                write!(
+                    f,
+                    "Save state version {version} is not supported by this version of the emulator"
+                )
+            }
+
+            Self::SaveStateRomMismatch => {
+                write!(f, "This save state was created with a different ROM")
+            }
+
+            Self::SaveStateNeedsBootRom => {
+                write!(f, "This save state was created while the boot ROM was running, load the boot ROM first")
+            }
+
+            Self::InvalidMovie => {
+                write!(f, "Invalid or unsupported movie file")
+            }
+
+            Self::MovieRomMismatch => {
+                write!(f, "This movie was recorded with a different ROM")
+            }
+
+            Self::InvalidBootRom { size } => {
+                write!(
+                    f,
+                    "Invalid boot ROM of {size} bytes, expected a DMG or CGB boot ROM"
+                )
+            }
+
+            Self::InvalidGbs => {
+                write!(f, "Invalid GBS file")
+            }
+
+            Self::InvalidGbsTrack { track_count } => {
+                write!(f, "The GBS file only has {track_count} tracks")
+            }
         }
     }
 }
@@ -0,0 +1,238 @@
+use crate::cartridge::ROM_BANK_SIZE;
+use crate::error::{EmulationError, Result};
+
+/// GBS music file, the sound code and data of a game with a header that tells how to
+/// play it. The player puts the code in a cartridge of its own, together with a small
+/// driver that calls the init routine and then the play routine on every VBlank or
+/// timer interrupt.
+pub struct Gbs {
+    pub song_count: u8,
+    /// Song to play first, counting from 0
+    pub first_song: u8,
+    pub load_address: u16,
+    /// Routine that starts a song, with its number in A
+    pub init_address: u16,
+    /// Routine called on every interrupt to advance the song
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    /// TMA and TAC to play from the timer interrupt instead of VBlank
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    pub code: Vec<u8>,
+}
+
+const HEADER_SIZE: usize = 0x70;
+/// Everything below the load address belongs to the driver
+const MIN_LOAD_ADDRESS: u16 = 0x400;
+/// The code has to go in the cartridge ROM, VRAM comes right after it
+const MAX_LOAD_ADDRESS: u16 = 0x8000;
+
+const ENTRY_POINT: usize = 0x100;
+const INIT_DRIVER: usize = 0x150;
+const PLAY_DRIVER: usize = 0x200;
+const VBLANK_VECTOR: usize = 0x40;
+const TIMER_VECTOR: usize = 0x50;
+
+const VBLANK_INTERRUPT: u8 = 0x01;
+const TIMER_INTERRUPT: u8 = 0x04;
+const TIMER_ENABLED: u8 = 0x04;
+/// Bit of TAC that GBS files use to ask for CGB double speed
+const DOUBLE_SPEED: u8 = 0x80;
+
+const JP: u8 = 0xC3;
+const CALL: u8 = 0xCD;
+
+pub fn is_gbs(data: &[u8]) -> bool {
+    data.starts_with(b"GBS")
+}
+
+impl Gbs {
+    pub fn parse(data: &[u8]) -> Result<Gbs> {
+        if !is_gbs(data) || data.len() <= HEADER_SIZE {
+            return Err(EmulationError::InvalidGbs);
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let read_text = |offset: usize| {
+            let text = &data[offset..offset + 32];
+            let end = text.iter().position(|&byte| byte == 0).unwrap_or(32);
+            String::from_utf8_lossy(&text[..end]).into_owned()
+        };
+
+        let gbs = Gbs {
+            song_count: data[0x04],
+            first_song: data[0x05].saturating_sub(1),
+            load_address: read_u16(0x06),
+            init_address: read_u16(0x08),
+            play_address: read_u16(0x0A),
+            stack_pointer: read_u16(0x0C),
+            timer_modulo: data[0x0E],
+            timer_control: data[0x0F],
+            title: read_text(0x10),
+            author: read_text(0x30),
+            copyright: read_text(0x50),
+            code: data[HEADER_SIZE..].to_vec(),
+        };
+
+        let end = gbs.load_address as usize + gbs.code.len();
+        let is_valid = data[0x03] == 1
+            && gbs.song_count > 0
+            && gbs.load_address >= MIN_LOAD_ADDRESS
+            && gbs.load_address < MAX_LOAD_ADDRESS
+            && end <= ROM_BANK_SIZE * 512;
+        if !is_valid {
+            return Err(EmulationError::InvalidGbs);
+        }
+
+        Ok(gbs)
+    }
+
+    /// The play routine runs on the timer interrupt instead of VBlank
+    pub fn uses_timer(&self) -> bool {
+        self.timer_control & TIMER_ENABLED != 0
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.timer_control & DOUBLE_SPEED != 0
+    }
+
+    /// Builds a ROM with the code and the driver that plays the given song
+    pub fn rom(&self, song: u8) -> Vec<u8> {
+        let load_address = self.load_address as usize;
+        let size = (load_address + self.code.len()).div_ceil(ROM_BANK_SIZE);
+        let bank_amount = size.next_power_of_two().max(2);
+        let mut rom = vec![0xFF; bank_amount * ROM_BANK_SIZE];
+        rom[load_address..load_address + self.code.len()].copy_from_slice(&self.code);
+
+        // the RST instructions go to the code
+        for vector in (0x00..0x40).step_by(8) {
+            write_jump(&mut rom, vector, self.load_address + vector as u16);
+        }
+        write_jump(&mut rom, VBLANK_VECTOR, PLAY_DRIVER as u16);
+        write_jump(&mut rom, TIMER_VECTOR, PLAY_DRIVER as u16);
+
+        rom[ENTRY_POINT] = 0x00; // NOP
+        write_jump(&mut rom, ENTRY_POINT + 1, INIT_DRIVER as u16);
+
+        // header, with the title and an MBC with RAM for the code to bank switch
+        let title = self.title.as_bytes();
+        let title_size = title.len().min(15);
+        rom[0x134..0x144].fill(0);
+        rom[0x134..0x134 + title_size].copy_from_slice(&title[..title_size]);
+        rom[0x143] = if self.is_double_speed() { 0x80 } else { 0x00 };
+        rom[0x144..0x14D].fill(0);
+        rom[0x147] = 0x02; // MBC1+RAM
+        rom[0x148] = bank_amount.trailing_zeros() as u8 - 1;
+        rom[0x149] = 0x02; // 8 KiB
+        rom[0x14D] = rom[0x134..0x14D]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1));
+
+        let [sp_low, sp_high] = self.stack_pointer.to_le_bytes();
+        let mut init = vec![0xF3, 0x31, sp_low, sp_high]; // DI, LD SP,sp
+        if self.is_double_speed() {
+            // LD A,1; LDH (KEY1),A; STOP
+            init.extend_from_slice(&[0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00]);
+        }
+        let interrupt = if self.uses_timer() {
+            TIMER_INTERRUPT
+        } else {
+            VBLANK_INTERRUPT
+        };
+        init.extend_from_slice(&[0x3E, song]); // LD A,song
+        init.extend_from_slice(&call(self.init_address));
+        init.extend_from_slice(&[0x3E, self.timer_modulo, 0xE0, 0x06]); // LD A,tma; LDH (TMA),A
+        init.extend_from_slice(&[0x3E, self.timer_control & 0x07, 0xE0, 0x07]); // LD A,tac; LDH (TAC),A
+        init.extend_from_slice(&[0x3E, interrupt, 0xE0, 0xFF]); // LD A,interrupt; LDH (IE),A
+        init.extend_from_slice(&[0xAF, 0xE0, 0x0F, 0xFB]); // XOR A; LDH (IF),A; EI
+        init.extend_from_slice(&[0x76, 0x18, 0xFD]); // HALT; JR back to HALT
+        rom[INIT_DRIVER..INIT_DRIVER + init.len()].copy_from_slice(&init);
+
+        let mut play = vec![0xF5, 0xC5, 0xD5, 0xE5]; // PUSH AF, BC, DE, HL
+        play.extend_from_slice(&call(self.play_address));
+        play.extend_from_slice(&[0xE1, 0xD1, 0xC1, 0xF1, 0xD9]); // POP HL, DE, BC, AF; RETI
+        rom[PLAY_DRIVER..PLAY_DRIVER + play.len()].copy_from_slice(&play);
+
+        rom
+    }
+}
+
+fn call(address: u16) -> [u8; 3] {
+    let [low, high] = address.to_le_bytes();
+    [CALL, low, high]
+}
+
+fn write_jump(rom: &mut [u8], offset: usize, address: u16) {
+    let [low, high] = address.to_le_bytes();
+    rom[offset..offset + 3].copy_from_slice(&[JP, low, high]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::GameBoy;
+
+    fn test_gbs() -> Vec<u8> {
+        let mut data = b"GBS\x01\x03\x02".to_vec();
+        for address in [0x400u16, 0x400, 0x408, 0xFFFE] {
+            data.extend_from_slice(&address.to_le_bytes());
+        }
+        data.extend_from_slice(&[0x00, 0x00]);
+        data.resize(HEADER_SIZE, 0);
+        // init: LD (C001),A; LD A,80; LDH (NR52),A; RET
+        data.extend_from_slice(&[0xEA, 0x01, 0xC0, 0x3E, 0x80, 0xE0, 0x26, 0xC9]);
+        // play: LD HL,C000; INC (HL); RET
+        data.extend_from_slice(&[0x21, 0x00, 0xC0, 0x34, 0xC9]);
+        data
+    }
+
+    #[test]
+    fn calls_play_on_every_vblank() {
+        let gbs = Gbs::parse(&test_gbs()).unwrap();
+        assert_eq!(gbs.song_count, 3);
+        assert_eq!(gbs.first_song, 1);
+        assert!(!gbs.uses_timer());
+
+        let mut gb = GameBoy::new();
+        gb.load_gbs(&gbs, 2).unwrap();
+        gb.cpu.bus.write_byte(0xC000, 0).unwrap();
+        for _ in 0..10 {
+            gb.run_frame().unwrap();
+        }
+
+        assert_eq!(gb.cpu.bus.read_byte(0xC001).unwrap(), 2);
+        assert!((9..=10).contains(&gb.cpu.bus.read_byte(0xC000).unwrap()));
+        assert_eq!(gb.cpu.bus.read_byte(0xFF26).unwrap() & 0x80, 0x80);
+
+        assert!(matches!(
+            gb.load_gbs(&gbs, 3),
+            Err(EmulationError::InvalidGbsTrack { track_count: 3 })
+        ));
+    }
+
+    #[test]
+    fn plays_on_its_own_model_until_a_rom_is_loaded() {
+        let mut data = test_gbs();
+        data[0x0F] = DOUBLE_SPEED;
+        let gbs = Gbs::parse(&data).unwrap();
+
+        let mut gb = GameBoy::new();
+        gb.set_model(Model::Sgb);
+        gb.load_gbs(&gbs, 0).unwrap();
+        assert_eq!(gb.model(), Model::Cgb);
+        gb.load_gbs(&gbs, 1).unwrap();
+        gb.load_rom(vec![0; ROM_BANK_SIZE * 2]).unwrap();
+        assert_eq!(gb.model(), Model::Sgb);
+    }
+
+    #[test]
+    fn rejects_code_outside_of_the_rom() {
+        let mut data = test_gbs();
+        data[0x06..0x08].copy_from_slice(&0x8000u16.to_le_bytes());
+        assert!(matches!(Gbs::parse(&data), Err(EmulationError::InvalidGbs)));
+    }
+}
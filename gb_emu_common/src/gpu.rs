@@ -1,10 +1,75 @@
-use crate::error::Result;
+use crate::error::{EmulationError, Result};
+use crate::interrupt::{Interrupt, InterruptRegister};
+use crate::palette::{PaletteRam, Rgb555, DMG_SHADES};
+use crate::reset::{RamPattern, ResetKind};
+use crate::save_state::{StateReader, StateWriter};
+use crate::CYCLES_PER_FRAME;
 
 pub struct Gpu {
-    vram: [u8; VRAM_SIZE],
+    /// The CGB has a second VRAM bank, used for tiles and the BG map attributes
+    vram: [[u8; VRAM_SIZE]; VRAM_BANK_AMOUNT],
     oam: [u8; OAM_SIZE],
-    tile_set: [Tile; 0x180],
+    tile_set: [[Tile; 0x180]; VRAM_BANK_AMOUNT],
+    /// FF4F - VBK - VRAM bank used by the CPU
+    pub vram_bank: usize,
+    /// FF40 - LCDC - LCD Control (R/W)
+    pub lcdc: u8,
+    /// Interrupt sources selected on FF41 - STAT - LCD Status (R/W)
+    stat_interrupts: u8,
+    /// FF42 - SCY - Scroll Y (R/W)
+    pub scy: u8,
+    /// FF43 - SCX - Scroll X (R/W)
+    pub scx: u8,
     pub ly: u8, // LCD Y Coordinate (Read)
+    /// FF45 - LYC - LY Compare (R/W)
+    pub lyc: u8,
+    /// FF47 - BGP - BG Palette Data (R/W)
+    pub bgp: u8,
+    /// FF48 - OBP0 - Object Palette 0 Data (R/W)
+    pub obp0: u8,
+    /// FF49 - OBP1 - Object Palette 1 Data (R/W)
+    pub obp1: u8,
+    /// FF4A - WY - Window Y Position (R/W)
+    pub wy: u8,
+    /// FF4B - WX - Window X Position + 7 (R/W)
+    pub wx: u8,
+    mode: PpuMode,
+    /// Cycles since the beginning of the current line
+    line_cycles: u32,
+    /// State of the STAT interrupt line, the interrupt is only requested when it goes high
+    stat_line: bool,
+    frame_count: u64,
+    /// FF68/FF69 - BCPS/BCPD - Background color palettes
+    pub bg_palettes: PaletteRam,
+    /// FF6A/FF6B - OCPS/OCPD - Object color palettes
+    pub obj_palettes: PaletteRam,
+    /// FF6C - OPRI - Object priority mode, bit 0 set means DMG style priority
+    pub object_priority_mode: u8,
+    pub color_mode: ColorMode,
+    /// Line of the window being drawn, it only advances on lines where the window is visible
+    window_line: u8,
+    is_line_rendered: bool,
+    /// HBlanks that started since the last call to `take_hblanks`
+    hblanks: u32,
+    framebuffer: Vec<Rgb555>,
+}
+
+/// How the PPU picks the colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Shades of gray selected by BGP, OBP0 and OBP1
+    Dmg,
+    /// DMG game on a CGB, BGP, OBP0 and OBP1 select colors from the first CGB palettes
+    Compatibility,
+    Cgb,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PpuMode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
 }
 
 #[derive(Clone, Copy)]
@@ -24,21 +89,443 @@ fn empty_tile() -> Tile {
 impl Gpu {
     pub fn new() -> Gpu {
         Gpu {
-            vram: [0; VRAM_SIZE],
+            vram: [[0; VRAM_SIZE]; VRAM_BANK_AMOUNT],
             oam: [0; OAM_SIZE],
-            tile_set: [empty_tile(); 0x180],
-            ly: 0x90,
+            tile_set: [[empty_tile(); 0x180]; VRAM_BANK_AMOUNT],
+            vram_bank: 0,
+            lcdc: 0x91,
+            stat_interrupts: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+            mode: PpuMode::OamScan,
+            line_cycles: 0,
+            stat_line: false,
+            frame_count: 0,
+            bg_palettes: PaletteRam::new(),
+            obj_palettes: PaletteRam::new(),
+            object_priority_mode: 0,
+            color_mode: ColorMode::Dmg,
+            window_line: 0,
+            is_line_rendered: false,
+            hblanks: 0,
+            framebuffer: vec![DMG_SHADES[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn reset(&mut self, kind: ResetKind, pattern: RamPattern) {
+        *self = Gpu {
+            vram: self.vram,
+            oam: self.oam,
+            tile_set: self.tile_set,
+            frame_count: self.frame_count,
+            ..Gpu::new()
+        };
+
+        if kind == ResetKind::PowerCycle {
+            self.frame_count = 0;
+            for (bank, vram) in self.vram.iter_mut().enumerate() {
+                pattern.fill(vram, VRAM_BEGIN + bank * VRAM_SIZE);
+            }
+            pattern.fill(&mut self.oam, OAM_BEGIN);
+            self.update_tile_set();
+        }
+    }
+
+    /// Advances the PPU by the given amount of t-cycles
+    pub fn run(&mut self, cycles: u32, interrupt_flag: &mut InterruptRegister) {
+        self.line_cycles += cycles;
+
+        if !self.is_lcd_enabled() {
+            // Nothing is drawn, but the frontend still expects frames to end
+            if self.line_cycles >= CYCLES_PER_FRAME {
+                self.line_cycles -= CYCLES_PER_FRAME;
+                self.frame_count += 1;
+            }
+
+            return;
+        }
+
+        while self.line_cycles >= CYCLES_PER_LINE {
+            self.line_cycles -= CYCLES_PER_LINE;
+            if !self.is_line_rendered {
+                self.render_line();
+            }
+            self.is_line_rendered = false;
+            self.ly += 1;
+
+            if self.ly == SCREEN_HEIGHT as u8 {
+                interrupt_flag.request(Interrupt::VBlank);
+                self.frame_count += 1;
+            } else if self.ly > LAST_LINE {
+                self.ly = 0;
+                self.window_line = 0;
+            }
+        }
+
+        self.mode = if self.ly >= SCREEN_HEIGHT as u8 {
+            PpuMode::VBlank
+        } else if self.line_cycles < OAM_SCAN_CYCLES {
+            PpuMode::OamScan
+        } else if self.line_cycles < OAM_SCAN_CYCLES + DRAWING_CYCLES {
+            PpuMode::Drawing
+        } else {
+            PpuMode::HBlank
+        };
+
+        if self.mode == PpuMode::HBlank && !self.is_line_rendered {
+            self.render_line();
+        }
+
+        self.update_stat_line(interrupt_flag);
+    }
+
+    /// Draws the current line to the framebuffer
+    fn render_line(&mut self) {
+        self.is_line_rendered = true;
+        let ly = self.ly as usize;
+        if ly >= SCREEN_HEIGHT {
+            return;
+        }
+
+        // lines are rendered when their HBlank begins
+        self.hblanks += 1;
+
+        let mut line = [DMG_SHADES[0]; SCREEN_WIDTH];
+        // color number and priority of each background pixel, sprites need them
+        let mut bg_colors = [0; SCREEN_WIDTH];
+        let mut bg_priorities = [false; SCREEN_WIDTH];
+
+        let is_cgb = self.color_mode == ColorMode::Cgb;
+        // on CGB, LCDC bit 0 takes away the priority of the background instead of hiding it
+        let is_bg_enabled = is_cgb || self.lcdc & LCDC_BG_ENABLE != 0;
+        let wx = self.wx as usize;
+        let is_window_visible = is_bg_enabled
+            && self.lcdc & LCDC_WINDOW_ENABLE != 0
+            && ly >= self.wy as usize
+            && wx <= 166;
+        let mut is_window_drawn = false;
+
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let (tile_map, map_x, map_y) = if is_window_visible && x + 7 >= wx {
+                is_window_drawn = true;
+                let tile_map = if self.lcdc & LCDC_WINDOW_TILE_MAP != 0 {
+                    TILE_MAP_1
+                } else {
+                    TILE_MAP_0
+                };
+                (tile_map, x + 7 - wx, self.window_line as usize)
+            } else if is_bg_enabled {
+                let tile_map = if self.lcdc & LCDC_BG_TILE_MAP != 0 {
+                    TILE_MAP_1
+                } else {
+                    TILE_MAP_0
+                };
+                let map_x = (x + self.scx as usize) & 0xFF;
+                let map_y = (ly + self.scy as usize) & 0xFF;
+                (tile_map, map_x, map_y)
+            } else {
+                *pixel = self.bg_color(0, 0);
+                continue;
+            };
+
+            let map_pos = tile_map + (map_y / 8) * 32 + map_x / 8;
+            let tile_number = self.vram[0][map_pos];
+            // the attributes are on the same position of the second VRAM bank
+            let attributes = if is_cgb { self.vram[1][map_pos] } else { 0 };
+
+            let tile_index = if self.lcdc & LCDC_TILE_DATA != 0 {
+                tile_number as usize
+            } else {
+                // tiles 0-127 are taken from 0x9000, and 128-255 from 0x8800
+                (256 + tile_number as i8 as isize) as usize
+            };
+            let bank = ((attributes & BG_ATTRIBUTE_BANK) >> 3) as usize;
+            let mut row = map_y % 8;
+            if attributes & ATTRIBUTE_Y_FLIP != 0 {
+                row = 7 - row;
+            }
+            let mut column = map_x % 8;
+            if attributes & ATTRIBUTE_X_FLIP != 0 {
+                column = 7 - column;
+            }
+
+            let color = self.tile_set[bank][tile_index][row][column] as usize;
+            bg_colors[x] = color;
+            bg_priorities[x] = attributes & ATTRIBUTE_PRIORITY != 0;
+            *pixel = self.bg_color((attributes & ATTRIBUTE_PALETTE) as usize, color);
+        }
+
+        if is_window_drawn {
+            self.window_line += 1;
+        }
+
+        if self.lcdc & LCDC_OBJ_ENABLE != 0 {
+            self.render_line_sprites(&mut line, &bg_colors, &bg_priorities);
+        }
+
+        let start = ly * SCREEN_WIDTH;
+        self.framebuffer[start..start + SCREEN_WIDTH].copy_from_slice(&line);
+    }
+
+    fn render_line_sprites(
+        &self,
+        line: &mut [Rgb555; SCREEN_WIDTH],
+        bg_colors: &[usize; SCREEN_WIDTH],
+        bg_priorities: &[bool; SCREEN_WIDTH],
+    ) {
+        let ly = self.ly as usize;
+        let height = if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+
+        // only the first 10 sprites on the line (in OAM order) are drawn
+        let mut sprites: Vec<&[u8]> = self
+            .oam
+            .chunks_exact(4)
+            .filter(|sprite| {
+                let y = sprite[0] as usize;
+                ly + 16 >= y && ly + 16 < y + height
+            })
+            .take(10)
+            .collect();
+
+        // sprites are sorted from the highest to the lowest priority. The CGB only uses the
+        // OAM position, the DMG prefers the sprite that's more to the left.
+        let is_cgb = self.color_mode == ColorMode::Cgb;
+        if !is_cgb || self.object_priority_mode & 0b1 != 0 {
+            sprites.sort_by_key(|sprite| sprite[1]);
+        }
+
+        let mut is_drawn = [false; SCREEN_WIDTH];
+        for sprite in sprites {
+            let (y, x, tile, attributes) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+
+            let mut row = ly + 16 - y as usize;
+            if attributes & ATTRIBUTE_Y_FLIP != 0 {
+                row = height - 1 - row;
+            }
+            let tile_index = if height == 16 {
+                (tile & 0xFE) as usize + row / 8
+            } else {
+                tile as usize
+            };
+            let bank = if is_cgb {
+                ((attributes & OBJ_ATTRIBUTE_BANK) >> 3) as usize
+            } else {
+                0
+            };
+
+            for column in 0..8 {
+                let screen_x = x as usize + column;
+                if !(8..SCREEN_WIDTH + 8).contains(&screen_x) || is_drawn[screen_x - 8] {
+                    continue;
+                }
+                let screen_x = screen_x - 8;
+
+                let tile_column = if attributes & ATTRIBUTE_X_FLIP != 0 {
+                    7 - column
+                } else {
+                    column
+                };
+                let color = self.tile_set[bank][tile_index][row % 8][tile_column] as usize;
+                if color == 0 {
+                    // transparent
+                    continue;
+                }
+                is_drawn[screen_x] = true;
+
+                let is_behind_bg = attributes & ATTRIBUTE_PRIORITY != 0;
+                let bg_has_priority = if is_cgb {
+                    self.lcdc & LCDC_BG_ENABLE != 0 && (is_behind_bg || bg_priorities[screen_x])
+                } else {
+                    is_behind_bg
+                };
+                if bg_has_priority && bg_colors[screen_x] != 0 {
+                    continue;
+                }
+
+                line[screen_x] = self.obj_color(attributes, color);
+            }
+        }
+    }
+
+    fn bg_color(&self, palette: usize, color: usize) -> Rgb555 {
+        match self.color_mode {
+            ColorMode::Dmg => DMG_SHADES[dmg_shade(self.bgp, color)],
+            ColorMode::Compatibility => self.bg_palettes.color(0, dmg_shade(self.bgp, color)),
+            ColorMode::Cgb => self.bg_palettes.color(palette, color),
+        }
+    }
+
+    fn obj_color(&self, attributes: u8, color: usize) -> Rgb555 {
+        let dmg_palette = ((attributes & OBJ_ATTRIBUTE_DMG_PALETTE) >> 4) as usize;
+        let obp = if dmg_palette == 0 {
+            self.obp0
+        } else {
+            self.obp1
+        };
+
+        match self.color_mode {
+            ColorMode::Dmg => DMG_SHADES[dmg_shade(obp, color)],
+            ColorMode::Compatibility => self.obj_palettes.color(dmg_palette, dmg_shade(obp, color)),
+            ColorMode::Cgb => {
+                let palette = (attributes & ATTRIBUTE_PALETTE) as usize;
+                self.obj_palettes.color(palette, color)
+            }
+        }
+    }
+
+    /// Amount of HBlanks since the last call, used by the HBlank DMA
+    pub fn take_hblanks(&mut self) -> u32 {
+        std::mem::take(&mut self.hblanks)
+    }
+
+    /// The last complete frame, in RGB555
+    pub fn framebuffer(&self) -> &[Rgb555] {
+        &self.framebuffer
+    }
+
+    /// FF68-FF6C - CGB palette registers
+    pub fn read_palette_register(&self, address: usize) -> u8 {
+        let is_locked = self.is_palette_locked();
+        match address {
+            0xFF68 => self.bg_palettes.read_index(),
+            0xFF69 => self.bg_palettes.read_data(is_locked),
+            0xFF6A => self.obj_palettes.read_index(),
+            0xFF6B => self.obj_palettes.read_data(is_locked),
+            0xFF6C => 0xFE | self.object_priority_mode,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_palette_register(&mut self, address: usize, value: u8) {
+        let is_locked = self.is_palette_locked();
+        match address {
+            0xFF68 => self.bg_palettes.write_index(value),
+            0xFF69 => self.bg_palettes.write_data(value, is_locked),
+            0xFF6A => self.obj_palettes.write_index(value),
+            0xFF6B => self.obj_palettes.write_data(value, is_locked),
+            0xFF6C => self.object_priority_mode = value & 0b1,
+            _ => {}
+        }
+    }
+
+    /// The palettes can't be accessed while the PPU is drawing
+    fn is_palette_locked(&self) -> bool {
+        self.is_lcd_enabled() && self.mode == PpuMode::Drawing
+    }
+
+    fn update_stat_line(&mut self, interrupt_flag: &mut InterruptRegister) {
+        let stat_line = (self.stat_interrupts & STAT_LYC_INTERRUPT != 0 && self.ly == self.lyc)
+            || match self.mode {
+                PpuMode::HBlank => self.stat_interrupts & STAT_HBLANK_INTERRUPT != 0,
+                PpuMode::VBlank => self.stat_interrupts & STAT_VBLANK_INTERRUPT != 0,
+                PpuMode::OamScan => self.stat_interrupts & STAT_OAM_INTERRUPT != 0,
+                PpuMode::Drawing => false,
+            };
+
+        if stat_line && !self.stat_line {
+            interrupt_flag.request(Interrupt::LcdStat);
+        }
+        self.stat_line = stat_line;
+    }
+
+    pub fn read_register(&self, address: usize) -> Result<u8> {
+        match address {
+            LCD_CONTROL_REGISTER => Ok(self.lcdc),
+
+            LCD_STATUS_REGISTER => {
+                let coincidence = if self.ly == self.lyc { 1 } else { 0 };
+                let mode = if self.is_lcd_enabled() {
+                    self.mode as u8
+                } else {
+                    0
+                };
+
+                // bit 7 is unused and always reads as 1
+                Ok(0x80 | self.stat_interrupts | (coincidence << 2) | mode)
+            }
+
+            0xFF42 => Ok(self.scy),
+            0xFF43 => Ok(self.scx),
+            0xFF44 => Ok(self.ly),
+            0xFF45 => Ok(self.lyc),
+            0xFF47 => Ok(self.bgp),
+            0xFF48 => Ok(self.obp0),
+            0xFF49 => Ok(self.obp1),
+            0xFF4A => Ok(self.wy),
+            0xFF4B => Ok(self.wx),
+
+            _ => Err(EmulationError::InvalidMemoryRead { address }),
+        }
+    }
+
+    pub fn write_register(&mut self, address: usize, value: u8) -> Result<()> {
+        match address {
+            LCD_CONTROL_REGISTER => {
+                let was_enabled = self.is_lcd_enabled();
+                self.lcdc = value;
+
+                if was_enabled && !self.is_lcd_enabled() {
+                    // turning the LCD off resets LY and blanks the screen
+                    self.ly = 0;
+                    self.window_line = 0;
+                    self.framebuffer.fill(DMG_SHADES[0]);
+                    self.mode = PpuMode::HBlank;
+                    self.line_cycles = 0;
+                } else if !was_enabled && self.is_lcd_enabled() {
+                    self.mode = PpuMode::OamScan;
+                    self.line_cycles = 0;
+                }
+            }
+
+            LCD_STATUS_REGISTER => self.stat_interrupts = value & 0b0111_1000,
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF44 => {} // LY is read only
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+
+            _ => return Err(EmulationError::InvalidMemoryWrite { address, value }),
         }
+
+        Ok(())
+    }
+
+    pub const fn is_lcd_enabled(&self) -> bool {
+        self.lcdc & 0b1000_0000 != 0
+    }
+
+    pub const fn mode(&self) -> PpuMode {
+        self.mode
+    }
+
+    /// Amount of frames drawn since power on
+    pub const fn frame_count(&self) -> u64 {
+        self.frame_count
     }
 
     pub fn read_byte_vram(&self, address: usize) -> Result<u8> {
         let vram_pos = address - VRAM_BEGIN;
-        Ok(self.vram[vram_pos])
+        Ok(self.vram[self.vram_bank][vram_pos])
     }
 
     pub fn write_byte_vram(&mut self, address: usize, value: u8) -> Result<()> {
         let vram_pos = address - VRAM_BEGIN;
-        self.vram[vram_pos] = value;
+        self.vram[self.vram_bank][vram_pos] = value;
 
         // If our address is greater than 0x1800, we're not writing to the tile set storage
         // so we can just return.
@@ -46,6 +533,11 @@ impl Gpu {
             return Ok(());
         }
 
+        self.update_tile_row(self.vram_bank, vram_pos);
+        Ok(())
+    }
+
+    fn update_tile_row(&mut self, bank: usize, vram_pos: usize) {
         // Tiles rows are encoded in two bytes with the first byte always
         // on an even address. Bitwise ANDing the address with 0xffe
         // gives us the address of the first byte.
@@ -53,8 +545,8 @@ impl Gpu {
         let normalized_index = vram_pos & 0xFFFE;
 
         // First we need to get the two bytes that encode the tile row.
-        let byte1 = self.vram[normalized_index];
-        let byte2 = self.vram[normalized_index + 1];
+        let byte1 = self.vram[bank][normalized_index];
+        let byte2 = self.vram[bank][normalized_index + 1];
 
         // A tiles is 8 rows tall. Since each row is encoded with two bytes a tile
         // is therefore 16 bytes in total.
@@ -97,10 +589,8 @@ impl Gpu {
                 (false, false) => TilePixelValue::Zero,
             };
 
-            self.tile_set[tile_index][row_index][pixel_index] = value;
+            self.tile_set[bank][tile_index][row_index][pixel_index] = value;
         }
-
-        Ok(())
     }
 
     pub fn read_byte_oam(&self, address: usize) -> Result<u8> {
@@ -114,12 +604,142 @@ impl Gpu {
 
         Ok(())
     }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        for vram in &self.vram {
+            writer.write_bytes(vram);
+        }
+        writer.write_u8(self.vram_bank as u8);
+        writer.write_bytes(&self.oam);
+        writer.write_u8(self.lcdc);
+        writer.write_u8(self.stat_interrupts);
+        writer.write_u8(self.scy);
+        writer.write_u8(self.scx);
+        writer.write_u8(self.ly);
+        writer.write_u8(self.lyc);
+        writer.write_u8(self.bgp);
+        writer.write_u8(self.obp0);
+        writer.write_u8(self.obp1);
+        writer.write_u8(self.wy);
+        writer.write_u8(self.wx);
+        writer.write_u8(self.mode as u8);
+        writer.write_u32(self.line_cycles);
+        writer.write_bool(self.stat_line);
+        writer.write_u64(self.frame_count);
+        self.bg_palettes.save_state(writer);
+        self.obj_palettes.save_state(writer);
+        writer.write_u8(self.object_priority_mode);
+        writer.write_u8(self.window_line);
+        writer.write_bool(self.is_line_rendered);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        for vram in &mut self.vram {
+            reader.read_bytes(vram)?;
+        }
+        self.vram_bank = (reader.read_u8()? & 0b1) as usize;
+        reader.read_bytes(&mut self.oam)?;
+        self.lcdc = reader.read_u8()?;
+        self.stat_interrupts = reader.read_u8()?;
+        self.scy = reader.read_u8()?;
+        self.scx = reader.read_u8()?;
+        self.ly = reader.read_u8()?;
+        self.lyc = reader.read_u8()?;
+        self.bgp = reader.read_u8()?;
+        self.obp0 = reader.read_u8()?;
+        self.obp1 = reader.read_u8()?;
+        self.wy = reader.read_u8()?;
+        self.wx = reader.read_u8()?;
+        self.mode = match reader.read_u8()? {
+            0 => PpuMode::HBlank,
+            1 => PpuMode::VBlank,
+            2 => PpuMode::OamScan,
+            3 => PpuMode::Drawing,
+            _ => return Err(EmulationError::InvalidSaveState),
+        };
+        self.line_cycles = reader.read_u32()?;
+        self.stat_line = reader.read_bool()?;
+        self.frame_count = reader.read_u64()?;
+        self.bg_palettes.load_state(reader)?;
+        self.obj_palettes.load_state(reader)?;
+        self.object_priority_mode = reader.read_u8()? & 0b1;
+        self.window_line = reader.read_u8()?;
+        self.is_line_rendered = reader.read_bool()?;
+
+        // the tile set is just a cache of the VRAM, so it has to be rebuilt
+        self.update_tile_set();
+
+        Ok(())
+    }
+
+    fn update_tile_set(&mut self) {
+        for bank in 0..VRAM_BANK_AMOUNT {
+            for vram_pos in (0..0x1800).step_by(2) {
+                self.update_tile_row(bank, vram_pos);
+            }
+        }
+    }
+}
+
+impl Default for Gpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub const VRAM_BEGIN: usize = 0x8000;
 pub const VRAM_END: usize = 0x9FFF;
 pub const VRAM_SIZE: usize = VRAM_END - VRAM_BEGIN + 1;
+pub const VRAM_BANK_AMOUNT: usize = 2;
+pub const VRAM_BANK_REGISTER: usize = 0xFF4F;
 
 pub const OAM_BEGIN: usize = 0xFE00;
 pub const OAM_END: usize = 0xFE9F;
 pub const OAM_SIZE: usize = OAM_END - OAM_BEGIN + 1;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+// PPU timing, in t-cycles
+pub const CYCLES_PER_LINE: u32 = 456;
+pub const OAM_SCAN_CYCLES: u32 = 80;
+pub const DRAWING_CYCLES: u32 = 172;
+pub const LAST_LINE: u8 = 153;
+
+pub const LCD_CONTROL_REGISTER: usize = 0xFF40;
+pub const LCD_STATUS_REGISTER: usize = 0xFF41;
+pub const LCD_REGISTERS_START: usize = 0xFF40;
+pub const LCD_REGISTERS_END: usize = 0xFF4B;
+
+// LCDC bits
+const LCDC_BG_ENABLE: u8 = 0b0000_0001;
+const LCDC_OBJ_ENABLE: u8 = 0b0000_0010;
+const LCDC_OBJ_SIZE: u8 = 0b0000_0100;
+const LCDC_BG_TILE_MAP: u8 = 0b0000_1000;
+const LCDC_TILE_DATA: u8 = 0b0001_0000;
+const LCDC_WINDOW_ENABLE: u8 = 0b0010_0000;
+const LCDC_WINDOW_TILE_MAP: u8 = 0b0100_0000;
+
+/// Tile maps, relative to the beginning of VRAM
+const TILE_MAP_0: usize = 0x1800;
+const TILE_MAP_1: usize = 0x1C00;
+
+// BG map attributes and sprite attributes share most bits
+const ATTRIBUTE_PALETTE: u8 = 0b0000_0111;
+const BG_ATTRIBUTE_BANK: u8 = 0b0000_1000;
+const OBJ_ATTRIBUTE_BANK: u8 = 0b0000_1000;
+const OBJ_ATTRIBUTE_DMG_PALETTE: u8 = 0b0001_0000;
+const ATTRIBUTE_X_FLIP: u8 = 0b0010_0000;
+const ATTRIBUTE_Y_FLIP: u8 = 0b0100_0000;
+const ATTRIBUTE_PRIORITY: u8 = 0b1000_0000;
+
+// STAT interrupt sources
+const STAT_HBLANK_INTERRUPT: u8 = 0b0000_1000;
+const STAT_VBLANK_INTERRUPT: u8 = 0b0001_0000;
+const STAT_OAM_INTERRUPT: u8 = 0b0010_0000;
+const STAT_LYC_INTERRUPT: u8 = 0b0100_0000;
+
+/// The color of a pixel on the DMG, as selected by BGP, OBP0 or OBP1
+const fn dmg_shade(palette: u8, color: usize) -> usize {
+    ((palette >> (color * 2)) & 0b11) as usize
+}
@@ -21,7 +21,7 @@ impl convert::From<InterruptRegister> for u8 {
 
 impl convert::From<u8> for InterruptRegister {
     fn from(byte: u8) -> Self {
-        let v_blank = ((byte >> 0) & 0b1) != 0;
+        let v_blank = (byte & 0b1) != 0;
         let lcd_stat = ((byte >> 1) & 0b1) != 0;
         let timer = ((byte >> 2) & 0b1) != 0;
         let serial = ((byte >> 3) & 0b1) != 0;
@@ -36,3 +36,60 @@ impl convert::From<u8> for InterruptRegister {
         }
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    VBlank,
+    LcdStat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    /// Interrupts in order of priority
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LcdStat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// Address of the interrupt handler
+    pub const fn vector(&self) -> u16 {
+        match self {
+            Interrupt::VBlank => 0x40,
+            Interrupt::LcdStat => 0x48,
+            Interrupt::Timer => 0x50,
+            Interrupt::Serial => 0x58,
+            Interrupt::Joypad => 0x60,
+        }
+    }
+}
+
+impl InterruptRegister {
+    pub fn set(&mut self, interrupt: Interrupt, value: bool) {
+        match interrupt {
+            Interrupt::VBlank => self.v_blank = value,
+            Interrupt::LcdStat => self.lcd_stat = value,
+            Interrupt::Timer => self.timer = value,
+            Interrupt::Serial => self.serial = value,
+            Interrupt::Joypad => self.joypad = value,
+        }
+    }
+
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.set(interrupt, true);
+    }
+
+    pub const fn is_set(&self, interrupt: Interrupt) -> bool {
+        match interrupt {
+            Interrupt::VBlank => self.v_blank,
+            Interrupt::LcdStat => self.lcd_stat,
+            Interrupt::Timer => self.timer,
+            Interrupt::Serial => self.serial,
+            Interrupt::Joypad => self.joypad,
+        }
+    }
+}
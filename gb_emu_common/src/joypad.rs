@@ -0,0 +1,139 @@
+use std::convert;
+
+/// Buttons currently held down by the player
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Buttons {
+    pub right: bool,
+    pub left: bool,
+    pub up: bool,
+    pub down: bool,
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+/// FF00 - P1/JOYP - Joypad (R/W)
+pub struct Joypad {
+    pub buttons: Buttons,
+    select_action_buttons: bool,
+    select_direction_buttons: bool,
+}
+
+impl Buttons {
+    pub fn new() -> Buttons {
+        Buttons::default()
+    }
+
+    pub fn set(&mut self, button: Button, is_pressed: bool) {
+        match button {
+            Button::Right => self.right = is_pressed,
+            Button::Left => self.left = is_pressed,
+            Button::Up => self.up = is_pressed,
+            Button::Down => self.down = is_pressed,
+            Button::A => self.a = is_pressed,
+            Button::B => self.b = is_pressed,
+            Button::Select => self.select = is_pressed,
+            Button::Start => self.start = is_pressed,
+        }
+    }
+
+    pub const fn is_pressed(&self, button: Button) -> bool {
+        match button {
+            Button::Right => self.right,
+            Button::Left => self.left,
+            Button::Up => self.up,
+            Button::Down => self.down,
+            Button::A => self.a,
+            Button::B => self.b,
+            Button::Select => self.select,
+            Button::Start => self.start,
+        }
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            buttons: Buttons::new(),
+            select_action_buttons: false,
+            select_direction_buttons: false,
+        }
+    }
+
+    pub fn read_register(&self) -> u8 {
+        self.read_register_with(self.buttons)
+    }
+
+    /// Reads the register as if the given buttons were held, for other joypads
+    /// sharing the selection lines
+    pub fn read_register_with(&self, buttons: Buttons) -> u8 {
+        // The lower nibble is shared by both button groups, and a pressed button reads as 0
+        let buttons: u8 = buttons.into();
+        let mut pressed = 0;
+        if self.select_direction_buttons {
+            pressed |= buttons & 0x0F;
+        }
+        if self.select_action_buttons {
+            pressed |= buttons >> 4;
+        }
+
+        let select_action = if self.select_action_buttons { 0 } else { 1 };
+        let select_direction = if self.select_direction_buttons { 0 } else { 1 };
+
+        // the 2 upper bits are unused and always read as 1
+        0b1100_0000 | (select_action << 5) | (select_direction << 4) | (!pressed & 0x0F)
+    }
+
+    pub fn write_register(&mut self, value: u8) {
+        // only the selection bits can be written, and they're active low
+        self.select_action_buttons = (value & 0b0010_0000) == 0;
+        self.select_direction_buttons = (value & 0b0001_0000) == 0;
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl convert::From<Buttons> for u8 {
+    fn from(buttons: Buttons) -> u8 {
+        (buttons.right as u8)
+            | (buttons.left as u8) << 1
+            | (buttons.up as u8) << 2
+            | (buttons.down as u8) << 3
+            | (buttons.a as u8) << 4
+            | (buttons.b as u8) << 5
+            | (buttons.select as u8) << 6
+            | (buttons.start as u8) << 7
+    }
+}
+
+impl convert::From<u8> for Buttons {
+    fn from(byte: u8) -> Self {
+        Buttons {
+            right: (byte & 0b1) != 0,
+            left: ((byte >> 1) & 0b1) != 0,
+            up: ((byte >> 2) & 0b1) != 0,
+            down: ((byte >> 3) & 0b1) != 0,
+            a: ((byte >> 4) & 0b1) != 0,
+            b: ((byte >> 5) & 0b1) != 0,
+            select: ((byte >> 6) & 0b1) != 0,
+            start: ((byte >> 7) & 0b1) != 0,
+        }
+    }
+}
@@ -1,20 +1,71 @@
+pub mod apu;
+pub mod audio_recorder;
 pub mod cartridge;
+pub mod checksum;
+pub mod compatibility_palettes;
 pub mod cpu;
 pub mod cpu_registers;
+pub mod dma;
 pub mod error;
+pub mod gbs;
 pub mod gpu;
 pub mod instruction;
 pub mod interrupt;
+pub mod joypad;
+pub mod link;
 pub mod memory_bus;
+pub mod model;
+pub mod movie;
+#[cfg(not(target_family = "wasm"))]
+pub mod network_link;
+pub mod palette;
+pub mod png;
+pub mod printer;
+pub mod reset;
+pub mod rewind;
+pub mod run;
+pub mod save_state;
+pub mod serial;
+pub mod sgb;
 pub mod timer;
+pub mod vgm;
 
+use audio_recorder::AudioRecorder;
 use cartridge::create_cartridge;
+use cartridge::gbs::GbsCartridge;
+use checksum::crc32;
+use compatibility_palettes::CompatibilityPalette;
 use cpu::Cpu;
-use error::Result;
+use error::{EmulationError, Result};
+use gbs::Gbs;
+use joypad::{Button, Buttons};
+use memory_bus::{CGB_BOOT_ROM_SIZE, DMG_BOOT_ROM_SIZE};
+use model::Model;
+use movie::{Movie, MovieSession, MovieStart};
+use reset::{RamPattern, ResetKind};
+use rewind::{RewindBuffer, RewindConfig};
+use run::{RunCondition, StopReason};
+use save_state::*;
+use serial::SerialDevice;
+use std::any::Any;
+
+/// Amount of t-cycles it takes to draw a whole frame
+pub const CYCLES_PER_FRAME: u32 = 70_224;
 
 pub struct GameBoy {
     pub cpu: Cpu,
-    pub cycle: u32,
+    /// Master clock, t-cycles since power on
+    pub cycle: u64,
+    /// CRC-32 of the loaded ROM
+    pub rom_hash: u32,
+    /// Snapshots used to rewind the emulation, `None` if rewinding is disabled
+    pub rewind: Option<RewindBuffer>,
+    /// Movie being recorded or played back
+    pub movie: Option<MovieSession>,
+    /// Contents of the RAM after a power cycle
+    pub ram_pattern: RamPattern,
+    /// Model to go back to once a ROM replaces the GBS file being played
+    model_before_gbs: Option<Model>,
 }
 
 impl GameBoy {
@@ -22,38 +73,794 @@ impl GameBoy {
         GameBoy {
             cpu: Cpu::new(),
             cycle: 0,
+            rom_hash: 0,
+            rewind: None,
+            movie: None,
+            ram_pattern: RamPattern::default(),
+            model_before_gbs: None,
         }
     }
 
     pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<()> {
-        // TODO: Reset everything before loading ROM
-        self.cpu.pc = 0x0100;
-        
+        let rom_hash = crc32(&rom);
         let cartridge = create_cartridge(rom)?;
+        self.rom_hash = rom_hash;
         self.cpu.bus.cartridge = Some(Box::new(cartridge));
-        
+        if let Some(model) = self.model_before_gbs.take() {
+            self.cpu.bus.model = model;
+        }
+        self.reset(ResetKind::PowerCycle);
+
+        if let Some(rewind) = &mut self.rewind {
+            // the snapshots belong to the previous ROM
+            rewind.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Loads a GBS music file and plays one of its songs, counting from 0. The boot ROM
+    /// is skipped, and the file plays on a CGB if it needs double speed or on a DMG
+    /// otherwise, until a ROM is loaded.
+    pub fn load_gbs(&mut self, gbs: &Gbs, song: u8) -> Result<()> {
+        if song >= gbs.song_count {
+            return Err(EmulationError::InvalidGbsTrack {
+                track_count: gbs.song_count,
+            });
+        }
+
+        let rom = gbs.rom(song);
+        let rom_hash = crc32(&rom);
+        let cartridge = GbsCartridge::new(rom)?;
+        self.rom_hash = rom_hash;
+        self.cpu.bus.cartridge = Some(Box::new(cartridge));
+        let model = self.model();
+        self.model_before_gbs.get_or_insert(model);
+        self.cpu.bus.model = if gbs.is_double_speed() {
+            Model::Cgb
+        } else {
+            Model::Dmg
+        };
+
+        let boot_rom = self.cpu.bus.boot_rom.take();
+        self.reset(ResetKind::PowerCycle);
+        self.cpu.bus.boot_rom = boot_rom;
+
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+
         Ok(())
     }
 
-    pub fn step(&mut self) -> Result<()> {
+    /// Sets the boot ROM that runs before the cartridge, `None` skips it.
+    /// Takes effect on the next reset.
+    pub fn set_boot_rom(&mut self, boot_rom: Option<Vec<u8>>) -> Result<()> {
+        if let Some(boot_rom) = &boot_rom {
+            let size = boot_rom.len();
+            if size != DMG_BOOT_ROM_SIZE && size != CGB_BOOT_ROM_SIZE {
+                return Err(EmulationError::InvalidBootRom { size });
+            }
+        }
+
+        self.cpu.bus.boot_rom = boot_rom;
+        Ok(())
+    }
+
+    /// Resets the console, keeping the cartridge inserted
+    pub fn reset(&mut self, kind: ResetKind) {
+        self.reset_with_pattern(kind, self.ram_pattern);
+    }
+
+    /// Resets the console, filling the RAM with the given pattern on a power cycle
+    fn reset_with_pattern(&mut self, kind: ResetKind, ram_pattern: RamPattern) {
+        self.cpu.reset(kind, ram_pattern);
+        if self.cpu.bus.boot_rom.is_some() {
+            self.cpu.start_boot_rom();
+        } else {
+            self.cpu.apply_post_boot_state();
+        }
+        if kind == ResetKind::PowerCycle {
+            self.cycle = 0;
+        }
+    }
+
+    /// Hardware being emulated
+    pub const fn model(&self) -> Model {
+        self.cpu.bus.model
+    }
+
+    /// Changes the hardware being emulated. The registers only get the values its boot
+    /// ROM leaves behind on the next reset.
+    pub fn set_model(&mut self, model: Model) {
+        self.model_before_gbs = None;
+        self.cpu.bus.model = model;
+    }
+
+    /// Overrides the palette DMG games get on CGB, `None` lets the boot ROM pick it.
+    /// Applied right away if a DMG game is running in compatibility mode.
+    pub fn set_compatibility_palette(&mut self, palette: Option<CompatibilityPalette>) {
+        self.cpu.bus.set_compatibility_palette(palette);
+    }
+
+    /// Plugs a device into the link port, `None` unplugs it. Returns the previous one.
+    pub fn connect_serial_device(
+        &mut self,
+        device: Option<Box<dyn SerialDevice>>,
+    ) -> Option<Box<dyn SerialDevice>> {
+        std::mem::replace(&mut self.cpu.bus.serial.device, device)
+    }
+
+    /// The device plugged into the link port, if it's a `T`
+    pub fn serial_device<T: SerialDevice>(&self) -> Option<&T> {
+        let device: &dyn Any = self.cpu.bus.serial.device.as_deref()?;
+        device.downcast_ref()
+    }
+
+    pub fn serial_device_mut<T: SerialDevice>(&mut self) -> Option<&mut T> {
+        let device: &mut dyn Any = self.cpu.bus.serial.device.as_deref_mut()?;
+        device.downcast_mut()
+    }
+
+    /// Executes a single instruction, returns the amount of t-cycles it took
+    pub fn step(&mut self) -> Result<u32> {
+        let frame = self.frame_count();
         let cycles = self.cpu.step()?;
+        let cycles = self.cpu.bus.run(cycles);
+        self.cycle += cycles as u64;
+
+        if self.frame_count() != frame {
+            self.frame_done()?;
+        }
+
+        Ok(cycles)
+    }
+
+    /// Runs until the next VBlank
+    pub fn run_frame(&mut self) -> Result<()> {
+        let frame = self.frame_count();
+        while self.frame_count() == frame {
+            self.step()?;
+        }
 
-        self.cycle = self.cycle.wrapping_add(cycles);
         Ok(())
     }
 
-    pub fn has_rom_loaded(&self) -> bool {
-        match self.cpu.bus.cartridge {
-            Some(_) => true,
+    pub fn run_until(&mut self, condition: &RunCondition) -> StopReason {
+        let start_cycle = self.cycle;
+        let start_frame = self.frame_count();
+        self.cpu.bus.watchpoints = condition.watchpoints.clone();
+
+        let mut is_first_instruction = true;
+        let reason = loop {
+            // Don't check the breakpoints on the first instruction, otherwise we could
+            // never continue after hitting one
+            let pc = self.cpu.pc;
+            if !is_first_instruction && condition.breakpoints.contains(&pc) {
+                break StopReason::Breakpoint { address: pc };
+            }
+            is_first_instruction = false;
+
+            let was_stopped = self.cpu.is_stopped();
+            if let Err(err) = self.step() {
+                break StopReason::Error(err);
+            }
+
+            if let Some((address, value)) = self.cpu.bus.watchpoint_hit.take() {
+                break StopReason::Watchpoint { address, value };
+            }
+
+            if !was_stopped && self.cpu.is_stopped() {
+                break StopReason::Stop;
+            }
+
+            if condition.frame_end && self.frame_count() != start_frame {
+                break StopReason::FrameDone;
+            }
+
+            let budget_exhausted = condition
+                .cycle_budget
+                .is_some_and(|budget| self.cycle - start_cycle >= budget);
+            if budget_exhausted {
+                break StopReason::CycleBudgetExhausted;
+            }
+        };
+
+        self.cpu.bus.watchpoints.clear();
+        reason
+    }
+
+    /// The last frame drawn by the PPU, in RGB555
+    pub fn framebuffer(&self) -> &[palette::Rgb555] {
+        self.cpu.bus.gpu.framebuffer()
+    }
+
+    /// The picture a Super Game Boy sends to the TV, with the border and the colorized
+    /// screen. `None` when not running as a Super Game Boy.
+    pub fn sgb_screen(&self) -> Option<Vec<palette::Rgb555>> {
+        let sgb = self.cpu.bus.sgb.as_ref()?;
+        Some(sgb.render(self.framebuffer()))
+    }
+
+    /// Sets the buttons held on the joypad of player 2, 3 or 4 of a Super Game Boy
+    pub fn set_player_buttons(&mut self, player: usize, buttons: Buttons) {
+        if let Some(sgb) = &mut self.cpu.bus.sgb {
+            let index = player.wrapping_sub(2);
+            if let Some(player_buttons) = sgb.other_players.get_mut(index) {
+                *player_buttons = buttons;
+            }
+        }
+    }
+
+    /// Sets the amount of audio samples generated per second
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.bus.apu.set_sample_rate(sample_rate);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.cpu.bus.apu.sample_rate()
+    }
+
+    /// Records the audio from now on, replacing the current recording without finishing it.
+    /// The recording keeps the sample rate of the recorder when the one played changes.
+    pub fn start_audio_recording(&mut self, recorder: AudioRecorder) {
+        self.cpu.bus.apu.start_recording(recorder);
+    }
+
+    /// Ends the audio recording, if there's one
+    pub fn stop_audio_recording(&mut self) -> std::io::Result<()> {
+        match self.cpu.bus.apu.stop_recording() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording_audio(&self) -> bool {
+        self.cpu.bus.apu.is_recording()
+    }
+
+    /// Logs the writes to the sound registers from now on, to save them as a VGM file
+    pub fn start_vgm_logging(&mut self) {
+        self.cpu.bus.apu.start_vgm_logging();
+    }
+
+    /// Ends the VGM log, returning the VGM file
+    pub fn stop_vgm_logging(&mut self) -> Option<Vec<u8>> {
+        let logger = self.cpu.bus.apu.vgm_logger.take()?;
+        Some(logger.finish())
+    }
+
+    pub fn is_logging_vgm(&self) -> bool {
+        self.cpu.bus.apu.vgm_logger.is_some()
+    }
+
+    /// Takes the audio generated since the last call, as interleaved left and right
+    /// samples from -1 to 1
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.bus.apu.take_samples()
+    }
+
+    /// Amount of frames drawn since power on
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.bus.gpu.frame_count()
+    }
+
+    fn frame_done(&mut self) -> Result<()> {
+        let bus = &mut self.cpu.bus;
+        if let Some(sgb) = &mut bus.sgb {
+            sgb.frame_done(bus.gpu.framebuffer());
+        }
+
+        if let Some(movie) = &mut self.movie {
+            movie.frame_done(self.cpu.bus.joypad.buttons);
+            if let Some(buttons) = movie.current_buttons() {
+                self.cpu.bus.set_buttons(buttons);
+            }
+        }
+
+        let should_capture = match &mut self.rewind {
+            Some(rewind) => rewind.frame_done(),
             None => false,
+        };
+
+        if should_capture {
+            let state = self.save_state()?;
+            if let Some(rewind) = &mut self.rewind {
+                rewind.push(state);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_button(&mut self, button: Button, is_pressed: bool) {
+        let mut buttons = self.cpu.bus.joypad.buttons;
+        buttons.set(button, is_pressed);
+        self.set_buttons(buttons);
+    }
+
+    /// Sets the buttons held by the player. This is ignored while a movie is playing.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        let is_playing_movie = self.movie.as_ref().is_some_and(|movie| movie.is_playing());
+        if !is_playing_movie {
+            self.cpu.bus.set_buttons(buttons);
+        }
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.cpu.bus.joypad.buttons
+    }
+
+    /// Starts recording the inputs on every frame into a movie
+    pub fn record_movie(&mut self, start: MovieStart, rtc_seed: u64) -> Result<()> {
+        let movie = Movie::new(self.rom_hash, start, self.ram_pattern, rtc_seed);
+        self.apply_movie_start(&movie)?;
+        self.movie = Some(MovieSession::recording(movie));
+
+        Ok(())
+    }
+
+    pub fn play_movie(&mut self, movie: Movie) -> Result<()> {
+        if movie.rom_hash != self.rom_hash {
+            return Err(EmulationError::MovieRomMismatch);
+        }
+
+        self.apply_movie_start(&movie)?;
+        let session = MovieSession::playing(movie);
+        self.cpu.bus.joypad.buttons = session.current_buttons().unwrap_or_default();
+        self.movie = Some(session);
+
+        Ok(())
+    }
+
+    /// Stops the current movie and returns it
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|session| session.movie)
+    }
+
+    fn apply_movie_start(&mut self, movie: &Movie) -> Result<()> {
+        if !self.has_rom_loaded() {
+            return Err(EmulationError::NoRom);
+        }
+
+        match &movie.start {
+            MovieStart::PowerOn => {
+                // the user's pattern stays for the power cycles after the movie
+                self.reset_with_pattern(ResetKind::PowerCycle, movie.ram_pattern);
+                if let Some(cartridge) = &mut self.cpu.bus.cartridge {
+                    cartridge.set_rtc_time(movie.rtc_seed);
+                }
+            }
+            // the clock is part of the state
+            MovieStart::SaveState(state) => self.load_state(state)?,
+        }
+
+        // old snapshots would break the movie
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+
+        Ok(())
+    }
+
+    pub fn enable_rewind(&mut self, config: RewindConfig) {
+        self.rewind = Some(RewindBuffer::new(config));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Goes back to the most recent snapshot in the rewind buffer.
+    /// Returns false if there's nothing left to rewind or a movie is running.
+    pub fn rewind(&mut self) -> Result<bool> {
+        // the movie would keep counting frames forward and desync
+        let state = match &mut self.rewind {
+            Some(rewind) if self.movie.is_none() => rewind.pop()?,
+            _ => None,
+        };
+
+        match state {
+            Some(state) => {
+                self.load_state(&state)?;
+                Ok(true)
+            }
+            None => Ok(false),
         }
     }
+
+    pub fn has_rom_loaded(&self) -> bool {
+        self.cpu.bus.cartridge.is_some()
+    }
+
+    /// Creates a snapshot of the whole emulator
+    pub fn save_state(&self) -> Result<Vec<u8>> {
+        let payload = self.save_payload()?;
+        Ok(encode_save_state(self.rom_hash, &payload))
+    }
+
+    fn save_payload(&self) -> Result<Vec<u8>> {
+        let mut writer = StateWriter::new();
+        writer.write_u8(self.cpu.bus.model.to_u8());
+        writer.write_u64(self.cycle);
+        self.cpu.save_state(&mut writer);
+        self.cpu.bus.save_state(&mut writer)?;
+        Ok(writer.into_bytes())
+    }
+
+    /// Restores a snapshot created by `save_state`. States created by another version of
+    /// the format or with another ROM are rejected, and leave the emulator as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        if !self.has_rom_loaded() {
+            return Err(EmulationError::NoRom);
+        }
+
+        let (header, payload) = decode_save_state(data)?;
+        if header.rom_hash != self.rom_hash {
+            return Err(EmulationError::SaveStateRomMismatch);
+        }
+
+        // the parts are loaded one after the other, so an error halfway has to be undone
+        let backup = self.save_payload()?;
+        let result = self.load_payload(payload);
+        if result.is_err() {
+            self.load_payload(&backup)?;
+        }
+        result
+    }
+
+    fn load_payload(&mut self, payload: &[u8]) -> Result<()> {
+        let mut reader = StateReader::new(payload);
+        self.cpu.bus.model = Model::from_u8(reader.read_u8()?)?;
+        self.cycle = reader.read_u64()?;
+        self.cpu.load_state(&mut reader)?;
+        self.cpu.bus.load_state(&mut reader)?;
+
+        if !reader.is_empty() {
+            return Err(EmulationError::InvalidSaveState);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for GameBoy {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::cartridge::ROM_BANK_SIZE;
+    use crate::serial::SerialCapture;
+
+    // a ROM-only cartridge filled with NOPs
+    fn test_rom() -> Vec<u8> {
+        vec![0; ROM_BANK_SIZE * 2]
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(test_rom()).unwrap();
+        gb.cpu.registers.a = 0x12;
+        gb.cpu.bus.write_byte(0xC123, 0x45).unwrap();
+        let state = gb.save_state().unwrap();
+
+        gb.step().unwrap();
+        gb.cpu.registers.a = 0;
+        gb.cpu.bus.write_byte(0xC123, 0).unwrap();
+        gb.load_state(&state).unwrap();
+
+        assert_eq!(gb.cpu.pc, 0x0100);
+        assert_eq!(gb.cpu.registers.a, 0x12);
+        assert_eq!(gb.cpu.bus.read_byte(0xC123).unwrap(), 0x45);
+    }
+
+    #[test]
+    fn rejected_save_state_leaves_the_emulator_alone() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(test_rom()).unwrap();
+        let state = gb.save_state().unwrap();
+        let (_, payload) = decode_save_state(&state).unwrap();
+        // trailing bytes are only noticed once everything else is loaded
+        let mut payload = payload.to_vec();
+        payload.push(0);
+        let state = encode_save_state(gb.rom_hash, &payload);
+
+        gb.step().unwrap();
+        gb.cpu.registers.a = 0x12;
+        gb.cpu.bus.write_byte(0xC123, 0x45).unwrap();
+        let cycle = gb.cycle;
+        assert!(matches!(
+            gb.load_state(&state),
+            Err(EmulationError::InvalidSaveState)
+        ));
+
+        assert_eq!(gb.cycle, cycle);
+        assert_eq!(gb.cpu.pc, 0x0101);
+        assert_eq!(gb.cpu.registers.a, 0x12);
+        assert_eq!(gb.cpu.bus.read_byte(0xC123).unwrap(), 0x45);
+    }
+
+    #[test]
+    fn no_rewinding_during_movies() {
+        let mut rom = test_rom();
+        // JR -2
+        rom[0x0100] = 0x18;
+        rom[0x0101] = 0xFE;
+        let mut gb = GameBoy::new();
+        gb.enable_rewind(RewindConfig::new());
+        gb.load_rom(rom).unwrap();
+        gb.record_movie(MovieStart::PowerOn, 0).unwrap();
+        for _ in 0..10 {
+            gb.run_frame().unwrap();
+        }
+        assert!(!gb.rewind().unwrap());
+
+        gb.stop_movie();
+        gb.run_frame().unwrap();
+        assert!(gb.rewind().unwrap());
+    }
+
+    #[test]
+    fn save_state_rejects_other_rom() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(test_rom()).unwrap();
+        let state = gb.save_state().unwrap();
+
+        let mut other_rom = test_rom();
+        other_rom[0x0150] = 0x01;
+        gb.load_rom(other_rom).unwrap();
+
+        assert!(matches!(
+            gb.load_state(&state),
+            Err(EmulationError::SaveStateRomMismatch)
+        ));
+    }
+
+    #[test]
+    fn run_until_stops_at_breakpoint_and_frame_end() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(test_rom()).unwrap();
+
+        let mut condition = RunCondition::new();
+        condition.breakpoints.push(0x0110);
+        let reason = gb.run_until(&condition);
+        assert!(matches!(reason, StopReason::Breakpoint { address: 0x0110 }));
+        assert_eq!(gb.cycle, 16 * 4);
+
+        let reason = gb.run_until(&RunCondition::frame_end());
+        assert!(matches!(reason, StopReason::FrameDone));
+        assert_eq!(gb.frame_count(), 1);
+
+        let reason = gb.run_until(&RunCondition::cycles(100));
+        assert!(matches!(reason, StopReason::CycleBudgetExhausted));
+    }
+
+    #[test]
+    fn soft_reset_keeps_ram() {
+        let mut gb = GameBoy::new();
+        gb.ram_pattern = RamPattern::Ones;
+        gb.load_rom(test_rom()).unwrap();
+        assert_eq!(gb.cpu.bus.read_byte(0xC000).unwrap(), 0xFF);
+
+        gb.cpu.bus.write_byte(0xC000, 0x12).unwrap();
+        gb.cpu.pc = 0x1234;
+        gb.reset(ResetKind::Soft);
+        assert_eq!(gb.cpu.pc, 0x0100);
+        assert_eq!(gb.cpu.bus.read_byte(0xC000).unwrap(), 0x12);
+
+        gb.reset(ResetKind::PowerCycle);
+        assert_eq!(gb.cpu.bus.read_byte(0xC000).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn movies_power_on_with_their_ram_pattern() {
+        let mut gb = GameBoy::new();
+        gb.ram_pattern = RamPattern::Ones;
+        gb.load_rom(test_rom()).unwrap();
+        gb.record_movie(MovieStart::PowerOn, 0).unwrap();
+        let movie = gb.stop_movie().unwrap();
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.ram_pattern, RamPattern::Ones);
+
+        gb.ram_pattern = RamPattern::Zeros;
+        gb.reset(ResetKind::PowerCycle);
+        gb.play_movie(movie).unwrap();
+        assert_eq!(gb.cpu.bus.read_byte(0xC000).unwrap(), 0xFF);
+        assert_eq!(gb.ram_pattern, RamPattern::Zeros);
+    }
+
+    #[test]
+    fn boot_rom_is_unmapped_by_ff50() {
+        let mut boot_rom = vec![0; DMG_BOOT_ROM_SIZE];
+        // LD A, $01; LDH ($50), A
+        boot_rom[..4].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+
+        let mut gb = GameBoy::new();
+        gb.set_boot_rom(Some(boot_rom)).unwrap();
+        gb.load_rom(test_rom()).unwrap();
+        assert_eq!(gb.cpu.pc, 0x0000);
+        assert_eq!(gb.cpu.bus.read_byte(0x0000).unwrap(), 0x3E);
+
+        gb.step().unwrap();
+        gb.step().unwrap();
+        assert!(!gb.cpu.bus.is_boot_rom_mapped());
+        assert_eq!(gb.cpu.bus.read_byte(0x0000).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn state_saved_in_the_boot_rom_needs_it() {
+        let mut gb = GameBoy::new();
+        gb.set_boot_rom(Some(vec![0; DMG_BOOT_ROM_SIZE])).unwrap();
+        gb.load_rom(test_rom()).unwrap();
+        let state = gb.save_state().unwrap();
+
+        gb.set_boot_rom(None).unwrap();
+        gb.load_rom(test_rom()).unwrap();
+        gb.cpu.bus.write_byte(0xC123, 0x45).unwrap();
+        assert!(matches!(
+            gb.load_state(&state),
+            Err(EmulationError::SaveStateNeedsBootRom)
+        ));
+        assert_eq!(gb.cpu.pc, 0x0100);
+        assert!(!gb.cpu.bus.is_boot_rom_mapped());
+        assert_eq!(gb.cpu.bus.read_byte(0xC123).unwrap(), 0x45);
+    }
+
+    #[test]
+    fn cgb_banking_and_speed_switch() {
+        let mut rom = test_rom();
+        rom[0x0143] = 0x80;
+        // STOP
+        rom[0x0100] = 0x10;
+
+        let mut gb = GameBoy::new();
+        gb.set_model(Model::Cgb);
+        gb.load_rom(rom).unwrap();
+        assert!(gb.cpu.bus.is_cgb_mode());
+
+        let bus = &mut gb.cpu.bus;
+        bus.write_byte(0xD000, 0x11).unwrap();
+        bus.write_byte(0xFF70, 0x02).unwrap();
+        bus.write_byte(0xD000, 0x22).unwrap();
+        assert_eq!(bus.read_byte(0xFF70).unwrap(), 0xFA);
+        bus.write_byte(0xFF70, 0x00).unwrap();
+        assert_eq!(bus.read_byte(0xD000).unwrap(), 0x11);
+
+        bus.write_byte(0xFF4D, 0x01).unwrap();
+        gb.step().unwrap();
+        assert!(gb.cpu.bus.is_double_speed());
+        assert!(!gb.cpu.is_stopped());
+        assert_eq!(gb.cpu.bus.read_byte(0xFF4D).unwrap(), 0xFE);
+    }
+
+    #[test]
+    fn renders_background_with_cgb_palettes() {
+        let mut rom = test_rom();
+        rom[0x0143] = 0x80;
+
+        let mut gb = GameBoy::new();
+        gb.set_model(Model::Cgb);
+        gb.load_rom(rom).unwrap();
+
+        let bus = &mut gb.cpu.bus;
+        // every pixel of tile 0 uses color 3
+        for address in 0x8000..0x8010 {
+            bus.write_byte(address, 0xFF).unwrap();
+        }
+        // the whole background uses palette 2
+        bus.write_byte(0xFF4F, 1).unwrap();
+        for address in 0x9800..0x9C00 {
+            bus.write_byte(address, 0x02).unwrap();
+        }
+        // color 3 of palette 2 is pure red
+        bus.write_byte(0xFF68, 0x80 | (2 * 8 + 3 * 2)).unwrap();
+        bus.write_byte(0xFF69, 0x1F).unwrap();
+        bus.write_byte(0xFF69, 0x00).unwrap();
+
+        gb.run_frame().unwrap();
+        gb.run_frame().unwrap();
+
+        assert!(gb.framebuffer().iter().all(|&color| color == 0x001F));
+    }
+
+    #[test]
+    fn hdma_transfers() {
+        let mut rom = test_rom();
+        rom[0x0143] = 0x80;
+
+        let mut gb = GameBoy::new();
+        gb.set_model(Model::Cgb);
+        gb.load_rom(rom).unwrap();
+
+        let bus = &mut gb.cpu.bus;
+        for offset in 0..0x40 {
+            bus.write_byte(0xC000 + offset, offset as u8 + 1).unwrap();
+        }
+        bus.write_byte(0xFF51, 0xC0).unwrap();
+        bus.write_byte(0xFF52, 0x00).unwrap();
+        bus.write_byte(0xFF53, 0x00).unwrap();
+        bus.write_byte(0xFF54, 0x00).unwrap();
+
+        // general purpose DMA of 2 blocks
+        bus.write_byte(0xFF55, 0x01).unwrap();
+        assert_eq!(bus.read_byte(0x801F).unwrap(), 0x20);
+        assert_eq!(bus.read_byte(0xFF55).unwrap(), 0xFF);
+        assert_eq!(gb.step().unwrap(), 64);
+
+        // HBlank DMA of the 2 next blocks, one per line
+        let bus = &mut gb.cpu.bus;
+        bus.write_byte(0xFF55, 0x81).unwrap();
+        assert_eq!(bus.read_byte(0xFF55).unwrap(), 0x01);
+        while gb.cpu.bus.read_byte(0xFF55).unwrap() == 0x01 {
+            gb.step().unwrap();
+        }
+        assert_eq!(gb.cpu.bus.read_byte(0xFF55).unwrap(), 0x00);
+        assert_eq!(gb.cpu.bus.read_byte(0x802F).unwrap(), 0x30);
+        assert_eq!(gb.cpu.bus.read_byte(0x8030).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn oam_dma_transfers() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(test_rom()).unwrap();
+
+        let bus = &mut gb.cpu.bus;
+        for offset in 0..0xA0 {
+            bus.write_byte(0xC000 + offset, offset as u8 + 1).unwrap();
+        }
+        bus.write_byte(0xFF80, 0x42).unwrap();
+        bus.write_byte(0xFF46, 0xC0).unwrap();
+        assert_eq!(bus.read_byte(0xFF46).unwrap(), 0xC0);
+
+        // setup M-cycle, then the first byte
+        bus.run(8);
+        assert_eq!(bus.read_byte(0xFE00).unwrap(), 0xFF);
+        assert_eq!(bus.read_byte(0x0000).unwrap(), 0x01);
+        assert_eq!(bus.read_byte(0xFF80).unwrap(), 0x42);
+
+        // restarting keeps the old transfer going during the setup of the new one
+        bus.write_byte(0xFF46, 0xC0).unwrap();
+        bus.run(4);
+        assert_eq!(bus.read_byte(0x0000).unwrap(), 0x02);
+        bus.run(4 * 159);
+        assert!(bus.oam_dma.is_active());
+        bus.run(4);
+        assert!(!bus.oam_dma.is_active());
+        assert_eq!(bus.read_byte(0xFE00).unwrap(), 0x01);
+        assert_eq!(bus.read_byte(0xFE9F).unwrap(), 0xA0);
+        assert_eq!(bus.read_byte(0x0000).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn serial_transfer_goes_to_the_device() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(test_rom()).unwrap();
+        gb.connect_serial_device(Some(Box::new(SerialCapture::new())));
+
+        let bus = &mut gb.cpu.bus;
+        bus.write_byte(0xFF01, b'A').unwrap();
+        bus.write_byte(0xFF02, 0x81).unwrap();
+        assert_eq!(bus.read_byte(0xFF02).unwrap(), 0xFF);
+
+        // 8 bits at 8192 Hz
+        bus.run(4092);
+        assert_eq!(bus.read_byte(0xFF02).unwrap(), 0xFF);
+        bus.run(4);
+        assert_eq!(bus.read_byte(0xFF02).unwrap(), 0x7F);
+        assert_eq!(bus.read_byte(0xFF01).unwrap(), 0xFF);
+        assert_eq!(bus.read_byte(0xFF0F).unwrap() & 0x08, 0x08);
+
+        let capture = gb.serial_device_mut::<SerialCapture>().unwrap();
+        assert_eq!(capture.take_output(), b"A");
+    }
 }
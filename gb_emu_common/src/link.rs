@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error::Result;
+use crate::serial::SerialDevice;
+use crate::GameBoy;
+
+/// Two Game Boys connected with a link cable, running in lockstep
+pub struct LinkedPair {
+    pub first: GameBoy,
+    pub second: GameBoy,
+    cable: LinkCable,
+}
+
+impl LinkedPair {
+    pub fn new(mut first: GameBoy, mut second: GameBoy) -> LinkedPair {
+        let cable = LinkCable::connect(&mut first, &mut second);
+        LinkedPair {
+            first,
+            second,
+            cable,
+        }
+    }
+
+    /// Executes an instruction on the Game Boy that's behind
+    pub fn step(&mut self) -> Result<()> {
+        self.cable.step(&mut self.first, &mut self.second)
+    }
+
+    /// Runs until both Game Boys finished a frame
+    pub fn run_frame(&mut self) -> Result<()> {
+        self.cable.run_frame(&mut self.first, &mut self.second)
+    }
+
+    /// Unplugs the cable and gives back both Game Boys
+    pub fn into_inner(mut self) -> (GameBoy, GameBoy) {
+        LinkCable::disconnect(&mut self.first, &mut self.second);
+        (self.first, self.second)
+    }
+}
+
+/// Link cable between two Game Boys owned by someone else. Both have to be stepped
+/// through the cable so neither gets ahead of the other by more than an instruction.
+pub struct LinkCable {
+    wire: Rc<RefCell<Wire>>,
+}
+
+impl LinkCable {
+    pub fn connect(first: &mut GameBoy, second: &mut GameBoy) -> LinkCable {
+        let wire = Wire {
+            ends: [End::read(first), End::read(second)],
+            pending: [None; 2],
+        };
+        let wire = Rc::new(RefCell::new(wire));
+        for (side, gb) in [first, second].into_iter().enumerate() {
+            let port = LinkPort {
+                wire: wire.clone(),
+                side,
+            };
+            gb.connect_serial_device(Some(Box::new(port)));
+        }
+
+        LinkCable { wire }
+    }
+
+    pub fn disconnect(first: &mut GameBoy, second: &mut GameBoy) {
+        first.connect_serial_device(None);
+        second.connect_serial_device(None);
+    }
+
+    pub fn step(&self, first: &mut GameBoy, second: &mut GameBoy) -> Result<()> {
+        // the master clock counts normal speed cycles, so it works across speeds
+        let (side, gb) = if first.cycle <= second.cycle {
+            (0, first)
+        } else {
+            (1, second)
+        };
+
+        gb.step()?;
+        self.wire.borrow_mut().ends[side] = End::read(gb);
+        Ok(())
+    }
+
+    pub fn run_frame(&self, first: &mut GameBoy, second: &mut GameBoy) -> Result<()> {
+        let first_frame = first.frame_count();
+        let second_frame = second.frame_count();
+        while first.frame_count() == first_frame || second.frame_count() == second_frame {
+            self.step(first, second)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What each Game Boy last showed on its end of the cable
+struct Wire {
+    ends: [End; 2],
+    /// Bytes clocked in by the other side, waiting to complete an external clock transfer
+    pending: [Option<u8>; 2],
+}
+
+#[derive(Clone, Copy, Default)]
+struct End {
+    data: u8,
+    is_waiting_external_clock: bool,
+}
+
+impl End {
+    fn read(gb: &GameBoy) -> End {
+        let serial = &gb.cpu.bus.serial;
+        End {
+            data: serial.data,
+            is_waiting_external_clock: serial.is_waiting_external_clock(),
+        }
+    }
+}
+
+/// One of the plugs of the cable
+struct LinkPort {
+    wire: Rc<RefCell<Wire>>,
+    side: usize,
+}
+
+impl SerialDevice for LinkPort {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        if !wire.ends[other].is_waiting_external_clock {
+            // nobody is listening, the line stays high
+            return 0xFF;
+        }
+
+        wire.ends[other].is_waiting_external_clock = false;
+        wire.pending[other] = Some(sent);
+        wire.ends[other].data
+    }
+
+    fn run(&mut self, _cycles: u32, waiting: Option<u8>) -> Option<u8> {
+        // the byte this side answers with was already taken by the other side
+        waiting?;
+        self.wire.borrow_mut().pending[self.side].take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::ROM_BANK_SIZE;
+
+    fn game_boy() -> GameBoy {
+        let mut gb = GameBoy::new();
+        gb.load_rom(vec![0; ROM_BANK_SIZE * 2]).unwrap();
+        gb
+    }
+
+    #[test]
+    fn exchanges_bytes() {
+        let mut pair = LinkedPair::new(game_boy(), game_boy());
+        pair.second.cpu.bus.write_byte(0xFF01, 0x22).unwrap();
+        pair.second.cpu.bus.write_byte(0xFF02, 0x80).unwrap();
+        pair.first.cpu.bus.write_byte(0xFF01, 0x11).unwrap();
+        pair.first.cpu.bus.write_byte(0xFF02, 0x81).unwrap();
+
+        pair.run_frame().unwrap();
+        assert_eq!(pair.first.cpu.bus.read_byte(0xFF01).unwrap(), 0x22);
+        assert_eq!(pair.second.cpu.bus.read_byte(0xFF01).unwrap(), 0x11);
+        assert_eq!(pair.second.cpu.bus.read_byte(0xFF02).unwrap(), 0x7E);
+        assert!(pair.first.cycle.abs_diff(pair.second.cycle) < 32);
+    }
+}
@@ -1,16 +1,57 @@
+use crate::apu::*;
 use crate::cartridge::*;
+use crate::compatibility_palettes::CompatibilityPalette;
+use crate::dma::*;
 use crate::error::{EmulationError, Result};
 use crate::gpu::*;
+use crate::interrupt::Interrupt;
+use crate::joypad::{Buttons, Joypad};
+use crate::model::Model;
+use crate::palette::DMG_SHADES;
+use crate::reset::{RamPattern, ResetKind};
+use crate::save_state::{StateReader, StateWriter};
+use crate::serial::*;
+use crate::sgb::Sgb;
 use crate::timer::Timers;
 
 pub struct MemoryBus {
     pub gpu: Gpu,
     pub cartridge: Option<Box<dyn Cartridge>>,
+    pub joypad: Joypad,
+    /// Boot ROM overlaid on top of the cartridge until FF50 is written to
+    pub boot_rom: Option<Vec<u8>>,
+    is_boot_rom_mapped: bool,
+    /// Hardware being emulated, see `GameBoy::set_model`
+    pub model: Model,
+    /// CGB features are enabled, false when running DMG games on a CGB
+    cgb_mode: bool,
     work_ram_0: [u8; WORK_RAM_0_SIZE],
-    work_ram_1: [u8; WORK_RAM_N_SIZE],
+    /// Only the first bank is used outside of CGB mode
+    work_ram_n: [[u8; WORK_RAM_N_SIZE]; WORK_RAM_N_BANK_AMOUNT],
+    /// FF70 - SVBK - WRAM bank mapped to D000-DFFF, between 1 and 7
+    work_ram_bank: usize,
+    /// Bit 7 of FF4D - KEY1 - The CPU runs at twice the speed
+    is_double_speed: bool,
+    /// Bit 0 of FF4D - KEY1 - The next STOP instruction switches the speed
+    is_speed_switch_prepared: bool,
+    /// FF72-FF75 - Undocumented CGB registers without any known purpose
+    undocumented_registers: [u8; 4],
+    pub hdma: Hdma,
+    pub oam_dma: OamDma,
+    /// Palette to use for DMG games on CGB instead of the one the boot ROM would pick
+    pub compatibility_palette: Option<CompatibilityPalette>,
+    /// T-cycles the CPU has to wait for a DMA transfer
+    stall_cycles: u32,
     high_ram: [u8; HIGH_RAM_SIZE],
     timers: Timers,
-    sb: u8, // FF01 - SB - Serial transfer data (R/W)
+    pub serial: Serial,
+    pub apu: Apu,
+    /// Super Game Boy functions, when running as one
+    pub sgb: Option<Sgb>,
+    /// Addresses that should be reported when written to
+    pub watchpoints: Vec<u16>,
+    /// The last write to one of the watchpoints
+    pub watchpoint_hit: Option<(u16, u8)>,
 }
 
 impl MemoryBus {
@@ -20,19 +61,62 @@ impl MemoryBus {
         MemoryBus {
             gpu: Gpu::new(),
             cartridge: None,
+            joypad: Joypad::new(),
+            boot_rom: None,
+            is_boot_rom_mapped: false,
+            model: Model::default(),
+            cgb_mode: false,
             work_ram_0: [0; WORK_RAM_0_SIZE],
-            work_ram_1: [0; WORK_RAM_N_SIZE],
+            work_ram_n: [[0; WORK_RAM_N_SIZE]; WORK_RAM_N_BANK_AMOUNT],
+            work_ram_bank: 1,
+            is_double_speed: false,
+            is_speed_switch_prepared: false,
+            undocumented_registers: [0; 4],
+            hdma: Hdma::new(),
+            oam_dma: OamDma::new(),
+            compatibility_palette: None,
+            stall_cycles: 0,
             high_ram: [0; HIGH_RAM_SIZE],
             timers: Timers::new(),
-            sb: 0,
+            serial: Serial::new(),
+            apu: Apu::new(),
+            sgb: None,
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
         }
     }
 
     pub fn read_byte(&self, address: u16) -> Result<u8> {
+        if self.oam_dma.is_active() {
+            if let Some(value) = self.read_during_oam_dma(address as usize) {
+                return Ok(value);
+            }
+        }
+
+        self.read_byte_unrestricted(address)
+    }
+
+    /// What the CPU reads while the OAM DMA owns the bus, `None` for the addresses it
+    /// can still reach
+    fn read_during_oam_dma(&self, address: usize) -> Option<u8> {
+        match address {
+            OAM_BEGIN..=OAM_END => Some(0xFF),
+            IO_REGISTERS_START..=INTERRUPT_ENABLE_REGISTER => None,
+            // the CPU gets whatever the DMA is reading
+            _ => Some(self.oam_dma.last_byte()),
+        }
+    }
+
+    /// Reads a byte ignoring the restrictions of the OAM DMA
+    fn read_byte_unrestricted(&self, address: u16) -> Result<u8> {
         let address = address as usize;
         // Return an error if we don't have a ROM loaded
         let cartridge = self.cartridge.as_ref().ok_or(EmulationError::NoRom)?;
 
+        if let Some(value) = self.read_boot_rom(address) {
+            return Ok(value);
+        }
+
         match address {
             ROM_BANK_0_START..=ROM_BANK_N_END => cartridge.read_byte_rom(address),
 
@@ -47,7 +131,7 @@ impl MemoryBus {
 
             WORK_RAM_N_START..=WORK_RAM_N_END => {
                 let pos = address - WORK_RAM_N_START;
-                Ok(self.work_ram_1[pos])
+                Ok(self.work_ram_n[self.work_ram_bank - 1][pos])
             }
 
             ECHO_RAM_START..=ECHO_RAM_END => {
@@ -71,14 +155,27 @@ impl MemoryBus {
 
             DIVIDER_REGISTER => Ok(self.timers.divider_register),
 
+            TIMER_COUNTER_REGISTER => Ok(self.timers.timer_counter),
+
             TIMER_MODULO_REGISTER => Ok(self.timers.timer_modulo),
 
-            0xFF44 => Ok(self.gpu.ly),
+            TIMER_CONTROL_REGISTER => {
+                // the unused bits always read as 1
+                let register: u8 = self.timers.timer_control.into();
+                Ok(0b1111_1000 | register)
+            }
 
             IO_REGISTERS_START..=IO_REGISTERS_END => {
                 // TODO: Implement I/O registers
                 match address {
-                    0xFF01 => Ok(self.sb),
+                    JOYPAD_REGISTER => Ok(self.read_joypad()),
+                    BOOT_ROM_REGISTER => Ok(0xFF),
+                    CGB_REGISTERS_START..=CGB_REGISTERS_END => Ok(self.read_cgb_register(address)),
+                    SERIAL_DATA_REGISTER => Ok(self.serial.data),
+                    SERIAL_CONTROL_REGISTER => Ok(self.serial.read_control(self.cgb_mode)),
+                    OAM_DMA_REGISTER => Ok(self.oam_dma.register),
+                    REGISTERS_START..=WAVE_RAM_END => Ok(self.apu.read_register(address)),
+                    LCD_REGISTERS_START..=LCD_REGISTERS_END => self.gpu.read_register(address),
                     _ => Ok(0),
                 }
             }
@@ -96,18 +193,16 @@ impl MemoryBus {
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) -> Result<()> {
-        if cfg!(not(target_family = "wasm")) {
-            if address == 0xFF02 && value == 0x81 {
-                let char = self.read_byte(0xFF01)?;
-                let raw_text = vec![char];
-                match String::from_utf8(raw_text) {
-                    Ok(text) => print!("{text}"),
-                    Err(_) => print!("<?>"),
-                }
-            }
+        if self.watchpoints.contains(&address) {
+            self.watchpoint_hit = Some((address, value));
         }
 
         let address = address as usize;
+        // only HRAM and the IO registers can be written while the OAM DMA runs
+        if self.oam_dma.is_active() && address < IO_REGISTERS_START {
+            return Ok(());
+        }
+
         // Return an error if we don't have a ROM loaded
         let cartridge = self.cartridge.as_mut().ok_or(EmulationError::NoRom)?;
 
@@ -128,7 +223,7 @@ impl MemoryBus {
 
             WORK_RAM_N_START..=WORK_RAM_N_END => {
                 let pos = address - WORK_RAM_N_START;
-                self.work_ram_1[pos] = value;
+                self.work_ram_n[self.work_ram_bank - 1][pos] = value;
                 Ok(())
             }
 
@@ -141,6 +236,27 @@ impl MemoryBus {
 
             0xFEA0..=0xFEFF => Ok(()),
 
+            INTERRUPT_ENABLE_REGISTER | IO_REGISTERS_START..=IO_REGISTERS_END => {
+                self.write_io_register(address, value)
+            }
+
+            HIGH_RAM_START..=HIGH_RAM_END => {
+                let pos = address - HIGH_RAM_START;
+                self.high_ram[pos] = value;
+
+                Ok(())
+            }
+
+            _ => {
+                let error = EmulationError::InvalidMemoryWrite { address, value };
+                Err(error)
+            }
+        }
+    }
+
+    /// Writes to IE or one of the IO registers
+    fn write_io_register(&mut self, address: usize, value: u8) -> Result<()> {
+        match address {
             INTERRUPT_ENABLE_REGISTER => {
                 self.timers.interrupt_enable_register = value.into();
 
@@ -159,38 +275,528 @@ impl MemoryBus {
                 Ok(())
             }
 
+            TIMER_COUNTER_REGISTER => {
+                self.timers.timer_counter = value;
+                Ok(())
+            }
+
             TIMER_MODULO_REGISTER => {
                 self.timers.timer_modulo = value;
                 Ok(())
             }
 
+            TIMER_CONTROL_REGISTER => {
+                self.timers.timer_control = value.into();
+                Ok(())
+            }
+
             IO_REGISTERS_START..=IO_REGISTERS_END => {
                 // TODO: Implement I/O registers
                 match address {
-                    0xFF01 => {
-                        self.sb = value;
+                    JOYPAD_REGISTER => {
+                        self.joypad.write_register(value);
+                        if let Some(sgb) = &mut self.sgb {
+                            sgb.write_joypad(value);
+                        }
+                        Ok(())
+                    }
+                    BOOT_ROM_REGISTER => {
+                        // once unmapped, the boot ROM stays unmapped until the next reset
+                        if value != 0 {
+                            self.is_boot_rom_mapped = false;
+                        }
+                        Ok(())
+                    }
+                    CGB_REGISTERS_START..=CGB_REGISTERS_END => {
+                        self.write_cgb_register(address, value);
+                        Ok(())
+                    }
+                    SERIAL_DATA_REGISTER => {
+                        self.serial.data = value;
                         Ok(())
-                    },
+                    }
+                    SERIAL_CONTROL_REGISTER => {
+                        self.serial.write_control(value, self.cgb_mode);
+                        Ok(())
+                    }
+                    OAM_DMA_REGISTER => {
+                        self.oam_dma.write_register(value);
+                        Ok(())
+                    }
+                    REGISTERS_START..=WAVE_RAM_END => {
+                        self.apu.write_register(address, value);
+                        Ok(())
+                    }
+                    LCD_REGISTERS_START..=LCD_REGISTERS_END => {
+                        self.gpu.write_register(address, value)
+                    }
                     _ => Ok(()),
                 }
             }
 
-            HIGH_RAM_START..=HIGH_RAM_END => {
-                let pos = address - HIGH_RAM_START;
-                self.high_ram[pos] = value;
+            _ => Err(EmulationError::InvalidMemoryWrite { address, value }),
+        }
+    }
 
-                Ok(())
+    /// Puts every register back to its initial value. The cartridge stays inserted
+    /// and the buttons stay held.
+    pub fn reset(&mut self, kind: ResetKind, pattern: RamPattern) {
+        self.gpu.reset(kind, pattern);
+        self.timers = Timers::new();
+        let buttons = self.joypad.buttons;
+        self.joypad = Joypad::new();
+        self.joypad.buttons = buttons;
+        self.serial.reset();
+        self.apu.reset();
+        self.is_boot_rom_mapped = false;
+        self.work_ram_bank = 1;
+        self.is_double_speed = false;
+        self.is_speed_switch_prepared = false;
+        self.undocumented_registers = [0; 4];
+        self.hdma = Hdma::new();
+        self.oam_dma = OamDma::new();
+        self.stall_cycles = 0;
+        self.watchpoint_hit = None;
+
+        if kind == ResetKind::PowerCycle {
+            pattern.fill(&mut self.work_ram_0, WORK_RAM_0_START);
+            for (bank, work_ram) in self.work_ram_n.iter_mut().enumerate() {
+                pattern.fill(work_ram, WORK_RAM_N_START + bank * WORK_RAM_N_SIZE);
             }
+            pattern.fill(&mut self.high_ram, HIGH_RAM_START);
+        }
 
-            _ => {
-                let error = EmulationError::InvalidMemoryWrite { address, value };
-                Err(error)
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.reset(kind, pattern);
+        }
+    }
+
+    /// Registers that only exist on the CGB. Most of them only work in CGB mode.
+    fn read_cgb_register(&self, address: usize) -> u8 {
+        if !self.model.is_cgb() {
+            return 0xFF;
+        }
+
+        match address {
+            SPEED_SWITCH_REGISTER if self.cgb_mode => {
+                let speed = if self.is_double_speed { 0x80 } else { 0 };
+                let prepared = if self.is_speed_switch_prepared { 1 } else { 0 };
+                0x7E | speed | prepared
+            }
+            VRAM_BANK_REGISTER if self.cgb_mode => 0xFE | self.gpu.vram_bank as u8,
+            PALETTE_REGISTERS_START..=PALETTE_REGISTERS_END if self.can_access_palettes() => {
+                self.gpu.read_palette_register(address)
+            }
+            WORK_RAM_BANK_REGISTER if self.cgb_mode => 0xF8 | self.work_ram_bank as u8,
+            HDMA_REGISTERS_START..=HDMA_REGISTERS_END if self.cgb_mode => {
+                self.hdma.read_register(address)
             }
+            0xFF72 | 0xFF73 => self.undocumented_registers[address - 0xFF72],
+            0xFF74 if self.cgb_mode => self.undocumented_registers[2],
+            0xFF75 => 0x8F | self.undocumented_registers[3],
+            // PCM amplitudes of the sound channels
+            0xFF76 | 0xFF77 => {
+                let outputs = self.apu.channel_outputs();
+                let first = (address - 0xFF76) * 2;
+                outputs[first + 1] << 4 | outputs[first]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_cgb_register(&mut self, address: usize, value: u8) {
+        if !self.model.is_cgb() {
+            return;
+        }
+
+        match address {
+            CGB_MODE_REGISTER if self.is_boot_rom_mapped => {
+                // the boot ROM switches to DMG compatibility mode when the game isn't for CGB
+                self.cgb_mode = value & 0b0100 == 0;
+                self.update_color_mode();
+            }
+            SPEED_SWITCH_REGISTER if self.cgb_mode => {
+                self.is_speed_switch_prepared = value & 0b1 != 0;
+            }
+            VRAM_BANK_REGISTER if self.cgb_mode => self.gpu.vram_bank = (value & 0b1) as usize,
+            PALETTE_REGISTERS_START..=PALETTE_REGISTERS_END if self.can_access_palettes() => {
+                self.gpu.write_palette_register(address, value)
+            }
+            HDMA_REGISTERS_START..=HDMA_REGISTERS_END if self.cgb_mode => {
+                match self.hdma.write_register(address, value) {
+                    Some(HdmaStart::GeneralPurpose { blocks }) => {
+                        for _ in 0..blocks {
+                            self.copy_hdma_block();
+                        }
+                    }
+                    // with the LCD off there's no HBlank, so the first block is copied right away
+                    Some(HdmaStart::HBlank) if !self.gpu.is_lcd_enabled() => {
+                        self.run_hblank_dma();
+                    }
+                    _ => {}
+                }
+            }
+            WORK_RAM_BANK_REGISTER if self.cgb_mode => {
+                // bank 0 can't be selected, it maps bank 1 instead
+                self.work_ram_bank = ((value & 0b111) as usize).max(1);
+            }
+            0xFF72 | 0xFF73 => self.undocumented_registers[address - 0xFF72] = value,
+            0xFF74 if self.cgb_mode => self.undocumented_registers[2] = value,
+            0xFF75 => self.undocumented_registers[3] = value & 0b0111_0000,
+            _ => {}
+        }
+    }
+
+    /// Copies the byte of the current M-cycle of the OAM DMA
+    fn run_oam_dma_cycle(&mut self) {
+        if let Some((source, offset)) = self.oam_dma.tick() {
+            // the sources above WRAM read its echo
+            let source = if source as usize >= ECHO_RAM_START {
+                source - 0x2000
+            } else {
+                source
+            };
+            let value = self.read_byte_unrestricted(source).unwrap_or(0xFF);
+            self.oam_dma.set_last_byte(value);
+            let _ = self.gpu.write_byte_oam(OAM_BEGIN + offset, value);
+        }
+    }
+
+    /// Copies the next 16 bytes of a VRAM DMA, the CPU is halted meanwhile
+    fn copy_hdma_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for offset in 0..HDMA_BLOCK_SIZE {
+            let value = self
+                .read_byte_unrestricted(source.wrapping_add(offset))
+                .unwrap_or(0xFF);
+            let address = VRAM_BEGIN + (destination + offset) as usize;
+            let _ = self.gpu.write_byte_vram(address, value);
+        }
+
+        // copying a block takes 8 m-cycles, or 16 in double speed
+        self.stall_cycles += if self.is_double_speed { 64 } else { 32 };
+    }
+
+    fn run_hblank_dma(&mut self) {
+        self.copy_hdma_block();
+        self.hdma.hblank_block_done();
+    }
+
+    /// T-cycles the CPU has to wait before executing the next instruction
+    pub fn take_stall_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    /// In DMG compatibility mode, only the boot ROM can set up the palettes
+    const fn can_access_palettes(&self) -> bool {
+        self.cgb_mode || self.is_boot_rom_mapped
+    }
+
+    /// Overrides the palette DMG games get on CGB, `None` goes back to the game's one
+    pub fn set_compatibility_palette(&mut self, palette: Option<CompatibilityPalette>) {
+        self.compatibility_palette = palette;
+        if self.gpu.color_mode == ColorMode::Compatibility && !self.is_boot_rom_mapped {
+            let palette = palette.unwrap_or_else(|| self.game_compatibility_palette());
+            self.apply_compatibility_palette(palette);
+        }
+    }
+
+    /// Palette the boot ROM picks for the inserted game
+    fn game_compatibility_palette(&self) -> CompatibilityPalette {
+        self.cartridge
+            .as_ref()
+            .map(|cartridge| CompatibilityPalette::for_header(&cartridge.get_header()))
+            .unwrap_or_default()
+    }
+
+    /// Sets the palettes a DMG game uses when running in CGB compatibility mode
+    pub fn apply_compatibility_palette(&mut self, palette: CompatibilityPalette) {
+        self.gpu.bg_palettes.set_palette(0, palette.bg);
+        self.gpu.obj_palettes.set_palette(0, palette.obj0);
+        self.gpu.obj_palettes.set_palette(1, palette.obj1);
+    }
+
+    fn update_color_mode(&mut self) {
+        self.gpu.color_mode = if !self.model.is_cgb() {
+            ColorMode::Dmg
+        } else if self.cgb_mode {
+            ColorMode::Cgb
+        } else {
+            ColorMode::Compatibility
+        };
+    }
+
+    pub const fn is_cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+
+    pub const fn is_double_speed(&self) -> bool {
+        self.is_double_speed
+    }
+
+    pub const fn is_speed_switch_prepared(&self) -> bool {
+        self.is_speed_switch_prepared
+    }
+
+    /// Toggles double speed mode, done by the CPU when executing STOP after preparing the switch
+    pub fn switch_speed(&mut self) {
+        self.is_double_speed = !self.is_double_speed;
+        self.is_speed_switch_prepared = false;
+    }
+
+    fn read_boot_rom(&self, address: usize) -> Option<u8> {
+        if !self.is_boot_rom_mapped {
+            return None;
+        }
+
+        let boot_rom = self.boot_rom.as_ref()?;
+        match address {
+            BOOT_ROM_START..=BOOT_ROM_END => boot_rom.get(address).copied(),
+            // the CGB boot ROM is split in two, leaving the cartridge header visible
+            CGB_BOOT_ROM_START..=CGB_BOOT_ROM_END => boot_rom.get(address).copied(),
+            _ => None,
+        }
+    }
+
+    /// Maps the boot ROM, if there's one
+    pub fn map_boot_rom(&mut self) {
+        let model = self.model;
+        // the CGB always starts in CGB mode, the boot ROM decides if it has to leave it
+        self.cgb_mode = model.is_cgb();
+        self.apu.is_cgb = model.is_cgb();
+        self.create_sgb();
+        self.is_boot_rom_mapped = self.boot_rom.is_some();
+        self.update_color_mode();
+    }
+
+    pub const fn is_boot_rom_mapped(&self) -> bool {
+        self.is_boot_rom_mapped
+    }
+
+    /// Sets the IO registers to the values the boot ROM of the model leaves behind
+    pub fn apply_post_boot_state(&mut self) {
+        let model = self.model;
+        self.cgb_mode = model.is_cgb()
+            && self
+                .cartridge
+                .as_ref()
+                .is_some_and(|cartridge| cartridge.get_header().supports_cgb());
+        self.create_sgb();
+        self.update_color_mode();
+
+        match self.gpu.color_mode {
+            ColorMode::Dmg => {}
+            ColorMode::Compatibility => {
+                // a palette picked by the user wins over the one the boot ROM would pick,
+                // which is the one of the buttons held during the logo or the game's one
+                let palette = self
+                    .compatibility_palette
+                    .or_else(|| CompatibilityPalette::for_buttons(self.joypad.buttons))
+                    .unwrap_or_else(|| self.game_compatibility_palette());
+                self.apply_compatibility_palette(palette);
+                self.gpu.object_priority_mode = 1;
+            }
+            ColorMode::Cgb => {
+                // the boot ROM leaves every background color white
+                for palette in 0..8 {
+                    self.gpu
+                        .bg_palettes
+                        .set_palette(palette, [DMG_SHADES[0]; 4]);
+                }
+            }
+        }
+        for &(address, value) in model.post_boot_io_registers() {
+            match address {
+                // the last OAM DMA of the boot ROM is long over
+                OAM_DMA_REGISTER => self.oam_dma.register = value,
+                // the SGB already got the packets of its boot ROM
+                JOYPAD_REGISTER => self.joypad.write_register(value),
+                _ => self
+                    .write_io_register(address, value)
+                    .expect("the post-boot tables only list writable IO registers"),
+            }
+        }
+        self.timers.set_cycle_count(model.post_boot_divider());
+        // the SGB boot ROM doesn't play a sound
+        self.apu
+            .apply_post_boot_state(model.is_cgb(), !model.is_sgb());
+    }
+
+    fn create_sgb(&mut self) {
+        let supports_sgb = self
+            .cartridge
+            .as_ref()
+            .is_some_and(|cartridge| cartridge.get_header().supports_sgb());
+        self.sgb = self.model.is_sgb().then(|| Sgb::new(supports_sgb));
+    }
+
+    fn read_joypad(&self) -> u8 {
+        let Some(sgb) = &self.sgb else {
+            return self.joypad.read_register();
+        };
+
+        if let Some(value) = sgb.read_joypad() {
+            return value;
+        }
+        match sgb.current_player_buttons() {
+            Some(buttons) => self.joypad.read_register_with(buttons),
+            None => self.joypad.read_register(),
         }
     }
 
     pub fn reset_divider_register(&mut self) {
-        self.timers.divider_register = 0;
+        let old_divider = self.timers.cycle_count();
+        self.timers.reset_divider();
+        self.clock_frame_sequencer(old_divider);
+    }
+
+    /// Advances every component on the bus by the given amount of t-cycles at the
+    /// CPU speed. Returns how long that took in normal speed t-cycles.
+    pub fn run(&mut self, cycles: u32) -> u32 {
+        let old_divider = self.timers.cycle_count();
+        self.timers.run(cycles);
+        self.clock_frame_sequencer(old_divider);
+        self.serial.run(
+            cycles,
+            self.is_double_speed,
+            &mut self.timers.interrupt_flag_register,
+        );
+        for _ in 0..cycles / 4 {
+            self.run_oam_dma_cycle();
+        }
+
+        // the PPU keeps the same speed in double speed mode
+        let cycles = if self.is_double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.gpu
+            .run(cycles, &mut self.timers.interrupt_flag_register);
+        self.apu.run(cycles);
+
+        for _ in 0..self.gpu.take_hblanks() {
+            if self.hdma.is_hblank_active() {
+                self.run_hblank_dma();
+            }
+        }
+
+        cycles
+    }
+
+    /// The APU frame sequencer is clocked when bit 4 of DIV goes from 1 to 0, bit 5 in
+    /// double speed
+    fn clock_frame_sequencer(&mut self, old_divider: u16) {
+        let bit = if self.is_double_speed { 0x2000 } else { 0x1000 };
+        if old_divider & bit != 0 && self.timers.cycle_count() & bit == 0 {
+            self.apu.step_frame_sequencer();
+        }
+    }
+
+    /// Sets the buttons held by the player, requesting a joypad interrupt when a
+    /// selected button gets pressed
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        let old_lines = self.joypad.read_register() & 0x0F;
+        self.joypad.buttons = buttons;
+        let new_lines = self.joypad.read_register() & 0x0F;
+
+        // the lines are active low
+        if old_lines & !new_lines != 0 {
+            self.timers
+                .interrupt_flag_register
+                .request(Interrupt::Joypad);
+        }
+    }
+
+    /// The highest priority interrupt that is both requested and enabled
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        let enabled = self.timers.interrupt_enable_register;
+        let requested = self.timers.interrupt_flag_register;
+
+        Interrupt::ALL
+            .into_iter()
+            .find(|&interrupt| enabled.is_set(interrupt) && requested.is_set(interrupt))
+    }
+
+    pub fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.timers.interrupt_flag_register.set(interrupt, false);
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) -> Result<()> {
+        let cartridge = self.cartridge.as_ref().ok_or(EmulationError::NoRom)?;
+
+        writer.write_bool(self.is_boot_rom_mapped);
+        writer.write_bool(self.cgb_mode);
+        writer.write_bytes(&self.work_ram_0);
+        for work_ram in &self.work_ram_n {
+            writer.write_bytes(work_ram);
+        }
+        writer.write_u8(self.work_ram_bank as u8);
+        writer.write_bool(self.is_double_speed);
+        writer.write_bool(self.is_speed_switch_prepared);
+        writer.write_bytes(&self.undocumented_registers);
+        self.hdma.save_state(writer);
+        self.oam_dma.save_state(writer);
+        writer.write_u32(self.stall_cycles);
+        writer.write_bytes(&self.high_ram);
+        self.serial.save_state(writer);
+        self.apu.save_state(writer);
+        writer.write_bool(self.sgb.is_some());
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(writer);
+        }
+        writer.write_u8(self.joypad.read_register());
+        self.timers.save_state(writer);
+        self.gpu.save_state(writer);
+        cartridge.save_state(writer);
+
+        Ok(())
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let cartridge = self.cartridge.as_mut().ok_or(EmulationError::NoRom)?;
+
+        // states saved while the boot ROM was running need it to be loaded, checked
+        // before anything is overwritten
+        let is_boot_rom_mapped = reader.read_bool()?;
+        if is_boot_rom_mapped && self.boot_rom.is_none() {
+            return Err(EmulationError::SaveStateNeedsBootRom);
+        }
+        self.is_boot_rom_mapped = is_boot_rom_mapped;
+        self.cgb_mode = reader.read_bool()?;
+        reader.read_bytes(&mut self.work_ram_0)?;
+        for work_ram in &mut self.work_ram_n {
+            reader.read_bytes(work_ram)?;
+        }
+        self.work_ram_bank = ((reader.read_u8()? & 0b111) as usize).max(1);
+        self.is_double_speed = reader.read_bool()?;
+        self.is_speed_switch_prepared = reader.read_bool()?;
+        reader.read_bytes(&mut self.undocumented_registers)?;
+        self.hdma.load_state(reader)?;
+        self.oam_dma.load_state(reader)?;
+        self.stall_cycles = reader.read_u32()?;
+        reader.read_bytes(&mut self.high_ram)?;
+        self.serial.load_state(reader)?;
+        self.apu.load_state(reader)?;
+        if reader.read_bool()? {
+            let sgb = self.sgb.get_or_insert_with(|| Sgb::new(false));
+            sgb.load_state(reader)?;
+        } else {
+            self.sgb = None;
+        }
+        self.joypad.write_register(reader.read_u8()?);
+        self.timers.load_state(reader)?;
+        self.gpu.load_state(reader)?;
+        cartridge.load_state(reader)?;
+        self.update_color_mode();
+
+        Ok(())
+    }
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -201,6 +807,7 @@ pub const WORK_RAM_0_SIZE: usize = WORK_RAM_0_END - WORK_RAM_0_START + 1;
 pub const WORK_RAM_N_START: usize = 0xD000;
 pub const WORK_RAM_N_END: usize = 0xDFFF;
 pub const WORK_RAM_N_SIZE: usize = WORK_RAM_N_END - WORK_RAM_N_START + 1;
+pub const WORK_RAM_N_BANK_AMOUNT: usize = 7;
 
 pub const ECHO_RAM_START: usize = 0xE000;
 pub const ECHO_RAM_END: usize = 0xFDFF;
@@ -217,6 +824,24 @@ pub const HIGH_RAM_SIZE: usize = HIGH_RAM_END - HIGH_RAM_START + 1;
 pub const INTERRUPT_ENABLE_REGISTER: usize = 0xFFFF;
 pub const INTERRUPT_FLAG_REGISTER: usize = 0xFF0F;
 
+pub const JOYPAD_REGISTER: usize = 0xFF00;
+pub const BOOT_ROM_REGISTER: usize = 0xFF50;
+
+pub const CGB_MODE_REGISTER: usize = 0xFF4C;
+pub const SPEED_SWITCH_REGISTER: usize = 0xFF4D;
+pub const WORK_RAM_BANK_REGISTER: usize = 0xFF70;
+pub const PALETTE_REGISTERS_START: usize = 0xFF68;
+pub const PALETTE_REGISTERS_END: usize = 0xFF6C;
+pub const CGB_REGISTERS_START: usize = 0xFF4C;
+pub const CGB_REGISTERS_END: usize = 0xFF77;
+
+pub const BOOT_ROM_START: usize = 0x0000;
+pub const BOOT_ROM_END: usize = 0x00FF;
+pub const DMG_BOOT_ROM_SIZE: usize = BOOT_ROM_END - BOOT_ROM_START + 1;
+pub const CGB_BOOT_ROM_START: usize = 0x0200;
+pub const CGB_BOOT_ROM_END: usize = 0x08FF;
+pub const CGB_BOOT_ROM_SIZE: usize = CGB_BOOT_ROM_END + 1;
+
 // Timers
 pub const DIVIDER_REGISTER: usize = 0xFF04;
 pub const TIMER_COUNTER_REGISTER: usize = 0xFF05;
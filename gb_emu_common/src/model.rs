@@ -0,0 +1,248 @@
+use crate::cartridge::header::Header;
+use crate::cpu_registers::{FlagsRegister, Registers};
+use crate::error::{EmulationError, Result};
+
+/// Game Boy hardware revision. They run the same games, but each boot ROM leaves
+/// the CPU and IO registers with different values, which games use to detect the hardware.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Model {
+    /// Original Game Boy with the early boot ROM
+    Dmg0,
+    /// Original Game Boy
+    #[default]
+    Dmg,
+    /// Game Boy Pocket and Light
+    Mgb,
+    /// Super Game Boy
+    Sgb,
+    /// Super Game Boy 2
+    Sgb2,
+    /// Game Boy Color
+    Cgb,
+    /// Game Boy Advance, running Game Boy Color games
+    Agb,
+}
+
+impl Model {
+    pub const ALL: [Model; 7] = [
+        Model::Dmg0,
+        Model::Dmg,
+        Model::Mgb,
+        Model::Sgb,
+        Model::Sgb2,
+        Model::Cgb,
+        Model::Agb,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Model::Dmg0 => "DMG0",
+            Model::Dmg => "DMG",
+            Model::Mgb => "MGB",
+            Model::Sgb => "SGB",
+            Model::Sgb2 => "SGB2",
+            Model::Cgb => "CGB",
+            Model::Agb => "AGB",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Model> {
+        Model::ALL
+            .into_iter()
+            .find(|model| model.name().eq_ignore_ascii_case(name))
+    }
+
+    pub const fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub const fn is_sgb(self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    /// CPU registers right after the boot ROM jumps to 0x0100
+    pub fn post_boot_registers(self, header: &Header) -> Registers {
+        // the DMG boot ROM leaves the flags of the header checksum comparison
+        let checksum_flags = FlagsRegister {
+            zero: true,
+            subtract: false,
+            half_carry: header.header_checksum != 0,
+            carry: header.header_checksum != 0,
+        };
+
+        let (a, f, bc, de, hl) = match self {
+            Model::Dmg0 => (0x01, FlagsRegister::new(), 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x01, checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF, checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x01, FlagsRegister::new(), 0x0014, 0x0000, 0xC060),
+            Model::Sgb2 => (0xFF, FlagsRegister::new(), 0x0014, 0x0000, 0xC060),
+            Model::Cgb | Model::Agb if header.supports_cgb() => {
+                (0x11, 0x80.into(), 0x0000, 0xFF56, 0x000D)
+            }
+            Model::Cgb | Model::Agb => {
+                // the compatibility palette lookup leaves the title checksum in B
+                let b = if header.is_nintendo_licensee() {
+                    header.title_checksum
+                } else {
+                    0
+                };
+                let hl = if b == 0x43 || b == 0x58 {
+                    0x991A
+                } else {
+                    0x007C
+                };
+
+                (0x11, 0x80.into(), (b as u16) << 8, 0x0008, hl)
+            }
+        };
+
+        let mut registers = Registers::new();
+        registers.a = a;
+        registers.f = f;
+        registers.set_bc(bc);
+        registers.set_de(de);
+        registers.set_hl(hl);
+
+        if self == Model::Agb {
+            // the GBA boot ROM runs an extra `INC B`
+            registers.b = registers.b.wrapping_add(1);
+            registers.f.zero = registers.b == 0;
+            registers.f.subtract = false;
+            registers.f.half_carry = registers.b & 0x0F == 0;
+        }
+
+        registers
+    }
+
+    /// Internal counter of the timers after the boot ROM, DIV is its upper byte
+    pub const fn post_boot_divider(self) -> u16 {
+        match self {
+            Model::Dmg0 => 0x182C,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            // the SGB boot ROM waits for the SNES, so it doesn't finish at a fixed time
+            Model::Sgb | Model::Sgb2 => 0x0000,
+            Model::Cgb | Model::Agb => 0x267C,
+        }
+    }
+
+    /// IO registers after the boot ROM, as written to them. DIV, LY and STAT depend on
+    /// when the boot ROM finishes, and the sound registers on the sound it plays.
+    pub const fn post_boot_io_registers(self) -> &'static [(usize, u8)] {
+        if self.is_cgb() {
+            &CGB_IO_REGISTERS
+        } else {
+            &DMG_IO_REGISTERS
+        }
+    }
+
+    pub const fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> Result<Model> {
+        Model::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or(EmulationError::InvalidSaveState)
+    }
+}
+
+const DMG_IO_REGISTERS: [(usize, u8); 18] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    // the boot ROM waits for VBlank with interrupts disabled
+    (0xFF0F, 0xE1), // IF
+    (0xFF40, 0x91), // LCDC
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF46, 0xFF), // DMA
+    (0xFF47, 0xFC), // BGP
+    (0xFF48, 0xFF), // OBP0
+    (0xFF49, 0xFF), // OBP1
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+    (0xFFFF, 0x00), // IE
+];
+
+const CGB_IO_REGISTERS: [(usize, u8); 20] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7F), // SC
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF40, 0x91), // LCDC
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF46, 0x00), // DMA
+    (0xFF47, 0xFC), // BGP
+    (0xFF48, 0xFF), // OBP0
+    (0xFF49, 0xFF), // OBP1
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+    (0xFF4F, 0x00), // VBK
+    (0xFF70, 0x00), // SVBK
+    (0xFFFF, 0x00), // IE
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::ROM_BANK_SIZE;
+    use crate::GameBoy;
+
+    fn header(cgb_flag: u8) -> Header {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        rom[0x0143] = cgb_flag;
+        Header::read_rom_header(&rom).unwrap()
+    }
+
+    #[test]
+    fn post_boot_registers() {
+        let registers = Model::Dmg.post_boot_registers(&header(0));
+        assert_eq!(registers.get_af(), 0x0180);
+        assert_eq!(registers.get_bc(), 0x0013);
+        assert_eq!(registers.get_de(), 0x00D8);
+        assert_eq!(registers.get_hl(), 0x014D);
+
+        let registers = Model::Cgb.post_boot_registers(&header(0x80));
+        assert_eq!(registers.get_af(), 0x1180);
+        assert_eq!(registers.get_de(), 0xFF56);
+
+        let registers = Model::Agb.post_boot_registers(&header(0x80));
+        assert_eq!(registers.get_af(), 0x1100);
+        assert_eq!(registers.get_bc(), 0x0100);
+    }
+
+    #[test]
+    fn post_boot_io_registers() {
+        for (model, serial_control, oam_dma) in [(Model::Dmg, 0x7E, 0xFF), (Model::Cgb, 0x7F, 0x00)]
+        {
+            let mut rom = vec![0; ROM_BANK_SIZE * 2];
+            rom[0x0143] = if model.is_cgb() { 0x80 } else { 0x00 };
+            let mut gb = GameBoy::new();
+            gb.set_model(model);
+            gb.load_rom(rom).unwrap();
+
+            let bus = &gb.cpu.bus;
+            assert_eq!(bus.read_byte(0xFF02).unwrap(), serial_control);
+            assert_eq!(
+                bus.read_byte(0xFF04).unwrap(),
+                (model.post_boot_divider() >> 8) as u8
+            );
+            assert_eq!(bus.read_byte(0xFF07).unwrap(), 0xF8);
+            assert_eq!(bus.read_byte(0xFF0F).unwrap() & 0x1F, 0x01);
+            assert_eq!(bus.read_byte(0xFF40).unwrap(), 0x91);
+            assert_eq!(bus.read_byte(0xFF46).unwrap(), oam_dma);
+            assert_eq!(bus.read_byte(0xFF47).unwrap(), 0xFC);
+            assert_eq!(bus.read_byte(0xFFFF).unwrap(), 0x00);
+        }
+    }
+}
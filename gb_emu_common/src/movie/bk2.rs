@@ -0,0 +1,163 @@
+//! Import and export of BizHawk movies (`.bk2`).
+//!
+//! A `.bk2` file is a ZIP archive, the inputs are stored as text in `Input Log.txt`
+//! with one line per frame. Each button has a column, and `.` means the button isn't held.
+
+use super::zip::{read_zip, write_zip, ZipEntry};
+use super::{Movie, MovieStart};
+use crate::error::{EmulationError, Result};
+use crate::joypad::{Button, Buttons};
+use crate::reset::RamPattern;
+
+const HEADER_FILE: &str = "Header.txt";
+const INPUT_LOG_FILE: &str = "Input Log.txt";
+
+/// Buttons in the order used by BizHawk's Game Boy cores, with the mnemonic of each of them
+const COLUMNS: [(&str, char, Option<Button>); 9] = [
+    ("Up", 'U', Some(Button::Up)),
+    ("Down", 'D', Some(Button::Down)),
+    ("Left", 'L', Some(Button::Left)),
+    ("Right", 'R', Some(Button::Right)),
+    ("Start", 'S', Some(Button::Start)),
+    ("Select", 's', Some(Button::Select)),
+    ("B", 'B', Some(Button::B)),
+    ("A", 'A', Some(Button::A)),
+    ("Power", 'P', None),
+];
+
+/// Converts a movie to the BizHawk format. Movies that start from a save state can't be
+/// exported, since BizHawk can't load our save states.
+pub fn export_bk2(movie: &Movie, game_name: &str) -> Result<Vec<u8>> {
+    if movie.start != MovieStart::PowerOn {
+        return Err(EmulationError::InvalidMovie);
+    }
+
+    let header = format!(
+        "\
+        MovieVersion BizHawk v2.0.0\n\
+        Platform GB\n\
+        Core Gambatte\n\
+        GameName {game_name}\n\
+        rerecordCount 0\n\
+        StartsFromSavestate False\n\
+        "
+    );
+
+    let key: Vec<&str> = COLUMNS.iter().map(|(name, _, _)| *name).collect();
+    let mut input_log = format!("[Input]\nLogKey:#{}|\n", key.join("|"));
+    for buttons in &movie.frames {
+        input_log.push('|');
+        for (_, mnemonic, button) in COLUMNS {
+            let is_pressed = button.is_some_and(|button| buttons.is_pressed(button));
+            input_log.push(if is_pressed { mnemonic } else { '.' });
+        }
+        input_log.push_str("|\n");
+    }
+    input_log.push_str("[/Input]\n");
+
+    let entries = [
+        ZipEntry {
+            name: String::from(HEADER_FILE),
+            data: header.into_bytes(),
+        },
+        ZipEntry {
+            name: String::from(INPUT_LOG_FILE),
+            data: input_log.into_bytes(),
+        },
+    ];
+
+    Ok(write_zip(&entries))
+}
+
+/// Reads a BizHawk movie. BizHawk doesn't know our ROM hash, so the caller has to supply it.
+pub fn import_bk2(data: &[u8], rom_hash: u32) -> Result<Movie> {
+    let entries = read_zip(data)?;
+    let input_log = entries
+        .iter()
+        .find(|entry| entry.name == INPUT_LOG_FILE)
+        .ok_or(EmulationError::InvalidMovie)?;
+    let input_log = String::from_utf8_lossy(&input_log.data);
+
+    if let Some(header) = entries.iter().find(|entry| entry.name == HEADER_FILE) {
+        let header = String::from_utf8_lossy(&header.data);
+        let starts_from_savestate = header
+            .lines()
+            .any(|line| line.trim().eq_ignore_ascii_case("StartsFromSavestate True"));
+        if starts_from_savestate {
+            return Err(EmulationError::InvalidMovie);
+        }
+    }
+
+    // Which button each column of the log represents
+    let mut columns: Vec<Option<Button>> = COLUMNS.iter().map(|(_, _, button)| *button).collect();
+    let mut frames = Vec::new();
+    for line in input_log.lines() {
+        let line = line.trim();
+        if let Some(key) = line.strip_prefix("LogKey:") {
+            columns = parse_log_key(key);
+        } else if line.starts_with('|') {
+            let inputs = line.trim_matches('|').replace('|', "");
+            let mut buttons = Buttons::new();
+            for (input, button) in inputs.chars().zip(&columns) {
+                if let Some(button) = button {
+                    buttons.set(*button, input != '.');
+                }
+            }
+
+            frames.push(buttons);
+        }
+    }
+
+    Ok(Movie {
+        rom_hash,
+        start: MovieStart::PowerOn,
+        ram_pattern: RamPattern::default(),
+        rtc_seed: 0,
+        frames,
+    })
+}
+
+fn parse_log_key(key: &str) -> Vec<Option<Button>> {
+    key.split(['#', '|'])
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            // multiplayer cores prefix the buttons with the player number
+            let name = name.strip_prefix("P1 ").unwrap_or(name);
+            COLUMNS
+                .iter()
+                .find(|(column, _, _)| *column == name)
+                .and_then(|(_, _, button)| *button)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_and_import() {
+        let mut movie = Movie::new(0x1234, MovieStart::PowerOn, RamPattern::default(), 0);
+        let mut buttons = Buttons::new();
+        movie.frames.push(buttons);
+        buttons.start = true;
+        buttons.a = true;
+        movie.frames.push(buttons);
+
+        let data = export_bk2(&movie, "TEST").unwrap();
+        let imported = import_bk2(&data, 0x1234).unwrap();
+
+        assert_eq!(imported, movie);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        assert!(import_bk2(b"PK\x05\x06\0\0", 0).is_err());
+
+        let movie = Movie::new(0x1234, MovieStart::PowerOn, RamPattern::default(), 0);
+        let data = export_bk2(&movie, "TEST").unwrap();
+        for length in 0..data.len() {
+            assert!(import_bk2(&data[..length], 0x1234).is_err());
+        }
+    }
+}
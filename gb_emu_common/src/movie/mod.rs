@@ -0,0 +1,179 @@
+pub mod bk2;
+mod zip;
+
+use crate::error::{EmulationError, Result};
+use crate::joypad::Buttons;
+use crate::reset::RamPattern;
+use crate::save_state::{StateReader, StateWriter};
+
+const MOVIE_MAGIC: &[u8; 4] = b"GBMV";
+const MOVIE_VERSION: u32 = 2;
+
+/// Recording of the joypad state on every frame. Since the emulation is deterministic,
+/// feeding the same inputs from the same starting point always gives the same result.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    /// CRC-32 of the ROM the movie was recorded with
+    pub rom_hash: u32,
+    pub start: MovieStart,
+    /// Contents of the RAM at power-on
+    pub ram_pattern: RamPattern,
+    /// Initial value for the cartridge real time clock
+    pub rtc_seed: u64,
+    /// Buttons held during each frame
+    pub frames: Vec<Buttons>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MovieStart {
+    PowerOn,
+    /// The movie starts from an embedded save state
+    SaveState(Vec<u8>),
+}
+
+pub enum MovieMode {
+    Recording,
+    Playing {
+        frame: usize,
+    },
+    /// Playback reached the end of the movie
+    Finished,
+}
+
+/// A movie being recorded or played back by a `GameBoy`
+pub struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+}
+
+impl Movie {
+    pub fn new(rom_hash: u32, start: MovieStart, ram_pattern: RamPattern, rtc_seed: u64) -> Movie {
+        Movie {
+            rom_hash,
+            start,
+            ram_pattern,
+            rtc_seed,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(MOVIE_MAGIC);
+        writer.write_u32(MOVIE_VERSION);
+        writer.write_u32(self.rom_hash);
+        let (pattern, seed) = match self.ram_pattern {
+            RamPattern::Zeros => (0, 0),
+            RamPattern::Ones => (1, 0),
+            RamPattern::Random { seed } => (2, seed),
+        };
+        writer.write_u8(pattern);
+        writer.write_u64(seed);
+        writer.write_u64(self.rtc_seed);
+
+        match &self.start {
+            MovieStart::PowerOn => writer.write_u8(0),
+            MovieStart::SaveState(state) => {
+                writer.write_u8(1);
+                writer.write_vec(state);
+            }
+        }
+
+        let frames: Vec<u8> = self.frames.iter().map(|&buttons| buttons.into()).collect();
+        writer.write_vec(&frames);
+
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie> {
+        let mut reader = StateReader::new(data);
+        let mut magic = [0; 4];
+        reader.read_bytes(&mut magic)?;
+        if &magic != MOVIE_MAGIC || reader.read_u32()? != MOVIE_VERSION {
+            return Err(EmulationError::InvalidMovie);
+        }
+
+        let rom_hash = reader.read_u32()?;
+        let pattern = reader.read_u8()?;
+        let seed = reader.read_u64()?;
+        let ram_pattern = match pattern {
+            0 => RamPattern::Zeros,
+            1 => RamPattern::Ones,
+            2 => RamPattern::Random { seed },
+            _ => return Err(EmulationError::InvalidMovie),
+        };
+        let rtc_seed = reader.read_u64()?;
+        let start = match reader.read_u8()? {
+            0 => MovieStart::PowerOn,
+            1 => MovieStart::SaveState(reader.read_vec()?),
+            _ => return Err(EmulationError::InvalidMovie),
+        };
+
+        let frames = reader.read_vec()?.into_iter().map(Buttons::from).collect();
+
+        Ok(Movie {
+            rom_hash,
+            start,
+            ram_pattern,
+            rtc_seed,
+            frames,
+        })
+    }
+}
+
+impl MovieSession {
+    pub fn recording(movie: Movie) -> MovieSession {
+        MovieSession {
+            movie,
+            mode: MovieMode::Recording,
+        }
+    }
+
+    pub fn playing(movie: Movie) -> MovieSession {
+        let mode = if movie.frames.is_empty() {
+            MovieMode::Finished
+        } else {
+            MovieMode::Playing { frame: 0 }
+        };
+
+        MovieSession { movie, mode }
+    }
+
+    /// Buttons that should be held during the current frame, `None` if we're not playing
+    pub fn current_buttons(&self) -> Option<Buttons> {
+        match self.mode {
+            MovieMode::Playing { frame } => self.movie.frames.get(frame).copied(),
+            _ => None,
+        }
+    }
+
+    /// Called at the end of each frame with the buttons that were held during it
+    pub fn frame_done(&mut self, buttons: Buttons) {
+        match self.mode {
+            MovieMode::Recording => self.movie.frames.push(buttons),
+
+            MovieMode::Playing { frame } => {
+                let next_frame = frame + 1;
+                self.mode = if next_frame < self.movie.frames.len() {
+                    MovieMode::Playing { frame: next_frame }
+                } else {
+                    MovieMode::Finished
+                };
+            }
+
+            MovieMode::Finished => {}
+        }
+    }
+
+    pub const fn is_recording(&self) -> bool {
+        matches!(self.mode, MovieMode::Recording)
+    }
+
+    pub const fn is_playing(&self) -> bool {
+        matches!(self.mode, MovieMode::Playing { .. })
+    }
+
+    pub const fn is_finished(&self) -> bool {
+        matches!(self.mode, MovieMode::Finished)
+    }
+}
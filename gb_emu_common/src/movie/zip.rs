@@ -0,0 +1,199 @@
+//! Just enough of the ZIP format to read and write BizHawk movies.
+
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use crate::checksum::crc32;
+use crate::error::{EmulationError, Result};
+use crate::save_state::{StateReader, StateWriter};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x0403_4B50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0201_4B50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4B50;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+pub struct ZipEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Creates a ZIP archive with uncompressed entries
+pub fn write_zip(entries: &[ZipEntry]) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    let mut central_directory = StateWriter::new();
+    let mut offset = 0;
+
+    for entry in entries {
+        let name = entry.name.as_bytes();
+        let crc = crc32(&entry.data);
+        let size = entry.data.len() as u32;
+
+        writer.write_u32(LOCAL_FILE_HEADER_SIGNATURE);
+        writer.write_u16(20); // version needed to extract
+        writer.write_u16(0); // flags
+        writer.write_u16(METHOD_STORED);
+        writer.write_u16(0); // modification time
+        writer.write_u16(0); // modification date
+        writer.write_u32(crc);
+        writer.write_u32(size); // compressed size
+        writer.write_u32(size); // uncompressed size
+        writer.write_u16(name.len() as u16);
+        writer.write_u16(0); // extra field length
+        writer.write_bytes(name);
+        writer.write_bytes(&entry.data);
+
+        central_directory.write_u32(CENTRAL_DIRECTORY_SIGNATURE);
+        central_directory.write_u16(20); // version made by
+        central_directory.write_u16(20); // version needed to extract
+        central_directory.write_u16(0); // flags
+        central_directory.write_u16(METHOD_STORED);
+        central_directory.write_u16(0); // modification time
+        central_directory.write_u16(0); // modification date
+        central_directory.write_u32(crc);
+        central_directory.write_u32(size);
+        central_directory.write_u32(size);
+        central_directory.write_u16(name.len() as u16);
+        central_directory.write_u16(0); // extra field length
+        central_directory.write_u16(0); // comment length
+        central_directory.write_u16(0); // disk number
+        central_directory.write_u16(0); // internal attributes
+        central_directory.write_u32(0); // external attributes
+        central_directory.write_u32(offset);
+        central_directory.write_bytes(name);
+
+        offset += (30 + name.len() + entry.data.len()) as u32;
+    }
+
+    let central_directory = central_directory.into_bytes();
+    writer.write_bytes(&central_directory);
+
+    writer.write_u32(END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    writer.write_u16(0); // disk number
+    writer.write_u16(0); // disk where the central directory starts
+    writer.write_u16(entries.len() as u16);
+    writer.write_u16(entries.len() as u16);
+    writer.write_u32(central_directory.len() as u32);
+    writer.write_u32(offset);
+    writer.write_u16(0); // comment length
+
+    writer.into_bytes()
+}
+
+/// Reads every file in a ZIP archive. Only uncompressed and deflated entries are supported.
+pub fn read_zip(data: &[u8]) -> Result<Vec<ZipEntry>> {
+    read_entries(data).map_err(|_| EmulationError::InvalidMovie)
+}
+
+fn read_entries(data: &[u8]) -> Result<Vec<ZipEntry>> {
+    // The end of central directory record is at the end of the file, followed by
+    // a comment of variable length
+    let eocd_pos = (0..=data.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE))
+        .rev()
+        .find(|&pos| read_u32_at(data, pos) == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or(EmulationError::InvalidMovie)?;
+
+    let eocd = data
+        .get(eocd_pos + 10..)
+        .ok_or(EmulationError::InvalidMovie)?;
+    let mut eocd = StateReader::new(eocd);
+    let entry_amount = eocd.read_u16()?;
+    let _central_directory_size = eocd.read_u32()?;
+    let central_directory_offset = eocd.read_u32()? as usize;
+
+    let mut entries = Vec::new();
+    let mut pos = central_directory_offset;
+    for _ in 0..entry_amount {
+        let mut header = StateReader::new(data.get(pos..).ok_or(EmulationError::InvalidMovie)?);
+        if header.read_u32()? != CENTRAL_DIRECTORY_SIGNATURE {
+            return Err(EmulationError::InvalidMovie);
+        }
+
+        let mut skipped = [0; 6];
+        header.read_bytes(&mut skipped)?; // versions and flags
+        let method = header.read_u16()?;
+        header.read_u32()?; // modification time and date
+        let crc = header.read_u32()?;
+        let compressed_size = header.read_u32()? as usize;
+        let uncompressed_size = header.read_u32()? as usize;
+        let name_len = header.read_u16()? as usize;
+        let extra_len = header.read_u16()? as usize;
+        let comment_len = header.read_u16()? as usize;
+        let mut skipped = [0; 8];
+        header.read_bytes(&mut skipped)?; // disk number and attributes
+        let local_header_offset = header.read_u32()? as usize;
+        let mut name = vec![0; name_len];
+        header.read_bytes(&mut name)?;
+        pos += 46 + name_len + extra_len + comment_len;
+
+        // the local header has its own name and extra field lengths
+        let local_name_len = read_u16_at(data, local_header_offset + 26);
+        let local_extra_len = read_u16_at(data, local_header_offset + 28);
+        let (local_name_len, local_extra_len) = match (local_name_len, local_extra_len) {
+            (Some(name_len), Some(extra_len)) => (name_len as usize, extra_len as usize),
+            _ => return Err(EmulationError::InvalidMovie),
+        };
+
+        let data_start = local_header_offset + 30 + local_name_len + local_extra_len;
+        let compressed = data_start
+            .checked_add(compressed_size)
+            .and_then(|data_end| data.get(data_start..data_end))
+            .ok_or(EmulationError::InvalidMovie)?;
+
+        let contents = match method {
+            METHOD_STORED => compressed.to_vec(),
+            // a few bytes can inflate to gigabytes, don't go past what the entry claims
+            METHOD_DEFLATE => decompress_to_vec_with_limit(compressed, uncompressed_size)
+                .map_err(|_| EmulationError::InvalidMovie)?,
+            _ => return Err(EmulationError::InvalidMovie),
+        };
+
+        if crc32(&contents) != crc {
+            return Err(EmulationError::InvalidMovie);
+        }
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(&name).to_string(),
+            data: contents,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn read_u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    let bytes = data.get(pos..pos.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniz_oxide::deflate::compress_to_vec;
+
+    #[test]
+    fn stops_inflating_at_the_declared_size() {
+        let contents = [0; 4096];
+        let entry = ZipEntry {
+            name: String::from("Input Log.txt"),
+            data: compress_to_vec(&contents, 6),
+        };
+        // the entry claims to be as big as its compressed data
+        let mut zip = write_zip(&[entry]);
+        let central_directory = zip
+            .windows(4)
+            .position(|bytes| bytes == CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes())
+            .unwrap();
+        zip[central_directory + 10] = METHOD_DEFLATE as u8;
+        zip[central_directory + 16..central_directory + 20]
+            .copy_from_slice(&crc32(&contents).to_le_bytes());
+
+        assert!(matches!(read_zip(&zip), Err(EmulationError::InvalidMovie)));
+    }
+}
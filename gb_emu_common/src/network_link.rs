@@ -0,0 +1,282 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::serial::SerialDevice;
+
+/// Link cable to a Game Boy running in another process, over TCP.
+///
+/// Both sides report their master clock every `SYNC_INTERVAL` cycles and stop to wait for
+/// the other one when they get more than `MAX_DRIFT` cycles ahead of it. A byte clocked
+/// by one side is answered by the other once it reaches the same point in time.
+/// If the connection drops, or the other side doesn't answer for `timeout`, the link
+/// behaves as if nothing was plugged in and `is_connected` turns false.
+pub struct NetworkLink {
+    stream: Option<TcpStream>,
+    /// Bytes received that don't make a whole message yet
+    buffer: Vec<u8>,
+    /// Normal speed t-cycles since the link was connected
+    cycle: u64,
+    /// Last cycle the other side reported
+    remote_cycle: u64,
+    next_sync: u64,
+    /// Byte clocked by the other side and the cycle it did it at
+    pending_transfer: Option<(u64, u8)>,
+    /// How long to wait for the other side before giving up on the connection
+    pub timeout: Duration,
+}
+
+impl NetworkLink {
+    pub fn new(stream: TcpStream) -> io::Result<NetworkLink> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(NetworkLink {
+            stream: Some(stream),
+            buffer: Vec::new(),
+            cycle: 0,
+            remote_cycle: 0,
+            next_sync: 0,
+            pending_transfer: None,
+            timeout: TIMEOUT,
+        })
+    }
+
+    /// Waits for the other emulator to join
+    pub fn host(address: impl ToSocketAddrs) -> io::Result<NetworkLink> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        NetworkLink::new(stream)
+    }
+
+    /// Connects to an emulator that's hosting
+    pub fn join(address: impl ToSocketAddrs) -> io::Result<NetworkLink> {
+        NetworkLink::new(TcpStream::connect(address)?)
+    }
+
+    pub const fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn send(&mut self, message: Message) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+
+        let result = stream.set_nonblocking(false).and_then(|_| {
+            stream.write_all(&message.to_bytes())?;
+            stream.set_nonblocking(true)
+        });
+        if result.is_err() {
+            self.stream = None;
+        }
+    }
+
+    /// Reads the next message. Without `block` it only returns what already arrived.
+    fn receive(&mut self, block: bool) -> Option<Message> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if self.buffer.len() >= MESSAGE_SIZE {
+                let bytes: Vec<u8> = self.buffer.drain(..MESSAGE_SIZE).collect();
+                return Message::from_bytes(&bytes);
+            }
+
+            let stream = self.stream.as_mut()?;
+            let mut bytes = [0; 64];
+            match stream.read(&mut bytes) {
+                // the other side hung up
+                Ok(0) => {
+                    self.stream = None;
+                    return None;
+                }
+                Ok(length) => self.buffer.extend_from_slice(&bytes[..length]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    if !block {
+                        return None;
+                    }
+                    if Instant::now() >= deadline {
+                        self.stream = None;
+                        return None;
+                    }
+                    std::thread::yield_now();
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.stream = None;
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Handles a message that isn't the answer to one of our transfers
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Sync { cycle } => self.remote_cycle = cycle,
+            Message::Transfer { cycle, data } => {
+                self.remote_cycle = cycle;
+                self.pending_transfer = Some((cycle, data));
+            }
+            // an answer nobody is waiting for
+            Message::Reply { .. } => {}
+        }
+    }
+}
+
+impl SerialDevice for NetworkLink {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        let cycle = self.cycle;
+        self.send(Message::Transfer { cycle, data: sent });
+
+        while let Some(message) = self.receive(true) {
+            match message {
+                Message::Reply { data } => return data,
+                // both sides used their internal clock, neither listens to the other
+                Message::Transfer { cycle, .. } => {
+                    self.remote_cycle = cycle;
+                    self.send(Message::Reply { data: 0xFF });
+                }
+                message => self.handle(message),
+            }
+        }
+
+        0xFF
+    }
+
+    fn run(&mut self, cycles: u32, waiting: Option<u8>) -> Option<u8> {
+        if !self.is_connected() {
+            return None;
+        }
+
+        self.cycle += cycles as u64;
+        if self.cycle >= self.next_sync {
+            let cycle = self.cycle;
+            self.send(Message::Sync { cycle });
+            self.next_sync = cycle + SYNC_INTERVAL;
+        }
+
+        while let Some(message) = self.receive(false) {
+            self.handle(message);
+        }
+
+        // don't get too far ahead, unless there's a transfer to answer
+        while self.pending_transfer.is_none() && self.cycle > self.remote_cycle + MAX_DRIFT {
+            match self.receive(true) {
+                Some(message) => self.handle(message),
+                None => return None,
+            }
+        }
+
+        match self.pending_transfer {
+            Some((cycle, data)) if cycle <= self.cycle => {
+                self.pending_transfer = None;
+                // a Game Boy that isn't waiting for a transfer doesn't drive the line
+                self.send(Message::Reply {
+                    data: waiting.unwrap_or(0xFF),
+                });
+                waiting.map(|_| data)
+            }
+            _ => None,
+        }
+    }
+}
+
+enum Message {
+    Sync { cycle: u64 },
+    Transfer { cycle: u64, data: u8 },
+    Reply { data: u8 },
+}
+
+impl Message {
+    fn to_bytes(&self) -> [u8; MESSAGE_SIZE] {
+        let (kind, cycle, data) = match *self {
+            Message::Sync { cycle } => (0, cycle, 0),
+            Message::Transfer { cycle, data } => (1, cycle, data),
+            Message::Reply { data } => (2, 0, data),
+        };
+
+        let mut bytes = [0; MESSAGE_SIZE];
+        bytes[0] = kind;
+        bytes[1..9].copy_from_slice(&cycle.to_le_bytes());
+        bytes[9] = data;
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Message> {
+        let cycle = u64::from_le_bytes(bytes[1..9].try_into().ok()?);
+        let data = bytes[9];
+        match bytes[0] {
+            0 => Some(Message::Sync { cycle }),
+            1 => Some(Message::Transfer { cycle, data }),
+            2 => Some(Message::Reply { data }),
+            _ => None,
+        }
+    }
+}
+
+const MESSAGE_SIZE: usize = 10;
+/// Cycles between the reports of the master clock, a bit less than a scanline
+const SYNC_INTERVAL: u64 = 256;
+/// How far ahead of the other side an emulator can run
+const MAX_DRIFT: u64 = 4 * SYNC_INTERVAL;
+/// Default for `NetworkLink::timeout`
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::ROM_BANK_SIZE;
+    use crate::GameBoy;
+    use std::thread;
+
+    // loops forever on a JR -2
+    fn game_boy() -> GameBoy {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        rom[0x0100] = 0x18;
+        rom[0x0101] = 0xFE;
+        let mut gb = GameBoy::new();
+        gb.load_rom(rom).unwrap();
+        gb
+    }
+
+    #[test]
+    fn exchanges_bytes_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // the second Game Boy waits on the external clock in another thread
+        let slave = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut gb = game_boy();
+            gb.connect_serial_device(Some(Box::new(NetworkLink::new(stream).unwrap())));
+            gb.cpu.bus.write_byte(0xFF01, 0x22).unwrap();
+            gb.cpu.bus.write_byte(0xFF02, 0x80).unwrap();
+            for _ in 0..3 {
+                gb.run_frame().unwrap();
+            }
+            gb.cpu.bus.read_byte(0xFF01).unwrap()
+        });
+
+        let mut gb = game_boy();
+        gb.connect_serial_device(Some(Box::new(NetworkLink::join(address).unwrap())));
+        gb.cpu.bus.write_byte(0xFF01, 0x11).unwrap();
+        gb.cpu.bus.write_byte(0xFF02, 0x81).unwrap();
+        gb.run_frame().unwrap();
+        assert_eq!(gb.cpu.bus.read_byte(0xFF01).unwrap(), 0x22);
+
+        // unplug the cable so the other side stops waiting for this clock
+        gb.connect_serial_device(None);
+        assert_eq!(slave.join().unwrap(), 0x11);
+    }
+
+    #[test]
+    fn gives_up_on_a_silent_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut link = NetworkLink::join(listener.local_addr().unwrap()).unwrap();
+        link.timeout = Duration::from_millis(50);
+        // accepted but never answered
+        let _stream = listener.accept().unwrap();
+
+        assert_eq!(link.exchange(0x11), 0xFF);
+        assert!(!link.is_connected());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::error::Result;
+use crate::save_state::{StateReader, StateWriter};
+
+/// Colors are stored as RGB555: 5 bits per channel, red in the lowest bits
+pub type Rgb555 = u16;
+
+/// Shades of the DMG screen, from the lightest to the darkest
+pub const DMG_SHADES: [Rgb555; 4] = [
+    rgb555(31, 31, 31),
+    rgb555(21, 21, 21),
+    rgb555(10, 10, 10),
+    rgb555(0, 0, 0),
+];
+
+pub const PALETTE_RAM_SIZE: usize = 64;
+
+/// Memory of the CGB color palettes: 8 palettes of 4 colors, 2 bytes per color.
+/// It's accessed through an index register (BCPS/OCPS) and a data register (BCPD/OCPD).
+#[derive(Clone)]
+pub struct PaletteRam {
+    data: [u8; PALETTE_RAM_SIZE],
+    index: u8,
+    auto_increment: bool,
+}
+
+pub const fn rgb555(red: u8, green: u8, blue: u8) -> Rgb555 {
+    (red as u16) | (green as u16) << 5 | (blue as u16) << 10
+}
+
+/// Converts a color to 8 bits per channel
+pub const fn rgb555_to_rgb888(color: Rgb555) -> [u8; 3] {
+    [
+        expand_channel(color),
+        expand_channel(color >> 5),
+        expand_channel(color >> 10),
+    ]
+}
+
+const fn expand_channel(channel: u16) -> u8 {
+    let channel = (channel & 0x1F) as u8;
+    channel << 3 | channel >> 2
+}
+
+impl PaletteRam {
+    pub fn new() -> PaletteRam {
+        PaletteRam {
+            data: [0; PALETTE_RAM_SIZE],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    /// BCPS/OCPS - Palette Specification
+    pub fn read_index(&self) -> u8 {
+        let auto_increment = if self.auto_increment { 0x80 } else { 0 };
+        // bit 6 is unused
+        auto_increment | 0x40 | self.index
+    }
+
+    pub fn write_index(&mut self, value: u8) {
+        self.auto_increment = value & 0x80 != 0;
+        self.index = value & 0x3F;
+    }
+
+    /// BCPD/OCPD - Palette Data. The PPU is using the palettes while drawing, so
+    /// they can't be accessed.
+    pub fn read_data(&self, is_locked: bool) -> u8 {
+        if is_locked {
+            0xFF
+        } else {
+            self.data[self.index as usize]
+        }
+    }
+
+    pub fn write_data(&mut self, value: u8, is_locked: bool) {
+        if !is_locked {
+            self.data[self.index as usize] = value;
+        }
+
+        // the index is incremented even if the write was blocked
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    pub fn color(&self, palette: usize, color: usize) -> Rgb555 {
+        let pos = palette * 8 + color * 2;
+        u16::from_le_bytes([self.data[pos], self.data[pos + 1]]) & 0x7FFF
+    }
+
+    pub fn set_color(&mut self, palette: usize, color: usize, value: Rgb555) {
+        let pos = palette * 8 + color * 2;
+        let [low, high] = value.to_le_bytes();
+        self.data[pos] = low;
+        self.data[pos + 1] = high;
+    }
+
+    pub fn set_palette(&mut self, palette: usize, colors: [Rgb555; 4]) {
+        for (color, value) in colors.into_iter().enumerate() {
+            self.set_color(palette, color, value);
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        writer.write_u8(self.read_index());
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes(&mut self.data)?;
+        self.write_index(reader.read_u8()?);
+        Ok(())
+    }
+}
+
+impl Default for PaletteRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,38 @@
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+use crate::checksum::crc32;
+
+/// Encodes an 8-bit RGB image as a PNG file
+pub fn encode_rgb(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGB, deflate, adaptive filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // every row starts with its filter type, we don't filter
+    let row_size = width as usize * 3;
+    let mut rows = Vec::with_capacity((row_size + 1) * height as usize);
+    for row in pixels.chunks_exact(row_size) {
+        rows.push(0);
+        rows.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &compress_to_vec_zlib(&rows, 6));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
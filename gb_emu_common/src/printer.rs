@@ -0,0 +1,433 @@
+use crate::png;
+use crate::serial::SerialDevice;
+
+/// Game Boy Printer. Receives packets through the link port, keeps the image data
+/// in its buffer and prints it on pages that can be exported as PNG files.
+pub struct Printer {
+    state: PacketState,
+    command: u8,
+    is_compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    /// Image data received since the last print, 2 bits per pixel tiles
+    buffer: Vec<u8>,
+    status: u8,
+    /// T-cycles left until the current print is done
+    printing_cycles: u32,
+    /// Page being printed, continues on the next print until a bottom margin is fed
+    page: Option<PrintedPage>,
+    /// Pages that came out of the printer
+    pub pages: Vec<PrintedPage>,
+}
+
+/// Position in the packet of the byte the printer is receiving
+#[derive(Clone, Copy, PartialEq)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+/// A printout, 160 pixels wide
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrintedPage {
+    pub height: usize,
+    /// Shade of every pixel, from 0 (white) to 3 (black)
+    pub pixels: Vec<u8>,
+}
+
+impl Printer {
+    pub fn new() -> Printer {
+        Printer {
+            state: PacketState::Magic1,
+            command: 0,
+            is_compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            buffer: Vec::new(),
+            status: 0,
+            printing_cycles: 0,
+            page: None,
+            pages: Vec::new(),
+        }
+    }
+
+    /// Tears off the page being printed, for a print that didn't feed a bottom margin
+    /// before the printer goes away
+    pub fn finish_page(&mut self) {
+        self.pages.extend(self.page.take());
+    }
+
+    /// Removes the pages printed so far
+    pub fn take_pages(&mut self) -> Vec<PrintedPage> {
+        std::mem::take(&mut self.pages)
+    }
+
+    /// Handles a byte, returns what the printer answers with
+    fn receive(&mut self, value: u8) -> u8 {
+        let mut reply = 0;
+        self.state = match self.state {
+            PacketState::Magic1 if value == 0x88 => PacketState::Magic2,
+            PacketState::Magic1 => PacketState::Magic1,
+            PacketState::Magic2 if value == 0x33 => PacketState::Command,
+            PacketState::Magic2 => PacketState::Magic1,
+            PacketState::Command => {
+                self.command = value;
+                self.checksum = value as u16;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.is_compressed = value & 1 != 0;
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.length = value as u16;
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.length |= (value as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                self.data.clear();
+                if self.length == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.data.push(value);
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                if self.data.len() == self.length as usize {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.checksum = self.checksum.wrapping_sub(value as u16);
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.checksum = self.checksum.wrapping_sub((value as u16) << 8);
+                if self.checksum == 0 {
+                    self.status &= !STATUS_CHECKSUM_ERROR;
+                    self.run_command();
+                } else {
+                    self.status |= STATUS_CHECKSUM_ERROR;
+                }
+                PacketState::Alive
+            }
+            PacketState::Alive => {
+                reply = ALIVE;
+                PacketState::Status
+            }
+            PacketState::Status => {
+                reply = self.status;
+                PacketState::Magic1
+            }
+        };
+
+        reply
+    }
+
+    fn run_command(&mut self) {
+        match self.command {
+            COMMAND_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.printing_cycles = 0;
+            }
+            COMMAND_PRINT => self.print(),
+            COMMAND_DATA => {
+                // an empty data packet only marks the end of the data
+                let data = std::mem::take(&mut self.data);
+                if self.is_compressed {
+                    decompress(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED_DATA;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_IMAGE_DATA_FULL;
+                }
+            }
+            COMMAND_BREAK => {
+                self.buffer.clear();
+                self.printing_cycles = 0;
+                self.status &=
+                    !(STATUS_PRINTING | STATUS_UNPROCESSED_DATA | STATUS_IMAGE_DATA_FULL);
+            }
+            // the status is sent back after every packet
+            COMMAND_STATUS => {}
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    fn print(&mut self) {
+        let [_sheets, margins, palette, _exposure] = match self.data[..] {
+            [sheets, margins, palette, exposure] => [sheets, margins, palette, exposure],
+            _ => {
+                self.status |= STATUS_PACKET_ERROR;
+                return;
+            }
+        };
+        // most games send 0 for the usual palette
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let top_margin = (margins >> 4) as usize * MARGIN_LINES;
+        let bottom_margin = (margins & 0x0F) as usize * MARGIN_LINES;
+
+        let page = self.page.get_or_insert_with(PrintedPage::new);
+        page.feed(top_margin);
+        let buffer = std::mem::take(&mut self.buffer);
+        for band in buffer.chunks(BAND_SIZE) {
+            page.print_band(band, palette);
+        }
+
+        if bottom_margin > 0 {
+            page.feed(bottom_margin);
+            self.pages.extend(self.page.take());
+        }
+
+        let bands = buffer.len().div_ceil(BAND_SIZE) as u32;
+        self.printing_cycles = bands * PRINTING_CYCLES_PER_BAND;
+        self.status &= !(STATUS_UNPROCESSED_DATA | STATUS_IMAGE_DATA_FULL);
+        // only feeding paper is done right away
+        if bands > 0 {
+            self.status |= STATUS_PRINTING;
+        }
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerialDevice for Printer {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.receive(sent)
+    }
+
+    fn run(&mut self, cycles: u32, _waiting: Option<u8>) -> Option<u8> {
+        if self.printing_cycles > 0 {
+            self.printing_cycles = self.printing_cycles.saturating_sub(cycles);
+            if self.printing_cycles == 0 {
+                self.status &= !STATUS_PRINTING;
+            }
+        }
+
+        // the printer never drives the clock
+        None
+    }
+}
+
+impl PrintedPage {
+    fn new() -> PrintedPage {
+        PrintedPage {
+            height: 0,
+            pixels: Vec::new(),
+        }
+    }
+
+    /// Feeds blank paper
+    fn feed(&mut self, lines: usize) {
+        self.height += lines;
+        self.pixels.resize(self.height * PAGE_WIDTH, 0);
+    }
+
+    /// Prints a band of 20 tiles wide and up to 2 tiles high, a partial row is fed
+    /// whole with the missing tiles left blank
+    fn print_band(&mut self, band: &[u8], palette: u8) {
+        let rows = band.len().div_ceil(TILES_PER_ROW * TILE_SIZE);
+        let top = self.height;
+        self.feed(rows * 8);
+
+        for (index, tile) in band.chunks_exact(TILE_SIZE).enumerate() {
+            let tile_x = (index % TILES_PER_ROW) * 8;
+            let tile_y = top + (index / TILES_PER_ROW) * 8;
+            for (y, line) in tile.chunks_exact(2).enumerate() {
+                for x in 0..8 {
+                    let bit = 7 - x;
+                    let color = ((line[0] >> bit) & 1) | (((line[1] >> bit) & 1) << 1);
+                    let shade = (palette >> (color * 2)) & 0b11;
+                    self.pixels[(tile_y + y) * PAGE_WIDTH + tile_x + x] = shade;
+                }
+            }
+        }
+    }
+
+    pub const fn width(&self) -> usize {
+        PAGE_WIDTH
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.pixels.len() * 3);
+        for &shade in &self.pixels {
+            let value = PAPER_SHADES[shade as usize];
+            rgb.extend_from_slice(&[value, value, value]);
+        }
+
+        png::encode_rgb(PAGE_WIDTH as u32, self.height as u32, &rgb)
+    }
+}
+
+/// Expands the run-length encoding of compressed data packets
+fn decompress(data: &[u8], output: &mut Vec<u8>) {
+    let mut bytes = data.iter();
+    while let Some(&control) = bytes.next() {
+        if control & 0x80 != 0 {
+            // a byte repeated
+            let length = (control & 0x7F) as usize + 2;
+            if let Some(&value) = bytes.next() {
+                output.extend(std::iter::repeat_n(value, length));
+            }
+        } else {
+            // bytes copied as they are
+            let length = control as usize + 1;
+            output.extend(bytes.by_ref().take(length));
+        }
+    }
+}
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_BREAK: u8 = 0x08;
+const COMMAND_STATUS: u8 = 0x0F;
+
+/// Sent by the printer in the byte after the checksum, to say it's connected
+const ALIVE: u8 = 0x81;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0b0000_0001;
+const STATUS_PRINTING: u8 = 0b0000_0010;
+const STATUS_IMAGE_DATA_FULL: u8 = 0b0000_0100;
+const STATUS_UNPROCESSED_DATA: u8 = 0b0000_1000;
+const STATUS_PACKET_ERROR: u8 = 0b0001_0000;
+
+const PAGE_WIDTH: usize = 160;
+const TILE_SIZE: usize = 16;
+const TILES_PER_ROW: usize = PAGE_WIDTH / 8;
+/// A data packet holds 2 rows of tiles
+const BAND_SIZE: usize = 2 * TILES_PER_ROW * TILE_SIZE;
+/// The printer holds 9 bands, a 160x144 image
+const BUFFER_SIZE: usize = 9 * BAND_SIZE;
+/// Pixel lines fed for each unit of margin
+const MARGIN_LINES: usize = 8;
+/// Roughly how long it takes the printer to print a band of 16 lines
+const PRINTING_CYCLES_PER_BAND: u32 = 4_194_304 / 8;
+/// Brightness of the shades on paper
+const PAPER_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_packet(printer: &mut Printer, command: u8, data: &[u8]) -> u8 {
+        let mut packet = vec![
+            0x88,
+            0x33,
+            command,
+            0,
+            data.len() as u8,
+            (data.len() >> 8) as u8,
+        ];
+        packet.extend_from_slice(data);
+        let checksum = packet[2..]
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        let replies: Vec<u8> = packet.iter().map(|&byte| printer.exchange(byte)).collect();
+        assert_eq!(replies[replies.len() - 2], ALIVE);
+        replies[replies.len() - 1]
+    }
+
+    #[test]
+    fn prints_a_page() {
+        let mut printer = Printer::new();
+        assert_eq!(send_packet(&mut printer, COMMAND_INIT, &[]), 0);
+
+        // a band where every pixel uses color 3
+        let status = send_packet(&mut printer, COMMAND_DATA, &[0xFF; BAND_SIZE]);
+        assert_eq!(status, STATUS_UNPROCESSED_DATA);
+        send_packet(&mut printer, COMMAND_DATA, &[]);
+
+        // no margin on top, one at the bottom, palette where color 3 is dark gray
+        let status = send_packet(&mut printer, COMMAND_PRINT, &[1, 0x01, 0x94, 0x40]);
+        assert_eq!(status, STATUS_PRINTING);
+        printer.run(PRINTING_CYCLES_PER_BAND, None);
+        assert_eq!(send_packet(&mut printer, COMMAND_STATUS, &[]), 0);
+
+        let pages = printer.take_pages();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].height, 16 + MARGIN_LINES);
+        assert_eq!(pages[0].pixels[0], 2);
+        assert_eq!(pages[0].pixels[16 * PAGE_WIDTH], 0);
+        assert!(pages[0].to_png().starts_with(&PNG_SIGNATURE_START));
+    }
+
+    #[test]
+    fn prints_a_partial_row_of_tiles() {
+        let mut printer = Printer::new();
+        send_packet(&mut printer, COMMAND_INIT, &[]);
+
+        // a row of tiles and one more tile
+        let data = [0xFF; (TILES_PER_ROW + 1) * TILE_SIZE];
+        send_packet(&mut printer, COMMAND_DATA, &data);
+        send_packet(&mut printer, COMMAND_PRINT, &[1, 0x01, 0xE4, 0x40]);
+
+        let pages = printer.take_pages();
+        assert_eq!(pages[0].height, 16 + MARGIN_LINES);
+        assert_eq!(pages[0].pixels[8 * PAGE_WIDTH], 3);
+        assert_eq!(pages[0].pixels[8 * PAGE_WIDTH + 8], 0);
+    }
+
+    #[test]
+    fn finishes_a_page_without_a_bottom_margin() {
+        let mut printer = Printer::new();
+        send_packet(&mut printer, COMMAND_INIT, &[]);
+        send_packet(&mut printer, COMMAND_DATA, &[0xFF; BAND_SIZE]);
+        send_packet(&mut printer, COMMAND_PRINT, &[1, 0x00, 0xE4, 0x40]);
+        assert!(printer.take_pages().is_empty());
+
+        printer.finish_page();
+        assert_eq!(printer.take_pages()[0].height, 16);
+        printer.finish_page();
+        assert!(printer.take_pages().is_empty());
+    }
+
+    #[test]
+    fn feeding_paper_does_not_keep_the_printer_busy() {
+        let mut printer = Printer::new();
+        send_packet(&mut printer, COMMAND_INIT, &[]);
+        let status = send_packet(&mut printer, COMMAND_PRINT, &[1, 0x03, 0xE4, 0x40]);
+        assert_eq!(status, 0);
+        assert_eq!(printer.take_pages()[0].height, 3 * MARGIN_LINES);
+    }
+
+    #[test]
+    fn decompresses_data() {
+        let mut output = Vec::new();
+        decompress(&[0x81, 0xAA, 0x01, 0x12, 0x34], &mut output);
+        assert_eq!(output, [0xAA, 0xAA, 0xAA, 0x12, 0x34]);
+    }
+
+    const PNG_SIGNATURE_START: [u8; 4] = [0x89, b'P', b'N', b'G'];
+}
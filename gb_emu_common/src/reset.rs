@@ -0,0 +1,46 @@
+/// How much of the console state survives a reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetKind {
+    /// Every register goes back to its initial value, but the RAM keeps its contents
+    Soft,
+    /// Turning the console off and on again, only battery backed RAM survives
+    PowerCycle,
+}
+
+/// Contents of the RAM after a power cycle. Real hardware starts with garbage, which
+/// some games (accidentally) depend on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RamPattern {
+    #[default]
+    Zeros,
+    /// Every byte is 0xFF
+    Ones,
+    /// Pseudo random bytes, the same seed always gives the same contents
+    Random { seed: u64 },
+}
+
+impl RamPattern {
+    /// Fills a region of RAM. `region` is mixed into the seed, so different regions
+    /// don't end up with the same contents.
+    pub fn fill(self, ram: &mut [u8], region: usize) {
+        match self {
+            RamPattern::Zeros => ram.fill(0),
+            RamPattern::Ones => ram.fill(0xFF),
+            RamPattern::Random { seed } => {
+                let mut state = seed ^ (region as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                for chunk in ram.chunks_mut(8) {
+                    let bytes = splitmix64(&mut state).to_le_bytes();
+                    chunk.copy_from_slice(&bytes[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec;
+
+use crate::error::{EmulationError, Result};
+
+pub struct RewindConfig {
+    /// Capture a snapshot every N frames
+    pub frame_interval: u32,
+    /// Amount of snapshots stored as deltas before a new keyframe is stored
+    pub keyframe_interval: usize,
+    /// Maximum amount of memory (in bytes) used by the compressed snapshots
+    pub memory_budget: usize,
+}
+
+/// Ring buffer of save states used to rewind the emulation.
+///
+/// Consecutive save states are almost identical, so instead of storing every one of them
+/// we store a full snapshot (the keyframe) followed by XOR deltas against it. The deltas
+/// are mostly zeroes and compress really well.
+pub struct RewindBuffer {
+    config: RewindConfig,
+    snapshots: VecDeque<Snapshot>,
+    /// Uncompressed copy of the latest keyframe, used to create new deltas
+    keyframe: Option<Vec<u8>>,
+    deltas_since_keyframe: usize,
+    frames_since_capture: u32,
+    used_memory: usize,
+}
+
+struct Snapshot {
+    is_keyframe: bool,
+    /// Compressed save state (for keyframes) or compressed XOR delta
+    data: Vec<u8>,
+}
+
+impl RewindConfig {
+    pub fn new() -> RewindConfig {
+        RewindConfig {
+            frame_interval: 1,
+            keyframe_interval: 60,
+            memory_budget: 32 * 1024 * 1024, // 32 MiB
+        }
+    }
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RewindBuffer {
+    pub fn new(config: RewindConfig) -> RewindBuffer {
+        RewindBuffer {
+            config,
+            snapshots: VecDeque::new(),
+            keyframe: None,
+            deltas_since_keyframe: 0,
+            frames_since_capture: 0,
+            used_memory: 0,
+        }
+    }
+
+    /// Should be called at the end of every frame, returns true if a snapshot should be
+    /// captured on this frame.
+    pub fn frame_done(&mut self) -> bool {
+        self.frames_since_capture += 1;
+        if self.frames_since_capture >= self.config.frame_interval {
+            self.frames_since_capture = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        let needs_keyframe = match &self.keyframe {
+            Some(keyframe) => {
+                keyframe.len() != state.len()
+                    || self.deltas_since_keyframe >= self.config.keyframe_interval
+            }
+            None => true,
+        };
+
+        let snapshot = if needs_keyframe {
+            let data = compress_to_vec(&state, COMPRESSION_LEVEL);
+            self.keyframe = Some(state);
+            self.deltas_since_keyframe = 0;
+
+            Snapshot {
+                is_keyframe: true,
+                data,
+            }
+        } else {
+            let keyframe = self.keyframe.as_ref().unwrap();
+            let delta = xor(keyframe, &state);
+            self.deltas_since_keyframe += 1;
+
+            Snapshot {
+                is_keyframe: false,
+                data: compress_to_vec(&delta, COMPRESSION_LEVEL),
+            }
+        };
+
+        self.used_memory += snapshot.data.len();
+        self.snapshots.push_back(snapshot);
+        self.enforce_memory_budget();
+    }
+
+    /// Removes the most recent snapshot and returns its save state
+    pub fn pop(&mut self) -> Result<Option<Vec<u8>>> {
+        let snapshot = match self.snapshots.pop_back() {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+        self.used_memory -= snapshot.data.len();
+
+        let data = decompress(&snapshot.data)?;
+        if snapshot.is_keyframe {
+            // the older snapshots are relative to the previous keyframe
+            self.restore_previous_keyframe()?;
+            return Ok(Some(data));
+        }
+
+        self.deltas_since_keyframe = self.deltas_since_keyframe.saturating_sub(1);
+        let keyframe = match &self.keyframe {
+            Some(keyframe) => keyframe,
+            None => return Err(EmulationError::InvalidSaveState),
+        };
+
+        Ok(Some(xor(keyframe, &data)))
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.keyframe = None;
+        self.deltas_since_keyframe = 0;
+        self.frames_since_capture = 0;
+        self.used_memory = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Amount of memory (in bytes) used by the compressed snapshots
+    pub const fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    fn restore_previous_keyframe(&mut self) -> Result<()> {
+        let pos = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.is_keyframe);
+
+        match pos {
+            Some(pos) => {
+                self.keyframe = Some(decompress(&self.snapshots[pos].data)?);
+                self.deltas_since_keyframe = self.snapshots.len() - pos - 1;
+            }
+
+            None => {
+                self.keyframe = None;
+                self.deltas_since_keyframe = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn enforce_memory_budget(&mut self) {
+        while self.used_memory > self.config.memory_budget {
+            // Deltas can't be used without their keyframe, so the whole group
+            // starting at the oldest keyframe has to go
+            let group_len = self
+                .snapshots
+                .iter()
+                .skip(1)
+                .position(|snapshot| snapshot.is_keyframe)
+                .map(|pos| pos + 1);
+
+            match group_len {
+                Some(group_len) => {
+                    for snapshot in self.snapshots.drain(..group_len) {
+                        self.used_memory -= snapshot.data.len();
+                    }
+                }
+
+                // never drop the group currently being written to
+                None => break,
+            }
+        }
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    decompress_to_vec(data).map_err(|_| EmulationError::InvalidSaveState)
+}
+
+const COMPRESSION_LEVEL: u8 = 1;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_states_in_reverse_order() {
+        let mut config = RewindConfig::new();
+        config.keyframe_interval = 2;
+        let mut buffer = RewindBuffer::new(config);
+
+        let states: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 64]).collect();
+        for state in &states {
+            buffer.push(state.clone());
+        }
+
+        for state in states.iter().rev() {
+            assert_eq!(buffer.pop().unwrap().as_ref(), Some(state));
+        }
+        assert_eq!(buffer.pop().unwrap(), None);
+    }
+
+    #[test]
+    fn drops_oldest_group_when_over_budget() {
+        let mut config = RewindConfig::new();
+        config.keyframe_interval = 1;
+        config.memory_budget = 1;
+        let mut buffer = RewindBuffer::new(config);
+
+        for i in 0..10u8 {
+            buffer.push(vec![i; 64]);
+        }
+
+        // only the latest keyframe and its delta are kept
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop().unwrap(), Some(vec![9; 64]));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::error::EmulationError;
+
+/// When `GameBoy::run_until` should give control back to the caller
+#[derive(Clone, Debug, Default)]
+pub struct RunCondition {
+    /// Stop when a frame is finished (the PPU enters VBlank)
+    pub frame_end: bool,
+    /// Maximum amount of t-cycles to run
+    pub cycle_budget: Option<u64>,
+    /// Stop before executing the instruction at any of these addresses
+    pub breakpoints: Vec<u16>,
+    /// Stop after a write to any of these addresses
+    pub watchpoints: Vec<u16>,
+}
+
+/// Why `GameBoy::run_until` stopped
+#[derive(Debug)]
+pub enum StopReason {
+    FrameDone,
+    CycleBudgetExhausted,
+    Breakpoint {
+        address: u16,
+    },
+    Watchpoint {
+        address: u16,
+        value: u8,
+    },
+    /// The CPU executed a STOP instruction
+    Stop,
+    Error(EmulationError),
+}
+
+impl RunCondition {
+    pub fn new() -> RunCondition {
+        RunCondition::default()
+    }
+
+    pub fn frame_end() -> RunCondition {
+        RunCondition {
+            frame_end: true,
+            ..RunCondition::default()
+        }
+    }
+
+    pub fn cycles(cycle_budget: u64) -> RunCondition {
+        RunCondition {
+            cycle_budget: Some(cycle_budget),
+            ..RunCondition::default()
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::checksum::crc32;
+use crate::error::{EmulationError, Result};
+
+/// Version of the save state layout. This must be incremented every time the data written
+/// by any of the `save_state` methods changes, so older states get rejected instead of
+/// being loaded into the wrong fields.
+pub const SAVE_STATE_VERSION: u32 = 14;
+
+const SAVE_STATE_MAGIC: &[u8; 4] = b"GBSS";
+const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Information stored at the beginning of every save state
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveStateHeader {
+    pub version: u32,
+    /// Version of the emulator that created the save state
+    pub emulator_version: String,
+    /// CRC-32 of the ROM that was loaded when the save state was created
+    pub rom_hash: u32,
+}
+
+/// Serializes the state of the emulator into a little-endian byte buffer
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+/// Reads data written by `StateWriter` in the same order it was written
+pub struct StateReader<'a> {
+    buffer: &'a [u8],
+    pos: usize,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { buffer: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    /// Writes a buffer with a known size, the reader must know how many bytes to read back
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Writes a buffer preceded by its length
+    pub fn write_vec(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(buffer: &'a [u8]) -> StateReader<'a> {
+        StateReader { buffer, pos: 0 }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        let bytes = self.take(1)?;
+        Ok(bytes[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(EmulationError::InvalidSaveState),
+        }
+    }
+
+    /// Fills `buffer` with the next bytes
+    pub fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let bytes = self.take(buffer.len())?;
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reads a buffer written by `StateWriter::write_vec`
+    pub fn read_vec(&mut self) -> Result<Vec<u8>> {
+        let len = self.read_u32()? as usize;
+        let bytes = self.take(len)?;
+        Ok(bytes.to_vec())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.buffer.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos + len;
+        if end > self.buffer.len() {
+            return Err(EmulationError::InvalidSaveState);
+        }
+
+        let bytes = &self.buffer[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+/// Puts a header in front of the serialized emulator state
+pub fn encode_save_state(rom_hash: u32, payload: &[u8]) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(SAVE_STATE_MAGIC);
+    writer.write_u32(SAVE_STATE_VERSION);
+    writer.write_vec(EMULATOR_VERSION.as_bytes());
+    writer.write_u32(rom_hash);
+    // the checksum lets us reject damaged files before touching the emulator state
+    writer.write_u32(crc32(payload));
+    writer.write_vec(payload);
+
+    writer.into_bytes()
+}
+
+/// Validates a save state and returns its header and the serialized emulator state
+pub fn decode_save_state(data: &[u8]) -> Result<(SaveStateHeader, &[u8])> {
+    let mut reader = StateReader::new(data);
+
+    let mut magic = [0; 4];
+    reader.read_bytes(&mut magic)?;
+    if &magic != SAVE_STATE_MAGIC {
+        return Err(EmulationError::InvalidSaveState);
+    }
+
+    let version = reader.read_u32()?;
+    if version != SAVE_STATE_VERSION {
+        return Err(EmulationError::UnsupportedSaveStateVersion { version });
+    }
+
+    let emulator_version = reader.read_vec()?;
+    let emulator_version = String::from_utf8_lossy(&emulator_version).to_string();
+    let rom_hash = reader.read_u32()?;
+    let checksum = reader.read_u32()?;
+
+    let len = reader.read_u32()? as usize;
+    let payload = reader.take(len)?;
+    if !reader.is_empty() || crc32(payload) != checksum {
+        return Err(EmulationError::InvalidSaveState);
+    }
+
+    let header = SaveStateHeader {
+        version,
+        emulator_version,
+        rom_hash,
+    };
+
+    Ok((header, payload))
+}
@@ -0,0 +1,176 @@
+use std::any::Any;
+
+use crate::error::Result;
+use crate::interrupt::{Interrupt, InterruptRegister};
+use crate::save_state::{StateReader, StateWriter};
+
+/// Something plugged into the link port
+pub trait SerialDevice: Any {
+    /// Exchanges a byte on a transfer clocked by the Game Boy, returns the byte the
+    /// device sent back
+    fn exchange(&mut self, sent: u8) -> u8;
+
+    /// Called as time passes, with the amount of normal speed t-cycles. `waiting` is
+    /// the content of SB while a transfer waits for the device to drive the clock.
+    /// Returns the byte the device clocked in, if it did.
+    fn run(&mut self, _cycles: u32, _waiting: Option<u8>) -> Option<u8> {
+        None
+    }
+}
+
+/// Keeps every byte the Game Boy sends, test ROMs use it to print their results
+pub struct SerialCapture {
+    pub output: Vec<u8>,
+}
+
+impl SerialCapture {
+    pub fn new() -> SerialCapture {
+        SerialCapture { output: Vec::new() }
+    }
+
+    /// Removes the captured bytes
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl Default for SerialCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerialDevice for SerialCapture {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.output.push(sent);
+        // nothing drives the data line
+        0xFF
+    }
+}
+
+/// FF01/FF02 - SB/SC - Serial transfer data and control
+pub struct Serial {
+    /// FF01 - SB - Serial transfer data
+    pub data: u8,
+    is_transferring: bool,
+    is_internal_clock: bool,
+    is_fast_clock: bool,
+    /// T-cycles left until the byte is shifted out when using the internal clock
+    cycles_left: u32,
+    pub device: Option<Box<dyn SerialDevice>>,
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            data: 0,
+            is_transferring: false,
+            is_internal_clock: false,
+            is_fast_clock: false,
+            cycles_left: 0,
+            device: None,
+        }
+    }
+
+    /// Stops any transfer, the device stays plugged
+    pub fn reset(&mut self) {
+        let device = self.device.take();
+        *self = Serial::new();
+        self.device = device;
+    }
+
+    /// Whether a transfer is waiting for something else to drive the clock
+    pub const fn is_waiting_external_clock(&self) -> bool {
+        self.is_transferring && !self.is_internal_clock
+    }
+
+    pub fn read_control(&self, cgb_mode: bool) -> u8 {
+        let transferring = if self.is_transferring { 0x80 } else { 0 };
+        let internal = if self.is_internal_clock { 1 } else { 0 };
+        if cgb_mode {
+            let fast = if self.is_fast_clock { 2 } else { 0 };
+            0x7C | transferring | fast | internal
+        } else {
+            0x7E | transferring | internal
+        }
+    }
+
+    pub fn write_control(&mut self, value: u8, cgb_mode: bool) {
+        self.is_transferring = value & 0x80 != 0;
+        self.is_internal_clock = value & 1 != 0;
+        // the fast clock only exists in CGB mode
+        self.is_fast_clock = cgb_mode && value & 2 != 0;
+        let bit_cycles = if self.is_fast_clock {
+            FAST_BIT_CYCLES
+        } else {
+            BIT_CYCLES
+        };
+        self.cycles_left = 8 * bit_cycles;
+    }
+
+    /// Advances the transfer by the given amount of t-cycles at the CPU speed. The serial
+    /// clock comes from the divider, so it gets twice as fast in double speed mode.
+    pub fn run(
+        &mut self,
+        cycles: u32,
+        is_double_speed: bool,
+        interrupt_flag: &mut InterruptRegister,
+    ) {
+        let waiting = self.is_waiting_external_clock().then_some(self.data);
+        if let Some(device) = &mut self.device {
+            let device_cycles = if is_double_speed { cycles / 2 } else { cycles };
+            if let Some(received) = device.run(device_cycles, waiting) {
+                self.complete(received, interrupt_flag);
+            }
+        }
+
+        if self.is_transferring && self.is_internal_clock {
+            self.cycles_left = self.cycles_left.saturating_sub(cycles);
+            if self.cycles_left == 0 {
+                // with nothing plugged in the data line stays high
+                let received = match &mut self.device {
+                    Some(device) => device.exchange(self.data),
+                    None => 0xFF,
+                };
+                self.complete(received, interrupt_flag);
+            }
+        }
+    }
+
+    fn complete(&mut self, received: u8, interrupt_flag: &mut InterruptRegister) {
+        self.data = received;
+        self.is_transferring = false;
+        interrupt_flag.request(Interrupt::Serial);
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.data);
+        writer.write_bool(self.is_transferring);
+        writer.write_bool(self.is_internal_clock);
+        writer.write_bool(self.is_fast_clock);
+        writer.write_u32(self.cycles_left);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.data = reader.read_u8()?;
+        self.is_transferring = reader.read_bool()?;
+        self.is_internal_clock = reader.read_bool()?;
+        self.is_fast_clock = reader.read_bool()?;
+        self.cycles_left = reader.read_u32()?;
+        Ok(())
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// T-cycles per bit with the 8192 Hz clock
+const BIT_CYCLES: u32 = 512;
+/// T-cycles per bit with the 262144 Hz clock of the CGB
+const FAST_BIT_CYCLES: u32 = 16;
+
+pub const SERIAL_DATA_REGISTER: usize = 0xFF01;
+pub const SERIAL_CONTROL_REGISTER: usize = 0xFF02;
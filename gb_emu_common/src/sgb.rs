@@ -0,0 +1,681 @@
+use crate::error::Result;
+use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::joypad::Buttons;
+use crate::palette::{Rgb555, DMG_SHADES};
+use crate::save_state::{StateReader, StateWriter};
+
+/// Width of the picture the SNES outputs, with the border around the Game Boy screen
+pub const SGB_SCREEN_WIDTH: usize = 256;
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+
+/// Super Game Boy. Games send it command packets by toggling the joypad select lines,
+/// to colorize the screen, draw a border and read up to 4 joypads.
+pub struct Sgb {
+    /// The SGB functions only work with games that declare support in their header
+    is_enabled: bool,
+    packet: [u8; PACKET_SIZE],
+    /// Bits received of the current packet, `None` when not receiving
+    received_bits: Option<usize>,
+    /// Packets of the current command
+    command_data: Vec<u8>,
+    /// Last value written to P1
+    last_joypad_write: u8,
+    /// The 4 palettes used on the game screen. Color 0 is shared.
+    pub palettes: [[Rgb555; 4]; 4],
+    /// Palettes transferred with PAL_TRN, selected with PAL_SET
+    system_palettes: Vec<Rgb555>,
+    /// Palette of every tile of the screen
+    attributes: [u8; ATTRIBUTE_MAP_SIZE],
+    /// Attribute maps transferred with ATTR_TRN, 2 bits per tile
+    attribute_files: Vec<u8>,
+    /// SNES 4 bits per pixel tiles of the border
+    border_tiles: Vec<u8>,
+    /// 32x28 tile map of the border and its 4 palettes
+    border_map: Vec<u8>,
+    border_palettes: [[Rgb555; 16]; 4],
+    mask: Mask,
+    /// Shades of the screen frozen by MASK_EN
+    frozen_screen: Vec<u8>,
+    /// VRAM transfer that reads the next frame
+    pending_transfer: Option<Transfer>,
+    player_count: u8,
+    current_player: u8,
+    /// Buttons of players 2 to 4
+    pub other_players: [Buttons; 3],
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mask {
+    None,
+    Freeze,
+    Black,
+    Color0,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Transfer {
+    Palettes,
+    Tiles { is_high_half: bool },
+    Border,
+    Attributes,
+}
+
+impl Sgb {
+    pub fn new(is_enabled: bool) -> Sgb {
+        Sgb {
+            is_enabled,
+            packet: [0; PACKET_SIZE],
+            received_bits: None,
+            command_data: Vec::new(),
+            last_joypad_write: 0x30,
+            palettes: [DMG_SHADES; 4],
+            system_palettes: vec![0; SYSTEM_PALETTE_AMOUNT * 4],
+            attributes: [0; ATTRIBUTE_MAP_SIZE],
+            attribute_files: vec![0; ATTRIBUTE_FILE_AMOUNT * ATTRIBUTE_FILE_SIZE],
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: [[0; 16]; 4],
+            mask: Mask::None,
+            frozen_screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            pending_transfer: None,
+            player_count: 1,
+            current_player: 0,
+            other_players: [Buttons::new(); 3],
+        }
+    }
+
+    /// Watches the writes to P1 for packet bits and joypad switches
+    pub fn write_joypad(&mut self, value: u8) {
+        let lines = value & 0x30;
+        let previous_lines = self.last_joypad_write & 0x30;
+        self.last_joypad_write = value;
+        if lines == previous_lines {
+            return;
+        }
+
+        match lines {
+            // both lines low resets the transfer and starts a new packet
+            0x00 => {
+                self.received_bits = Some(0);
+                self.packet = [0; PACKET_SIZE];
+            }
+            // P14 low sends a 0, P15 low sends a 1
+            0x20 | 0x10 => {
+                if let Some(bits) = self.received_bits {
+                    self.receive_bit(bits, lines == 0x10);
+                }
+            }
+            // both lines high between bits, this also moves to the next joypad
+            _ => {
+                if previous_lines == 0x10 && self.received_bits.is_none() {
+                    self.current_player = (self.current_player + 1) % self.player_count;
+                }
+            }
+        }
+    }
+
+    fn receive_bit(&mut self, bits: usize, bit: bool) {
+        if bits == PACKET_SIZE * 8 {
+            // the stop bit after the 128 data bits
+            self.received_bits = None;
+            self.packet_done();
+            return;
+        }
+
+        if bit {
+            self.packet[bits / 8] |= 1 << (bits % 8);
+        }
+        self.received_bits = Some(bits + 1);
+    }
+
+    fn packet_done(&mut self) {
+        if self.command_data.is_empty() && self.packet[0] & 0x07 == 0 {
+            // a command is at least one packet long
+            return;
+        }
+
+        self.command_data.extend_from_slice(&self.packet);
+        let packet_amount = (self.command_data[0] & 0x07) as usize;
+        if self.command_data.len() >= packet_amount * PACKET_SIZE {
+            let data = std::mem::take(&mut self.command_data);
+            if self.is_enabled {
+                self.run_command(&data);
+            }
+        }
+    }
+
+    fn run_command(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palette_pair(0, 1, data),
+            PAL23 => self.set_palette_pair(2, 3, data),
+            PAL03 => self.set_palette_pair(0, 3, data),
+            PAL12 => self.set_palette_pair(1, 2, data),
+            ATTR_BLK => self.attribute_blocks(data),
+            ATTR_LIN => self.attribute_lines(data),
+            ATTR_DIV => self.attribute_division(data),
+            ATTR_CHR => self.attribute_characters(data),
+            PAL_SET => self.palette_set(data),
+            PAL_TRN => self.pending_transfer = Some(Transfer::Palettes),
+            MLT_REQ => {
+                self.player_count = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.current_player = 0;
+            }
+            CHR_TRN => {
+                let is_high_half = data[1] & 1 != 0;
+                self.pending_transfer = Some(Transfer::Tiles { is_high_half });
+            }
+            PCT_TRN => self.pending_transfer = Some(Transfer::Border),
+            ATTR_TRN => self.pending_transfer = Some(Transfer::Attributes),
+            ATTR_SET => {
+                self.apply_attribute_file(data[1] & 0x3F);
+                if data[1] & 0x40 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            MASK_EN => {
+                self.mask = match data[1] & 0x03 {
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    3 => Mask::Color0,
+                    _ => Mask::None,
+                };
+            }
+            // sound, SNES code and the rest aren't emulated
+            _ => {}
+        }
+    }
+
+    fn set_palette_pair(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |index: usize| u16::from_le_bytes([data[1 + index * 2], data[2 + index * 2]]);
+        // color 0 is shared by every palette
+        for palette in &mut self.palettes {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    fn palette_set(&mut self, data: &[u8]) {
+        for palette in 0..4 {
+            let index = u16::from_le_bytes([data[1 + palette * 2], data[2 + palette * 2]]);
+            let start = (index as usize % SYSTEM_PALETTE_AMOUNT) * 4;
+            self.palettes[palette].copy_from_slice(&self.system_palettes[start..start + 4]);
+        }
+        let shared = self.palettes[0][0];
+        for palette in &mut self.palettes {
+            palette[0] = shared;
+        }
+
+        let flags = data[9];
+        if flags & 0x80 != 0 {
+            self.apply_attribute_file(flags & 0x3F);
+        }
+        if flags & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let amount = (data[1] & 0x1F) as usize;
+        for block in data[2..].chunks_exact(6).take(amount) {
+            let control = block[0] & 0x07;
+            let inside = block[1] & 0x03;
+            let border = (block[1] >> 2) & 0x03;
+            let outside = (block[1] >> 4) & 0x03;
+            // with only the inside or the outside changed, the border goes with it
+            let border = match control {
+                0b001 => Some(inside),
+                0b100 => Some(outside),
+                _ if control & 0b010 != 0 => Some(border),
+                _ => None,
+            };
+            let (x1, y1, x2, y2) = (block[2], block[3], block[4], block[5]);
+
+            for y in 0..ATTRIBUTE_MAP_HEIGHT as u8 {
+                for x in 0..ATTRIBUTE_MAP_WIDTH as u8 {
+                    let is_inside = x > x1 && x < x2 && y > y1 && y < y2;
+                    let is_outside = x < x1 || x > x2 || y < y1 || y > y2;
+                    let palette = if is_inside {
+                        (control & 0b001 != 0).then_some(inside)
+                    } else if is_outside {
+                        (control & 0b100 != 0).then_some(outside)
+                    } else {
+                        border
+                    };
+
+                    if let Some(palette) = palette {
+                        self.set_attribute(x as usize, y as usize, palette);
+                    }
+                }
+            }
+        }
+    }
+
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let amount = data[1] as usize;
+        for &line in data[2..].iter().take(amount) {
+            let index = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0x03;
+            if line & 0x80 != 0 {
+                for x in 0..ATTRIBUTE_MAP_WIDTH {
+                    self.set_attribute(x, index, palette);
+                }
+            } else {
+                for y in 0..ATTRIBUTE_MAP_HEIGHT {
+                    self.set_attribute(index, y, palette);
+                }
+            }
+        }
+    }
+
+    fn attribute_division(&mut self, data: &[u8]) {
+        let after = data[1] & 0x03;
+        let before = (data[1] >> 2) & 0x03;
+        let on_line = (data[1] >> 4) & 0x03;
+        let is_horizontal = data[1] & 0x40 != 0;
+        let line = data[2] as usize;
+
+        for y in 0..ATTRIBUTE_MAP_HEIGHT {
+            for x in 0..ATTRIBUTE_MAP_WIDTH {
+                let position = if is_horizontal { y } else { x };
+                let palette = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let mut x = data[1] as usize % ATTRIBUTE_MAP_WIDTH;
+        let mut y = data[2] as usize % ATTRIBUTE_MAP_HEIGHT;
+        let amount = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let is_vertical = data[5] & 1 != 0;
+
+        for i in 0..amount.min(ATTRIBUTE_MAP_SIZE) {
+            let Some(&byte) = data.get(6 + i / 4) else {
+                break;
+            };
+            let palette = (byte >> (6 - (i % 4) * 2)) & 0x03;
+            self.set_attribute(x, y, palette);
+
+            if is_vertical {
+                y += 1;
+                if y == ATTRIBUTE_MAP_HEIGHT {
+                    y = 0;
+                    x = (x + 1) % ATTRIBUTE_MAP_WIDTH;
+                }
+            } else {
+                x += 1;
+                if x == ATTRIBUTE_MAP_WIDTH {
+                    x = 0;
+                    y = (y + 1) % ATTRIBUTE_MAP_HEIGHT;
+                }
+            }
+        }
+    }
+
+    fn apply_attribute_file(&mut self, file: u8) {
+        let file = file as usize;
+        if file >= ATTRIBUTE_FILE_AMOUNT {
+            return;
+        }
+
+        let start = file * ATTRIBUTE_FILE_SIZE;
+        for i in 0..ATTRIBUTE_MAP_SIZE {
+            let byte = self.attribute_files[start + i / 4];
+            self.attributes[i] = (byte >> (6 - (i % 4) * 2)) & 0x03;
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < ATTRIBUTE_MAP_WIDTH && y < ATTRIBUTE_MAP_HEIGHT {
+            self.attributes[y * ATTRIBUTE_MAP_WIDTH + x] = palette;
+        }
+    }
+
+    /// Reads P1 while several joypads are connected. Returns `None` when the normal
+    /// joypad register should be read.
+    pub fn read_joypad(&self) -> Option<u8> {
+        if self.player_count == 1 {
+            return None;
+        }
+
+        // with both groups deselected, the lower bits tell which joypad is selected
+        if self.last_joypad_write & 0x30 == 0x30 {
+            Some(0xF0 | (0x0F - self.current_player))
+        } else {
+            None
+        }
+    }
+
+    /// Buttons of the joypad being read, `None` for the first player
+    pub fn current_player_buttons(&self) -> Option<Buttons> {
+        match self.current_player {
+            0 => None,
+            player => Some(self.other_players[player as usize - 1]),
+        }
+    }
+
+    /// Does the VRAM transfer requested in the last frame, reading the screen
+    pub fn frame_done(&mut self, framebuffer: &[Rgb555]) {
+        if self.mask != Mask::Freeze {
+            for (shade, &color) in self.frozen_screen.iter_mut().zip(framebuffer) {
+                *shade = shade_of(color);
+            }
+        }
+
+        let Some(transfer) = self.pending_transfer.take() else {
+            return;
+        };
+        let data = vram_transfer_data(framebuffer);
+        match transfer {
+            Transfer::Palettes => {
+                for (color, bytes) in self.system_palettes.iter_mut().zip(data.chunks_exact(2)) {
+                    *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+            }
+            Transfer::Tiles { is_high_half } => {
+                let start = if is_high_half { TRANSFER_SIZE } else { 0 };
+                self.border_tiles[start..start + TRANSFER_SIZE].copy_from_slice(&data);
+            }
+            Transfer::Border => {
+                self.border_map.copy_from_slice(&data[..BORDER_MAP_SIZE]);
+                let palettes = &data[BORDER_PALETTES_START..];
+                for (i, bytes) in palettes.chunks_exact(2).take(4 * 16).enumerate() {
+                    self.border_palettes[i / 16][i % 16] = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+            }
+            Transfer::Attributes => {
+                let size = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..size]);
+            }
+        }
+    }
+
+    /// Draws the border with the colorized Game Boy screen in the middle
+    pub fn render(&self, framebuffer: &[Rgb555]) -> Vec<Rgb555> {
+        let background = self.palettes[0][0];
+        let mut screen = vec![background; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT];
+        self.render_border(&mut screen);
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let index = y * SCREEN_WIDTH + x;
+                let shade = match self.mask {
+                    Mask::Freeze => self.frozen_screen[index],
+                    _ => shade_of(framebuffer[index]),
+                };
+                let palette = self.attributes[(y / 8) * ATTRIBUTE_MAP_WIDTH + x / 8] as usize;
+                let color = match self.mask {
+                    Mask::Black => 0,
+                    Mask::Color0 => background,
+                    _ => self.palettes[palette][shade as usize],
+                };
+                screen[(y + SCREEN_Y) * SGB_SCREEN_WIDTH + x + SCREEN_X] = color;
+            }
+        }
+
+        screen
+    }
+
+    fn render_border(&self, screen: &mut [Rgb555]) {
+        for (entry, bytes) in self.border_map.chunks_exact(2).enumerate() {
+            let entry_x = (entry % 32) * 8;
+            let entry_y = (entry / 32) * 8;
+            let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let tile = (value & 0xFF) as usize * 32;
+            let palette = ((value >> 10) & 0x07) as usize;
+            let flip_x = value & 0x4000 != 0;
+            let flip_y = value & 0x8000 != 0;
+
+            for y in 0..8 {
+                let tile_y = if flip_y { 7 - y } else { y };
+                let planes = [
+                    self.border_tiles[tile + tile_y * 2],
+                    self.border_tiles[tile + tile_y * 2 + 1],
+                    self.border_tiles[tile + 16 + tile_y * 2],
+                    self.border_tiles[tile + 16 + tile_y * 2 + 1],
+                ];
+                for x in 0..8 {
+                    let bit = if flip_x { x } else { 7 - x };
+                    let color = planes.iter().enumerate().fold(0, |color, (plane, &byte)| {
+                        color | ((byte >> bit) & 1) << plane
+                    });
+                    // color 0 is transparent, and only palettes 4 to 7 are for the border
+                    if color != 0 && (4..8).contains(&palette) {
+                        let pixel = (entry_y + y) * SGB_SCREEN_WIDTH + entry_x + x;
+                        screen[pixel] = self.border_palettes[palette - 4][color as usize];
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        writer.write_bytes(&self.packet);
+        writer.write_u16(self.received_bits.map_or(u16::MAX, |bits| bits as u16));
+        writer.write_vec(&self.command_data);
+        writer.write_u8(self.last_joypad_write);
+        for palette in &self.palettes {
+            for &color in palette {
+                writer.write_u16(color);
+            }
+        }
+        for &color in &self.system_palettes {
+            writer.write_u16(color);
+        }
+        writer.write_bytes(&self.attributes);
+        writer.write_bytes(&self.attribute_files);
+        writer.write_bytes(&self.border_tiles);
+        writer.write_bytes(&self.border_map);
+        for palette in &self.border_palettes {
+            for &color in palette {
+                writer.write_u16(color);
+            }
+        }
+        writer.write_u8(self.mask as u8);
+        writer.write_bytes(&self.frozen_screen);
+        let transfer = match self.pending_transfer {
+            None => 0,
+            Some(Transfer::Palettes) => 1,
+            Some(Transfer::Tiles {
+                is_high_half: false,
+            }) => 2,
+            Some(Transfer::Tiles { is_high_half: true }) => 3,
+            Some(Transfer::Border) => 4,
+            Some(Transfer::Attributes) => 5,
+        };
+        writer.write_u8(transfer);
+        writer.write_u8(self.player_count);
+        writer.write_u8(self.current_player);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.is_enabled = reader.read_bool()?;
+        reader.read_bytes(&mut self.packet)?;
+        let received_bits = reader.read_u16()?;
+        self.received_bits =
+            (received_bits as usize <= PACKET_SIZE * 8).then_some(received_bits as usize);
+        self.command_data = reader.read_vec()?;
+        self.command_data.truncate(7 * PACKET_SIZE);
+        self.last_joypad_write = reader.read_u8()?;
+        for palette in &mut self.palettes {
+            for color in palette {
+                *color = reader.read_u16()?;
+            }
+        }
+        for color in &mut self.system_palettes {
+            *color = reader.read_u16()?;
+        }
+        reader.read_bytes(&mut self.attributes)?;
+        for attribute in &mut self.attributes {
+            *attribute &= 0x03;
+        }
+        reader.read_bytes(&mut self.attribute_files)?;
+        reader.read_bytes(&mut self.border_tiles)?;
+        reader.read_bytes(&mut self.border_map)?;
+        for palette in &mut self.border_palettes {
+            for color in palette {
+                *color = reader.read_u16()?;
+            }
+        }
+        self.mask = match reader.read_u8()? {
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            3 => Mask::Color0,
+            _ => Mask::None,
+        };
+        reader.read_bytes(&mut self.frozen_screen)?;
+        for shade in &mut self.frozen_screen {
+            *shade &= 0x03;
+        }
+        self.pending_transfer = match reader.read_u8()? {
+            1 => Some(Transfer::Palettes),
+            2 => Some(Transfer::Tiles {
+                is_high_half: false,
+            }),
+            3 => Some(Transfer::Tiles { is_high_half: true }),
+            4 => Some(Transfer::Border),
+            5 => Some(Transfer::Attributes),
+            _ => None,
+        };
+        self.player_count = match reader.read_u8()? {
+            2 => 2,
+            4 => 4,
+            _ => 1,
+        };
+        self.current_player = reader.read_u8()? % self.player_count;
+        Ok(())
+    }
+}
+
+/// Shade of a pixel of the DMG framebuffer
+fn shade_of(color: Rgb555) -> u8 {
+    DMG_SHADES
+        .iter()
+        .position(|&shade| shade == color)
+        .unwrap_or(0) as u8
+}
+
+/// The 4KB a VRAM transfer reads: the screen turned back into 256 tiles of 2 bits per
+/// pixel, going through the screen tiles from left to right and top to bottom
+fn vram_transfer_data(framebuffer: &[Rgb555]) -> Vec<u8> {
+    let mut data = vec![0; TRANSFER_SIZE];
+    for (tile, bytes) in data.chunks_exact_mut(16).enumerate() {
+        let tile_x = (tile % ATTRIBUTE_MAP_WIDTH) * 8;
+        let tile_y = (tile / ATTRIBUTE_MAP_WIDTH) * 8;
+        for y in 0..8 {
+            for x in 0..8 {
+                let shade = shade_of(framebuffer[(tile_y + y) * SCREEN_WIDTH + tile_x + x]);
+                bytes[y * 2] |= (shade & 1) << (7 - x);
+                bytes[y * 2 + 1] |= (shade >> 1) << (7 - x);
+            }
+        }
+    }
+    data
+}
+
+const PACKET_SIZE: usize = 16;
+const TRANSFER_SIZE: usize = 0x1000;
+const SYSTEM_PALETTE_AMOUNT: usize = 512;
+const ATTRIBUTE_MAP_WIDTH: usize = SCREEN_WIDTH / 8;
+const ATTRIBUTE_MAP_HEIGHT: usize = SCREEN_HEIGHT / 8;
+const ATTRIBUTE_MAP_SIZE: usize = ATTRIBUTE_MAP_WIDTH * ATTRIBUTE_MAP_HEIGHT;
+const ATTRIBUTE_FILE_AMOUNT: usize = 45;
+/// 2 bits for each tile of the screen
+const ATTRIBUTE_FILE_SIZE: usize = ATTRIBUTE_MAP_SIZE / 4;
+const BORDER_TILES_SIZE: usize = 2 * TRANSFER_SIZE;
+const BORDER_MAP_SIZE: usize = 32 * 28 * 2;
+const BORDER_PALETTES_START: usize = 0x800;
+/// Position of the Game Boy screen inside the border
+const SCREEN_X: usize = (SGB_SCREEN_WIDTH - SCREEN_WIDTH) / 2;
+const SCREEN_Y: usize = (SGB_SCREEN_HEIGHT - SCREEN_HEIGHT) / 2;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a packet bit by bit like a game does
+    fn send_packet(sgb: &mut Sgb, packet: [u8; PACKET_SIZE]) {
+        sgb.write_joypad(0x00);
+        sgb.write_joypad(0x30);
+        for i in 0..PACKET_SIZE * 8 {
+            let bit = packet[i / 8] >> (i % 8) & 1;
+            sgb.write_joypad(if bit == 1 { 0x10 } else { 0x20 });
+            sgb.write_joypad(0x30);
+        }
+        // stop bit
+        sgb.write_joypad(0x20);
+        sgb.write_joypad(0x30);
+    }
+
+    #[test]
+    fn colorizes_the_screen() {
+        let mut sgb = Sgb::new(true);
+        let mut packet = [0; PACKET_SIZE];
+        // PAL01 with color 0 red and palette 1 color 3 blue
+        packet[0] = (PAL01 << 3) | 1;
+        packet[1..3].copy_from_slice(&0x001Fu16.to_le_bytes());
+        packet[13..15].copy_from_slice(&0x7C00u16.to_le_bytes());
+        send_packet(&mut sgb, packet);
+
+        // ATTR_DIV, left half with palette 0, right half with palette 1
+        let mut packet = [0; PACKET_SIZE];
+        packet[0] = (ATTR_DIV << 3) | 1;
+        packet[1] = 0b0001_0001;
+        packet[2] = 10;
+        send_packet(&mut sgb, packet);
+
+        let mut framebuffer = vec![DMG_SHADES[0]; SCREEN_WIDTH * SCREEN_HEIGHT];
+        framebuffer[SCREEN_WIDTH - 1] = DMG_SHADES[3];
+        let screen = sgb.render(&framebuffer);
+        assert_eq!(screen.len(), SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT);
+        let top_left = SCREEN_Y * SGB_SCREEN_WIDTH + SCREEN_X;
+        assert_eq!(screen[top_left], 0x001F);
+        assert_eq!(screen[top_left + SCREEN_WIDTH - 1], 0x7C00);
+    }
+
+    #[test]
+    fn switches_joypads() {
+        let mut sgb = Sgb::new(true);
+        let mut packet = [0; PACKET_SIZE];
+        packet[0] = (MLT_REQ << 3) | 1;
+        packet[1] = 1;
+        send_packet(&mut sgb, packet);
+        assert_eq!(sgb.read_joypad(), Some(0xFF));
+
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x30);
+        assert_eq!(sgb.read_joypad(), Some(0xFE));
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x30);
+        assert_eq!(sgb.read_joypad(), Some(0xFF));
+    }
+}
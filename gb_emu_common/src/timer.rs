@@ -1,7 +1,9 @@
 use core::panic;
 use std::convert;
 
-use crate::interrupt::InterruptRegister;
+use crate::error::Result;
+use crate::interrupt::{Interrupt, InterruptRegister};
+use crate::save_state::{StateReader, StateWriter};
 
 pub struct Timers {
     /// FF04 - DIV - Divider Register (R/W)
@@ -16,15 +18,17 @@ pub struct Timers {
     pub interrupt_flag_register: InterruptRegister,
     /// FFFF - IE - Interrupt Enable (R/W)
     pub interrupt_enable_register: InterruptRegister,
-    /// Cycle counter in t-cycles
+    /// Cycle counter in t-cycles, DIV is its upper byte
     cycle_count: u16,
 }
 
+#[derive(Clone, Copy)]
 pub struct TimerControl {
     enable: bool,
     speed: CpuSpeed,
 }
 
+#[derive(Clone, Copy)]
 pub enum CpuSpeed {
     Clock1024,
     Clock16,
@@ -45,25 +49,84 @@ impl Timers {
         }
     }
 
-    pub fn run(&mut self, cycles: u16) {
-        let old_cycle_count = self.cycle_count;
-        self.cycle_count = self.cycle_count.wrapping_add(cycles);
+    pub fn run(&mut self, cycles: u32) {
+        // The counter is advanced one m-cycle (4 t-cycles) at a time, since TIMA is
+        // incremented when the bit selected by TAC goes from 1 to 0
+        for _ in 0..cycles / 4 {
+            let old_cycle_count = self.cycle_count;
+            self.cycle_count = self.cycle_count.wrapping_add(4);
+
+            // Check if the timer is enabled
+            if self.timer_control.enable {
+                let bit = self.timer_control.speed.to_u16() / 2;
+                let falling_edge = old_cycle_count & bit != 0 && self.cycle_count & bit == 0;
+
+                if falling_edge {
+                    self.increment_timer_counter();
+                }
+            }
+        }
 
-        let diff = self.cycle_count / 64 - old_cycle_count / 64;
-        self.divider_register = self.divider_register.wrapping_add(diff as u8);
+        self.divider_register = (self.cycle_count >> 8) as u8;
+    }
 
-        // Check if the timer is enabled
-        if self.timer_control.enable {
-            let frequency = self.timer_control.speed.to_u16();
-            let diff = self.cycle_count / frequency - old_cycle_count / frequency;
+    fn increment_timer_counter(&mut self) {
+        let (next_timer_counter, did_overflow) = self.timer_counter.overflowing_add(1);
+        self.timer_counter = next_timer_counter;
+        if did_overflow {
+            self.timer_counter = self.timer_modulo;
+            self.interrupt_flag_register.request(Interrupt::Timer);
+        }
+    }
 
-            let (next_timer_counter, did_overflow) = self.timer_counter.overflowing_add(diff as u8);
-            self.timer_counter = next_timer_counter;
-            if did_overflow {
-                // TODO: Request a Timer Interrupt
-                self.timer_counter = self.timer_modulo;
-            }
+    pub const fn cycle_count(&self) -> u16 {
+        self.cycle_count
+    }
+
+    /// Sets the internal counter without clocking TIMA, DIV is its upper byte
+    pub fn set_cycle_count(&mut self, cycle_count: u16) {
+        self.cycle_count = cycle_count;
+        self.divider_register = (cycle_count >> 8) as u8;
+    }
+
+    /// Writing any value to DIV resets the whole internal counter
+    pub fn reset_divider(&mut self) {
+        let bit = self.timer_control.speed.to_u16() / 2;
+        if self.timer_control.enable && self.cycle_count & bit != 0 {
+            // resetting the counter can also cause a falling edge
+            self.increment_timer_counter();
         }
+
+        self.cycle_count = 0;
+        self.divider_register = 0;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.divider_register);
+        writer.write_u8(self.timer_counter);
+        writer.write_u8(self.timer_modulo);
+        writer.write_u8(self.timer_control.into());
+        writer.write_u8(self.interrupt_flag_register.into());
+        writer.write_u8(self.interrupt_enable_register.into());
+        writer.write_u16(self.cycle_count);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.divider_register = reader.read_u8()?;
+        self.timer_counter = reader.read_u8()?;
+        self.timer_modulo = reader.read_u8()?;
+        self.timer_control = reader.read_u8()?.into();
+        self.interrupt_flag_register = reader.read_u8()?.into();
+        self.interrupt_enable_register = reader.read_u8()?.into();
+        self.cycle_count = reader.read_u16()?;
+
+        Ok(())
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -73,9 +136,15 @@ impl TimerControl {
     }
 }
 
+impl Default for TimerControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CpuSpeed {
-    fn to_u16(&self) -> u16 {
-        match *self {
+    fn to_u16(self) -> u16 {
+        match self {
             CpuSpeed::Clock1024 => 1024,
             CpuSpeed::Clock16 => 16,
             CpuSpeed::Clock256 => 256,
@@ -101,9 +170,9 @@ impl convert::From<TimerControl> for u8 {
 impl convert::From<u8> for TimerControl {
     fn from(byte: u8) -> Self {
         let enable = (byte & 0b00000100) >> 2;
-        let enable = if enable != 0 { true } else { false };
+        let enable = enable != 0;
 
-        let speed = byte & 0x00000011;
+        let speed = byte & 0b00000011;
         let speed = match speed {
             0b00 => CpuSpeed::Clock1024,
             0b01 => CpuSpeed::Clock16,
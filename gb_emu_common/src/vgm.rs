@@ -0,0 +1,124 @@
+use crate::apu::{APU_CLOCK, REGISTERS_START};
+
+/// Logs the writes to the sound registers as a VGM file, which VGM players can play
+/// back with their own emulation of the Game Boy sound chip
+pub struct VgmLogger {
+    commands: Vec<u8>,
+    /// T-cycles since the start of the log, at normal speed
+    cycles: u64,
+    /// Samples waited for in the commands so far
+    samples: u64,
+}
+
+const VGM_VERSION: u32 = 0x0171;
+/// VGM timestamps are in samples at 44.1 kHz
+const VGM_SAMPLE_RATE: u64 = 44_100;
+const HEADER_SIZE: usize = 0x100;
+/// Offset of the field that points to the commands, which is relative to itself
+const DATA_OFFSET_FIELD: usize = 0x34;
+const GAME_BOY_CLOCK_FIELD: usize = 0x80;
+
+const WRITE_COMMAND: u8 = 0xB3;
+const WAIT_COMMAND: u8 = 0x61;
+const WAIT_60HZ_FRAME_COMMAND: u8 = 0x62;
+const WAIT_50HZ_FRAME_COMMAND: u8 = 0x63;
+/// 0x70 to 0x7F wait from 1 to 16 samples
+const SHORT_WAIT_COMMAND: u8 = 0x70;
+const END_COMMAND: u8 = 0x66;
+
+impl VgmLogger {
+    pub fn new() -> VgmLogger {
+        VgmLogger {
+            commands: Vec::new(),
+            cycles: 0,
+            samples: 0,
+        }
+    }
+
+    /// Advances the time by T-cycles at normal speed
+    pub fn run(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+
+    /// Logs a write to a sound register or the wave RAM
+    pub fn log_write(&mut self, address: usize, value: u8) {
+        self.wait();
+        let register = (address - REGISTERS_START) as u8;
+        self.commands
+            .extend_from_slice(&[WRITE_COMMAND, register, value]);
+    }
+
+    /// Adds the wait commands up to the current time
+    fn wait(&mut self) {
+        let target = self.cycles * VGM_SAMPLE_RATE / APU_CLOCK as u64;
+        while self.samples < target {
+            let samples = (target - self.samples).min(u16::MAX as u64);
+            match samples {
+                735 => self.commands.push(WAIT_60HZ_FRAME_COMMAND),
+                882 => self.commands.push(WAIT_50HZ_FRAME_COMMAND),
+                1..=16 => self.commands.push(SHORT_WAIT_COMMAND + samples as u8 - 1),
+                _ => {
+                    self.commands.push(WAIT_COMMAND);
+                    self.commands
+                        .extend_from_slice(&(samples as u16).to_le_bytes());
+                }
+            }
+            self.samples += samples;
+        }
+    }
+
+    /// Ends the log, returning the VGM file
+    pub fn finish(mut self) -> Vec<u8> {
+        self.wait();
+        self.commands.push(END_COMMAND);
+
+        let mut file = vec![0; HEADER_SIZE];
+        let file_size = (HEADER_SIZE + self.commands.len()) as u32;
+        let data_offset = (HEADER_SIZE - DATA_OFFSET_FIELD) as u32;
+        file[0x00..0x04].copy_from_slice(b"Vgm ");
+        file[0x04..0x08].copy_from_slice(&(file_size - 4).to_le_bytes());
+        file[0x08..0x0C].copy_from_slice(&VGM_VERSION.to_le_bytes());
+        file[0x18..0x1C].copy_from_slice(&(self.samples as u32).to_le_bytes());
+        file[DATA_OFFSET_FIELD..DATA_OFFSET_FIELD + 4].copy_from_slice(&data_offset.to_le_bytes());
+        file[GAME_BOY_CLOCK_FIELD..GAME_BOY_CLOCK_FIELD + 4]
+            .copy_from_slice(&APU_CLOCK.to_le_bytes());
+        file.extend_from_slice(&self.commands);
+        file
+    }
+}
+
+impl Default for VgmLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_timed_writes() {
+        let mut logger = VgmLogger::new();
+        logger.log_write(0xFF26, 0x80);
+        // a 60 Hz frame
+        logger.run(APU_CLOCK / 60 + 1);
+        logger.log_write(0xFF30, 0x12);
+        logger.run(APU_CLOCK / 44_100 * 3);
+        let file = logger.finish();
+
+        assert_eq!(&file[0..4], b"Vgm ");
+        assert_eq!(
+            u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize,
+            file.len() - 4
+        );
+        assert_eq!(
+            u32::from_le_bytes(file[0x80..0x84].try_into().unwrap()),
+            APU_CLOCK
+        );
+        assert_eq!(
+            &file[HEADER_SIZE..],
+            &[0xB3, 0x16, 0x80, 0x62, 0xB3, 0x20, 0x12, 0x72, 0x66]
+        );
+    }
+}
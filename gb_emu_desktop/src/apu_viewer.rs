@@ -0,0 +1,129 @@
+use egui::{Color32, Sense, Stroke, Ui};
+use gb_emu_common::apu::envelope::{Envelope, LengthCounter};
+use gb_emu_common::apu::oscilloscope::{Oscilloscope, OSCILLOSCOPE_LENGTH};
+use gb_emu_common::apu::{Apu, CHANNEL_NAMES};
+
+use crate::State;
+
+const DUTY_NAMES: [&str; 4] = ["12.5%", "25%", "50%", "75%"];
+const WAVE_VOLUMES: [&str; 4] = ["0%", "100%", "50%", "25%"];
+const TRACE_SIZE: (f32, f32) = (256.0, 48.0);
+
+/// Shows the state of each sound channel, with buttons to mute them or hear them alone
+pub fn show_apu_window(ctx: &egui::CtxRef, state: &mut State) {
+    let apu = &mut state.gb.cpu.bus.apu;
+    if !state.show_apu_window {
+        // the traces are only recorded while somebody looks at them
+        apu.oscilloscope = None;
+        return;
+    }
+    if apu.oscilloscope.is_none() {
+        apu.oscilloscope = Some(Oscilloscope::new());
+    }
+
+    egui::Window::new("Sound channels")
+        .open(&mut state.show_apu_window)
+        .show(ctx, |ui| {
+            for channel in 0..4 {
+                if channel > 0 {
+                    ui.separator();
+                }
+                show_channel(ui, apu, channel);
+            }
+        });
+}
+
+fn show_channel(ui: &mut Ui, apu: &mut Apu, channel: usize) {
+    ui.horizontal(|ui| {
+        ui.strong(CHANNEL_NAMES[channel]);
+        ui.checkbox(&mut apu.muted_channels[channel], "Mute");
+        let mut is_solo = apu.solo_channel == Some(channel);
+        if ui.checkbox(&mut is_solo, "Solo").changed() {
+            apu.solo_channel = is_solo.then_some(channel);
+        }
+    });
+
+    match channel {
+        0 | 1 => {
+            let square = if channel == 0 {
+                &apu.square1
+            } else {
+                &apu.square2
+            };
+            show_status(ui, square.is_enabled, square.frequency_hz(), &square.length);
+            show_envelope(ui, &square.envelope);
+            ui.label(format!("Duty: {}", DUTY_NAMES[square.duty() as usize]));
+        }
+
+        2 => {
+            let wave = &apu.wave;
+            show_status(ui, wave.is_enabled, wave.frequency_hz(), &wave.length);
+            ui.label(format!(
+                "Volume: {}, position: {}",
+                WAVE_VOLUMES[wave.volume_code() as usize],
+                wave.position()
+            ));
+            let ram: Vec<String> = wave.ram.iter().map(|byte| format!("{byte:02X}")).collect();
+            ui.monospace(ram.join(" "));
+        }
+
+        _ => {
+            let noise = &apu.noise;
+            show_status(ui, noise.is_enabled, noise.frequency_hz(), &noise.length);
+            show_envelope(ui, &noise.envelope);
+            let width = if noise.is_short_mode() { 7 } else { 15 };
+            ui.label(format!("LFSR: {width} bits"));
+        }
+    }
+
+    if let Some(oscilloscope) = &apu.oscilloscope {
+        show_trace(ui, oscilloscope, channel);
+    }
+}
+
+fn show_status(ui: &mut Ui, is_enabled: bool, frequency: f32, length: &LengthCounter) {
+    let status = if is_enabled { "on" } else { "off" };
+    let length = if length.is_enabled {
+        length.remaining().to_string()
+    } else {
+        String::from("disabled")
+    };
+    ui.label(format!(
+        "Status: {status}, frequency: {frequency:.1} Hz, length: {length}"
+    ));
+}
+
+fn show_envelope(ui: &mut Ui, envelope: &Envelope) {
+    let direction = if envelope.is_increasing() {
+        "up"
+    } else {
+        "down"
+    };
+    let envelope_text = match envelope.pace() {
+        0 => String::from("fixed"),
+        pace => format!("{direction} every {pace}/64 s"),
+    };
+    ui.label(format!(
+        "Volume: {}/15 (initial {}, {envelope_text})",
+        envelope.volume,
+        envelope.initial_volume()
+    ));
+}
+
+/// Draws the recent outputs of the channel, from 0 at the bottom to 15 at the top
+fn show_trace(ui: &mut Ui, oscilloscope: &Oscilloscope, channel: usize) {
+    let (response, painter) = ui.allocate_painter(TRACE_SIZE.into(), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+    let points = oscilloscope.traces[channel]
+        .iter()
+        .enumerate()
+        .map(|(i, &output)| {
+            let x = rect.left() + rect.width() * i as f32 / OSCILLOSCOPE_LENGTH as f32;
+            let y = rect.bottom() - rect.height() * output as f32 / 15.0;
+            egui::pos2(x, y)
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, Stroke::new(1.0, Color32::GREEN)));
+}
@@ -0,0 +1,133 @@
+use gb_emu_common::apu::DEFAULT_SAMPLE_RATE;
+
+#[cfg(not(target_family = "wasm"))]
+use crate::audio_device::AudioDevice;
+
+/// Audio kept queued, enough to not run out between two video frames
+const TARGET_LATENCY_MS: u32 = 50;
+/// Audio queued at most, emulated samples past it are dropped
+const DEVICE_LATENCY_MS: u32 = 150;
+/// The most the sample rate is adjusted by to keep the latency at the target,
+/// small enough to not change the pitch noticeably
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+/// Emulated frames per video frame at most when audio drives the emulation
+const MAX_FRAMES_PER_UPDATE: u32 = 4;
+/// T-cycles per second and per frame of the Game Boy
+const GB_CLOCK: f64 = 4_194_304.0;
+const GB_FRAME_CYCLES: f64 = 70_224.0;
+
+/// What decides when to run the next emulated frame
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pacing {
+    /// One emulated frame per video frame, the sample rate follows to keep the latency
+    Video,
+    /// As many emulated frames as the audio device needs to stay at the target latency
+    Audio,
+}
+
+/// Plays the audio of the emulator. There's no audio in the browser.
+pub struct Audio {
+    #[cfg(not(target_family = "wasm"))]
+    device: Option<AudioDevice>,
+    pub volume: f32,
+    pub is_muted: bool,
+    pub pacing: Pacing,
+}
+
+impl Audio {
+    /// Opens the audio device, the emulator stays silent when there isn't one
+    pub fn new() -> Audio {
+        Audio {
+            #[cfg(not(target_family = "wasm"))]
+            device: AudioDevice::open(DEVICE_LATENCY_MS).ok(),
+            volume: 1.0,
+            is_muted: false,
+            pacing: Pacing::Video,
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        cfg_if::cfg_if! {
+            if #[cfg(not(target_family = "wasm"))] {
+                self.device.is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Sample rate of the audio device, which recordings are made at too
+    pub fn sample_rate(&self) -> u32 {
+        cfg_if::cfg_if! {
+            if #[cfg(not(target_family = "wasm"))] {
+                self.device
+                    .as_ref()
+                    .map_or(DEFAULT_SAMPLE_RATE, |device| device.sample_rate)
+            } else {
+                DEFAULT_SAMPLE_RATE
+            }
+        }
+    }
+
+    /// Stereo frames waiting to be played
+    fn buffered_frames(&self) -> Option<usize> {
+        cfg_if::cfg_if! {
+            if #[cfg(not(target_family = "wasm"))] {
+                self.device.as_ref().map(AudioDevice::buffered_frames)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Difference between the target latency and the queued audio, from -1 when the
+    /// buffer is twice as full as it should be, to 1 when it's empty
+    fn latency_error(&self) -> Option<f64> {
+        let target = (self.sample_rate() * TARGET_LATENCY_MS / 1000) as f64;
+        let buffered = self.buffered_frames()? as f64;
+        Some(((target - buffered) / target).clamp(-1.0, 1.0))
+    }
+
+    /// Sample rate the emulator should generate at. Dynamic rate control: generating
+    /// slightly more or less samples keeps the queued audio at the target latency even
+    /// if the emulation doesn't run exactly at the speed of the audio device.
+    pub fn emulator_sample_rate(&self) -> u32 {
+        let error = self.latency_error().unwrap_or(0.0);
+        (self.sample_rate() as f64 * (1.0 + MAX_RATE_ADJUSTMENT * error)).round() as u32
+    }
+
+    /// Frames to emulate before the next video frame
+    pub fn frames_to_run(&self) -> u32 {
+        let Some(buffered) = self
+            .buffered_frames()
+            .filter(|_| self.pacing == Pacing::Audio)
+        else {
+            return 1;
+        };
+
+        let sample_rate = self.sample_rate() as f64;
+        let target = sample_rate * TARGET_LATENCY_MS as f64 / 1000.0;
+        let frame_samples = sample_rate * GB_FRAME_CYCLES / GB_CLOCK;
+        let missing = (target - buffered as f64).max(0.0);
+        ((missing / frame_samples).ceil() as u32).min(MAX_FRAMES_PER_UPDATE)
+    }
+
+    /// Queues interleaved stereo samples
+    pub fn play(&mut self, mut samples: Vec<f32>) {
+        let volume = if self.is_muted { 0.0 } else { self.volume };
+        for sample in &mut samples {
+            *sample *= volume;
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(device) = &mut self.device {
+            device.write(&samples);
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
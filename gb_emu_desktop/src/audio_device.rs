@@ -0,0 +1,93 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::Result;
+
+/// Default output device of the system. The samples are queued here and played from
+/// the audio thread.
+pub struct AudioDevice {
+    /// Plays while it's kept
+    _stream: Stream,
+    /// Interleaved left and right samples waiting to be played
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    pub sample_rate: u32,
+    /// Stereo frames queued at most, the ones past it are dropped
+    max_frames: usize,
+}
+
+impl AudioDevice {
+    pub fn open(latency_ms: u32) -> Result<AudioDevice> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("No audio device")?;
+        let supported_config = device.default_output_config()?;
+        let sample_format = supported_config.sample_format();
+        let config: StreamConfig = supported_config.into();
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let stream = match sample_format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone())?,
+            _ => return Err("Unsupported audio sample format".into()),
+        };
+        stream.play()?;
+
+        let sample_rate = config.sample_rate.0;
+        Ok(AudioDevice {
+            _stream: stream,
+            queue,
+            sample_rate,
+            max_frames: (sample_rate * latency_ms / 1000) as usize,
+        })
+    }
+
+    /// Queues interleaved stereo samples, dropping the ones that don't fit
+    pub fn write(&mut self, samples: &[f32]) {
+        if let Ok(mut queue) = self.queue.lock() {
+            let free = (self.max_frames * 2).saturating_sub(queue.len());
+            queue.extend(&samples[..samples.len().min(free)]);
+        }
+    }
+
+    /// Stereo frames waiting to be played
+    pub fn buffered_frames(&self) -> usize {
+        self.queue.lock().map_or(0, |queue| queue.len() / 2)
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<Stream> {
+    let channels = config.channels as usize;
+    let stream = device.build_output_stream(
+        config,
+        move |output: &mut [T], _| {
+            output.fill(T::EQUILIBRIUM);
+            let Ok(mut queue) = queue.lock() else {
+                return;
+            };
+            // plays silence when the queue runs out, like when the emulator is paused
+            for frame in output.chunks_mut(channels) {
+                let left = queue.pop_front().unwrap_or(0.0);
+                let right = queue.pop_front().unwrap_or(0.0);
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let value = match (channels, channel) {
+                        (1, _) => (left + right) / 2.0,
+                        (_, 0) => left,
+                        (_, 1) => right,
+                        _ => 0.0,
+                    };
+                    *sample = T::from_sample(value);
+                }
+            }
+        },
+        |_| {},
+        None,
+    )?;
+    Ok(stream)
+}
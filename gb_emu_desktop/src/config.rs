@@ -23,7 +23,7 @@ pub fn save_config(config_file: ConfigFile, content: &str) -> std::io::Result<()
         let config_path = dirs.config_dir();
         let app_config_path = config_path.join("rustboy");
         let config_file_name = config_file.get_file_name();
-        let config_file_path = app_config_path.join(&config_file_name);
+        let config_file_path = app_config_path.join(config_file_name);
         fs::create_dir_all(&app_config_path)?; // create dir if it not exists
 
         let mut file = fs::File::create(&config_file_path)?;
@@ -46,7 +46,7 @@ pub fn read_config(config_file: ConfigFile) -> std::io::Result<Option<String>> {
         let config_path = dirs.config_dir();
         let app_config_path = config_path.join("rustboy");
         let config_file_name = config_file.get_file_name();
-        let config_file_path = app_config_path.join(&config_file_name);
+        let config_file_path = app_config_path.join(config_file_name);
 
         if config_file_path.exists() {
             let content = fs::read_to_string(&config_file_path)?;
@@ -0,0 +1,69 @@
+use gb_emu_common::gbs::Gbs;
+
+use crate::{Result, State};
+
+/// GBS music file being played
+pub struct GbsPlayer {
+    pub gbs: Gbs,
+    /// Track being played, counting from 0
+    pub track: u8,
+}
+
+/// Starts playing the first track of a GBS file
+pub fn open_gbs(state: &mut State, data: &[u8]) -> Result<()> {
+    let gbs = Gbs::parse(data)?;
+    let track = gbs.first_song.min(gbs.song_count - 1);
+    state.gb.load_gbs(&gbs, track)?;
+    state.rom_title = Some(gbs.title.clone());
+    state.rom_info_description = None;
+    state.show_rom_info_window = false;
+    state.gbs_player = Some(GbsPlayer { gbs, track });
+    state.is_running = true;
+    state.show_error = false;
+
+    Ok(())
+}
+
+/// Shows the GBS file info and buttons to change the track
+pub fn show_gbs_window(ctx: &egui::CtxRef, state: &mut State) -> Result<()> {
+    let Some(player) = &mut state.gbs_player else {
+        return Ok(());
+    };
+
+    let mut track = player.track;
+    let mut is_restarting = false;
+    egui::Window::new("GBS player").show(ctx, |ui| {
+        let gbs = &player.gbs;
+        ui.heading(&gbs.title);
+        ui.label(&gbs.author);
+        ui.label(&gbs.copyright);
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(track > 0, egui::Button::new("Previous"))
+                .clicked()
+            {
+                track -= 1;
+            }
+            ui.label(format!("Track {} of {}", track + 1, gbs.song_count));
+            if ui
+                .add_enabled(track + 1 < gbs.song_count, egui::Button::new("Next"))
+                .clicked()
+            {
+                track += 1;
+            }
+        });
+
+        if ui.button("Restart track").clicked() {
+            is_restarting = true;
+        }
+    });
+
+    if track != player.track || is_restarting {
+        player.track = track;
+        state.gb.load_gbs(&player.gbs, track)?;
+    }
+
+    Ok(())
+}
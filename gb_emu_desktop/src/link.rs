@@ -0,0 +1,197 @@
+use gb_emu_common::link::LinkCable;
+use gb_emu_common::rewind::RewindConfig;
+use gb_emu_common::GameBoy;
+
+use crate::State;
+
+#[cfg(not(target_family = "wasm"))]
+use crate::{open_file, preferred_model, Result};
+#[cfg(not(target_family = "wasm"))]
+use directories::UserDirs;
+#[cfg(not(target_family = "wasm"))]
+use gb_emu_common::cartridge::header::Header;
+#[cfg(not(target_family = "wasm"))]
+use gb_emu_common::network_link::NetworkLink;
+#[cfg(not(target_family = "wasm"))]
+use gb_emu_common::printer::Printer;
+#[cfg(not(target_family = "wasm"))]
+use std::net::TcpListener;
+#[cfg(not(target_family = "wasm"))]
+use std::path::PathBuf;
+
+/// Address shown in the menu before the player types one
+pub const DEFAULT_NETWORK_ADDRESS: &str = "127.0.0.1:8765";
+
+/// Second Game Boy, linked to the main one and shown next to it
+pub struct Link {
+    pub gb: GameBoy,
+    pub cable: LinkCable,
+}
+
+/// Loads a ROM on a second Game Boy and connects it to the main one
+#[cfg(not(target_family = "wasm"))]
+pub fn handle_open_player_2_btn_click(state: &mut State) -> Result<()> {
+    let last_folder_path = state.last_used_dir.clone().map(PathBuf::from);
+    let Some(rom_path) = open_file(&last_folder_path)? else {
+        return Ok(());
+    };
+
+    let rom = std::fs::read(rom_path)?;
+    let header = Header::read_rom_header(&rom)?;
+    let mut gb = GameBoy::new();
+    gb.set_model(preferred_model(&header, state.dmg_palette));
+    gb.set_compatibility_palette(state.dmg_palette.compatibility_palette());
+    gb.load_rom(rom)?;
+
+    handle_disconnect_btn_click(state);
+    // rewinding only one of them would break the link
+    state.gb.disable_rewind();
+    let cable = LinkCable::connect(&mut state.gb, &mut gb);
+    state.link = Some(Link { gb, cable });
+    Ok(())
+}
+
+/// Starts waiting for another emulator to join, see `poll_network_host`
+#[cfg(not(target_family = "wasm"))]
+pub fn handle_host_btn_click(state: &mut State) -> Result<()> {
+    handle_disconnect_btn_click(state);
+    let listener = TcpListener::bind(&state.network_address)?;
+    listener.set_nonblocking(true)?;
+    state.network_listener = Some(listener);
+    Ok(())
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn handle_join_btn_click(state: &mut State) -> Result<()> {
+    handle_disconnect_btn_click(state);
+    let link = NetworkLink::join(&state.network_address)?;
+    connect_network_link(state, link);
+    Ok(())
+}
+
+/// Connects the emulator that joined, if there's one
+#[cfg(not(target_family = "wasm"))]
+pub fn poll_network_host(state: &mut State) -> Result<()> {
+    let Some(listener) = &state.network_listener else {
+        return Ok(());
+    };
+
+    match listener.accept() {
+        Ok((stream, _)) => {
+            state.network_listener = None;
+            connect_network_link(state, NetworkLink::new(stream)?);
+            Ok(())
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+        Err(err) => {
+            state.network_listener = None;
+            Err(err.into())
+        }
+    }
+}
+
+/// Unplugs the network link once the connection dropped, so the menu and rewind know
+#[cfg(not(target_family = "wasm"))]
+pub fn poll_network_link(state: &mut State) -> Result<()> {
+    let is_connected = state
+        .gb
+        .serial_device::<NetworkLink>()
+        .is_some_and(|link| link.is_connected());
+    if !state.is_network_linked || is_connected {
+        return Ok(());
+    }
+
+    handle_disconnect_btn_click(state);
+    Err("The connection to the other player was lost".into())
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn connect_network_link(state: &mut State, link: NetworkLink) {
+    // rewinding would break the link like with a local one
+    state.gb.disable_rewind();
+    state.gb.connect_serial_device(Some(Box::new(link)));
+    state.is_network_linked = true;
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn handle_connect_printer_btn_click(state: &mut State) {
+    handle_disconnect_btn_click(state);
+    state
+        .gb
+        .connect_serial_device(Some(Box::new(Printer::new())));
+    state.is_printer_connected = true;
+}
+
+/// Saves the pages that came out of the printer, without overwriting earlier printouts
+#[cfg(not(target_family = "wasm"))]
+pub fn save_printed_pages(state: &mut State) -> Result<()> {
+    let Some(printer) = state.gb.serial_device_mut::<Printer>() else {
+        return Ok(());
+    };
+
+    let directory = printout_directory(state.last_used_dir.as_deref());
+    for page in printer.take_pages() {
+        // the count starts over every session, so skip the pages printed before
+        let path = loop {
+            state.printed_pages += 1;
+            let path = directory.join(format!("rustboy_printout_{}.png", state.printed_pages));
+            if !path.exists() {
+                break path;
+            }
+        };
+        std::fs::write(&path, page.to_png())?;
+        state.last_printout = Some(path);
+    }
+
+    Ok(())
+}
+
+/// Saves the page being printed too, for when the printer goes away
+#[cfg(not(target_family = "wasm"))]
+pub fn finish_printout(state: &mut State) -> Result<()> {
+    if let Some(printer) = state.gb.serial_device_mut::<Printer>() {
+        printer.finish_page();
+    }
+    save_printed_pages(state)
+}
+
+/// The user's pictures folder, or else the last folder a file was opened from, or their home
+#[cfg(not(target_family = "wasm"))]
+fn printout_directory(last_used_dir: Option<&str>) -> PathBuf {
+    let user_dirs = UserDirs::new();
+    user_dirs
+        .as_ref()
+        .and_then(|user_dirs| user_dirs.picture_dir())
+        .map(|picture_dir| picture_dir.to_owned())
+        .or_else(|| last_used_dir.map(PathBuf::from))
+        .or_else(|| user_dirs.map(|user_dirs| user_dirs.home_dir().to_owned()))
+        .unwrap_or_default()
+}
+
+pub fn handle_disconnect_btn_click(state: &mut State) {
+    if let Some(mut link) = state.link.take() {
+        LinkCable::disconnect(&mut state.gb, &mut link.gb);
+        state.gb.enable_rewind(RewindConfig::new());
+    }
+
+    if state.is_network_linked {
+        state.gb.connect_serial_device(None);
+        state.gb.enable_rewind(RewindConfig::new());
+        state.is_network_linked = false;
+    }
+
+    if state.is_printer_connected {
+        #[cfg(not(target_family = "wasm"))]
+        if let Err(err) = finish_printout(state) {
+            state.error = Some(err);
+            state.show_error = true;
+        }
+        state.gb.connect_serial_device(None);
+        state.is_printer_connected = false;
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    {
+        state.network_listener = None;
+    }
+}
@@ -1,10 +1,31 @@
+mod apu_viewer;
+mod audio;
+#[cfg(not(target_family = "wasm"))]
+mod audio_device;
 mod config;
+mod gbs_player;
+mod link;
+#[cfg(not(target_family = "wasm"))]
+mod movie;
+#[cfg(not(target_family = "wasm"))]
+mod recording;
 #[cfg(target_family = "wasm")]
 mod wasm;
 
+use audio::{Audio, Pacing};
 use config::*;
 use gb_emu_common::cartridge::header::Header;
+use gb_emu_common::compatibility_palettes::{CompatibilityPalette, BUTTON_COMBINATIONS};
+use gb_emu_common::gbs::is_gbs;
+use gb_emu_common::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gb_emu_common::joypad::{Button, Buttons};
+use gb_emu_common::model::Model;
+use gb_emu_common::palette::rgb555_to_rgb888;
+use gb_emu_common::rewind::RewindConfig;
+use gb_emu_common::GameBoy;
+use gbs_player::GbsPlayer;
 use gilrs::{Event as GamepadEvent, Gilrs};
+use link::Link;
 use macroquad::prelude::*;
 use std::error::Error;
 use std::path::{Path, PathBuf};
@@ -13,6 +34,8 @@ use std::path::{Path, PathBuf};
 use directories::UserDirs;
 #[cfg(not(target_family = "wasm"))]
 use native_dialog::FileDialog;
+#[cfg(not(target_family = "wasm"))]
+use std::net::TcpListener;
 
 #[cfg(target_family = "wasm")]
 use std::cell::RefCell;
@@ -25,13 +48,104 @@ pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 const GB_SCREEN_WIDTH: f32 = 160.;
 const GB_SCREEN_HEIGHT: f32 = 144.;
+const SGB_SCREEN_WIDTH: f32 = 256.;
+const SGB_SCREEN_HEIGHT: f32 = 224.;
 const MENU_BAR_HEIGHT: f32 = 23.;
+const REWIND_KEY: KeyCode = KeyCode::Backspace;
+const KEY_MAPPING: [(KeyCode, Button); 8] = [
+    (KeyCode::Right, Button::Right),
+    (KeyCode::Left, Button::Left),
+    (KeyCode::Up, Button::Up),
+    (KeyCode::Down, Button::Down),
+    (KeyCode::X, Button::A),
+    (KeyCode::Z, Button::B),
+    (KeyCode::RightShift, Button::Select),
+    (KeyCode::Enter, Button::Start),
+];
+/// Keys of the second player when two Game Boys are linked, or on a Super Game Boy
+const PLAYER_2_KEY_MAPPING: [(KeyCode, Button); 8] = [
+    (KeyCode::D, Button::Right),
+    (KeyCode::A, Button::Left),
+    (KeyCode::W, Button::Up),
+    (KeyCode::S, Button::Down),
+    (KeyCode::H, Button::A),
+    (KeyCode::G, Button::B),
+    (KeyCode::Tab, Button::Select),
+    (KeyCode::Space, Button::Start),
+];
+/// Keys of the third and fourth players on a Super Game Boy
+const PLAYER_3_KEY_MAPPING: [(KeyCode, Button); 8] = [
+    (KeyCode::L, Button::Right),
+    (KeyCode::J, Button::Left),
+    (KeyCode::I, Button::Up),
+    (KeyCode::K, Button::Down),
+    (KeyCode::P, Button::A),
+    (KeyCode::O, Button::B),
+    (KeyCode::Key7, Button::Select),
+    (KeyCode::Key8, Button::Start),
+];
+const PLAYER_4_KEY_MAPPING: [(KeyCode, Button); 8] = [
+    (KeyCode::Kp6, Button::Right),
+    (KeyCode::Kp4, Button::Left),
+    (KeyCode::Kp8, Button::Up),
+    (KeyCode::Kp5, Button::Down),
+    (KeyCode::KpAdd, Button::A),
+    (KeyCode::KpSubtract, Button::B),
+    (KeyCode::KpDivide, Button::Select),
+    (KeyCode::KpEnter, Button::Start),
+];
+
+/// Colors DMG games are shown with
+#[derive(Clone, Copy, PartialEq)]
+pub enum DmgPalette {
+    /// The shades of a real DMG
+    Grayscale,
+    /// The palette the CGB boot ROM picks for the game
+    Automatic,
+    /// One of the palettes selectable with the buttons on the CGB boot ROM
+    Manual(usize),
+    /// Run on a Super Game Boy, which colorizes the games made for it and adds a border
+    SuperGameBoy,
+}
+
+impl DmgPalette {
+    /// Palette to give the emulator when running on CGB
+    pub fn compatibility_palette(self) -> Option<CompatibilityPalette> {
+        match self {
+            DmgPalette::Manual(index) => CompatibilityPalette::from_button_combination(index),
+            _ => None,
+        }
+    }
+}
 
 pub struct State {
+    pub gb: GameBoy,
+    pub audio: Audio,
+    pub dmg_palette: DmgPalette,
+    /// Second Game Boy connected with a link cable
+    pub link: Option<Link>,
+    /// Address to host on or to join for a link over the network
+    pub network_address: String,
+    /// Waiting for another emulator to join
+    #[cfg(not(target_family = "wasm"))]
+    pub network_listener: Option<TcpListener>,
+    /// The link port is connected to another emulator
+    pub is_network_linked: bool,
+    pub is_printer_connected: bool,
+    /// Pages printed since the emulator started, used to name the PNG files
+    pub printed_pages: usize,
+    pub last_printout: Option<PathBuf>,
+    /// Where to save the VGM log of the sound registers once it's stopped
+    #[cfg(not(target_family = "wasm"))]
+    pub vgm_path: Option<PathBuf>,
+    pub is_running: bool,
     pub quit: bool,
     pub show_menu_bar: bool,
     pub show_rom_info_window: bool,
+    pub show_apu_window: bool,
     pub rom_info_description: Option<String>,
+    pub rom_title: Option<String>,
+    pub gbs_player: Option<GbsPlayer>,
     pub is_waiting_file_callback: bool,
     pub last_used_dir: Option<String>,
     pub last_gamepad_event: Option<String>,
@@ -42,12 +156,31 @@ pub struct State {
 impl State {
     pub fn new() -> State {
         let last_used_dir = read_config(ConfigFile::LastUsedDirectory).unwrap_or(None);
+        let mut gb = GameBoy::new();
+        gb.enable_rewind(RewindConfig::new());
 
         State {
+            gb,
+            audio: Audio::new(),
+            dmg_palette: DmgPalette::Grayscale,
+            link: None,
+            network_address: String::from(link::DEFAULT_NETWORK_ADDRESS),
+            #[cfg(not(target_family = "wasm"))]
+            network_listener: None,
+            is_network_linked: false,
+            is_printer_connected: false,
+            printed_pages: 0,
+            last_printout: None,
+            #[cfg(not(target_family = "wasm"))]
+            vgm_path: None,
+            is_running: false,
             quit: false,
             show_menu_bar: true,
             show_rom_info_window: false,
+            show_apu_window: false,
             rom_info_description: None,
+            rom_title: None,
+            gbs_player: None,
             is_waiting_file_callback: false,
             last_used_dir,
             last_gamepad_event: None,
@@ -76,6 +209,17 @@ async fn main() {
     let mut gilrs = Gilrs::new().unwrap();
     let mut state = State::new();
 
+    let mut screen = Image::gen_image_color(SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16, WHITE);
+    let screen_texture = Texture2D::from_image(&screen);
+    screen_texture.set_filter(FilterMode::Nearest);
+    let mut player_2_screen = screen.clone();
+    let player_2_screen_texture = Texture2D::from_image(&player_2_screen);
+    player_2_screen_texture.set_filter(FilterMode::Nearest);
+    let mut sgb_screen =
+        Image::gen_image_color(SGB_SCREEN_WIDTH as u16, SGB_SCREEN_HEIGHT as u16, WHITE);
+    let sgb_screen_texture = Texture2D::from_image(&sgb_screen);
+    sgb_screen_texture.set_filter(FilterMode::Nearest);
+
     #[cfg(target_family = "wasm")]
     let web_events: Rc<RefCell<WebEvents>> = Rc::new(RefCell::new(WebEvents::new()));
 
@@ -90,6 +234,13 @@ async fn main() {
         clear_background(BLACK);
 
         if state.quit {
+            // finish the WAV headers, there's nowhere left to report an error to
+            let _ = state.gb.stop_audio_recording();
+            #[cfg(not(target_family = "wasm"))]
+            {
+                let _ = recording::handle_stop_vgm_log_btn_click(&mut state);
+                let _ = link::finish_printout(&mut state);
+            }
             break;
         }
 
@@ -110,6 +261,27 @@ async fn main() {
             state.show_menu_bar = !state.show_menu_bar;
         }
 
+        #[cfg(not(target_family = "wasm"))]
+        for result in [
+            link::poll_network_host(&mut state),
+            link::poll_network_link(&mut state),
+            link::save_printed_pages(&mut state),
+        ] {
+            if let Err(err) = result {
+                state.error = Some(err);
+                state.show_error = true;
+            }
+        }
+
+        if state.is_running {
+            let result = run_emulation(&mut state);
+            if let Err(err) = result {
+                state.error = Some(err);
+                state.show_error = true;
+                state.is_running = false;
+            }
+        }
+
         // Gamepad events
         while let Some(GamepadEvent { id, event, time }) = gilrs.next_event() {
             let event_description = format!("{:?} New event from {}: {:?}", time, id, event);
@@ -138,14 +310,184 @@ async fn main() {
                                 ui.close_menu();
                             }
 
-                            if cfg!(not(target_family = "wasm")) {
-                                if ui.button("Quit").clicked() {
-                                    state.quit = true;
+                            if cfg!(not(target_family = "wasm")) && ui.button("Quit").clicked() {
+                                state.quit = true;
+                                ui.close_menu();
+                            }
+                        });
+
+                        #[cfg(not(target_family = "wasm"))]
+                        if state.gb.has_rom_loaded() {
+                            ui.menu_button("Movie", |ui| {
+                                let mut result = Ok(());
+                                if ui.button("Record").clicked() {
+                                    result = movie::handle_record_movie_btn_click(&mut state);
+                                    ui.close_menu();
+                                }
+
+                                if ui.button("Play").clicked() {
+                                    result = movie::handle_play_movie_btn_click(&mut state);
+                                    ui.close_menu();
+                                }
+
+                                if state.gb.movie.is_some() && ui.button("Stop").clicked() {
+                                    result = movie::handle_stop_movie_btn_click(&mut state);
+                                    ui.close_menu();
+                                }
+
+                                if let Err(err) = result {
+                                    state.error = Some(err);
+                                    state.show_error = true;
+                                }
+                            });
+                        }
+
+                        ui.menu_button("Palette", |ui| {
+                            let mut choices = vec![
+                                ("Grayscale", DmgPalette::Grayscale),
+                                ("Automatic", DmgPalette::Automatic),
+                                ("Super Game Boy", DmgPalette::SuperGameBoy),
+                            ];
+                            for (index, (name, _, _)) in BUTTON_COMBINATIONS.iter().enumerate() {
+                                choices.push((name, DmgPalette::Manual(index)));
+                            }
+
+                            for (name, choice) in choices {
+                                if ui
+                                    .selectable_label(state.dmg_palette == choice, name)
+                                    .on_hover_text("Switching to or from grayscale or Super Game Boy takes effect on the next ROM load")
+                                    .clicked()
+                                {
+                                    state.dmg_palette = choice;
+                                    state.gb.set_compatibility_palette(choice.compatibility_palette());
+                                    if let Some(link) = &mut state.link {
+                                        link.gb.set_compatibility_palette(choice.compatibility_palette());
+                                    }
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+
+                        ui.menu_button("Audio", |ui| {
+                            if !state.audio.is_available() {
+                                ui.label("No audio device");
+                            }
+                            ui.checkbox(&mut state.audio.is_muted, "Mute");
+                            ui.add(egui::Slider::new(&mut state.audio.volume, 0.0..=1.0).text("Volume"));
+                            if ui
+                                .button("Sound channels")
+                                .on_hover_text("Shows each channel, with buttons to mute them or hear them alone")
+                                .clicked()
+                            {
+                                state.show_apu_window = true;
+                                ui.close_menu();
+                            }
+                            ui.separator();
+                            ui.radio_value(&mut state.audio.pacing, Pacing::Video, "Sync to video")
+                                .on_hover_text("Runs a frame per screen refresh");
+                            ui.radio_value(&mut state.audio.pacing, Pacing::Audio, "Sync to audio")
+                                .on_hover_text("Runs frames as the audio device needs them");
+
+                            #[cfg(not(target_family = "wasm"))]
+                            {
+                                ui.separator();
+                                let mut result = Ok(());
+                                if state.gb.is_recording_audio() {
+                                    if ui.button("Stop recording").clicked() {
+                                        result = recording::handle_stop_recording_btn_click(&mut state);
+                                        ui.close_menu();
+                                    }
+                                } else {
+                                    if ui.button("Record to WAV").clicked() {
+                                        result = recording::handle_record_audio_btn_click(&mut state, false);
+                                        ui.close_menu();
+                                    }
+                                    if ui
+                                        .button("Record to WAV with channel stems")
+                                        .on_hover_text("Also saves each channel to its own file")
+                                        .clicked()
+                                    {
+                                        result = recording::handle_record_audio_btn_click(&mut state, true);
+                                        ui.close_menu();
+                                    }
+                                }
+
+                                if state.gb.is_logging_vgm() {
+                                    if ui.button("Stop VGM log").clicked() {
+                                        result = recording::handle_stop_vgm_log_btn_click(&mut state);
+                                        ui.close_menu();
+                                    }
+                                } else if ui
+                                    .button("Log to VGM")
+                                    .on_hover_text("Logs the writes to the sound registers, for VGM players")
+                                    .clicked()
+                                {
+                                    result = recording::handle_log_vgm_btn_click(&mut state);
                                     ui.close_menu();
                                 }
+
+                                if let Err(err) = result {
+                                    state.error = Some(err);
+                                    state.show_error = true;
+                                }
                             }
                         });
 
+                        #[cfg(not(target_family = "wasm"))]
+                        if state.gb.has_rom_loaded() {
+                            ui.menu_button("Link", |ui| {
+                                if ui.button("Open ROM for player 2").clicked() {
+                                    let result = link::handle_open_player_2_btn_click(&mut state);
+                                    if let Err(err) = result {
+                                        state.error = Some(err);
+                                        state.show_error = true;
+                                    }
+                                    ui.close_menu();
+                                }
+
+                                ui.separator();
+                                ui.text_edit_singleline(&mut state.network_address);
+                                let mut result = Ok(());
+                                if ui.button("Host").clicked() {
+                                    result = link::handle_host_btn_click(&mut state);
+                                    ui.close_menu();
+                                }
+
+                                if ui.button("Join").clicked() {
+                                    result = link::handle_join_btn_click(&mut state);
+                                    ui.close_menu();
+                                }
+
+                                if let Err(err) = result {
+                                    state.error = Some(err);
+                                    state.show_error = true;
+                                }
+
+                                if state.network_listener.is_some() {
+                                    ui.label("Waiting for the other player...");
+                                }
+
+                                ui.separator();
+                                if ui.button("Connect printer").clicked() {
+                                    link::handle_connect_printer_btn_click(&mut state);
+                                    ui.close_menu();
+                                }
+
+                                if let Some(path) = &state.last_printout {
+                                    ui.label(format!("Last printout: {}", path.display()));
+                                }
+
+                                let is_linked = state.link.is_some()
+                                    || state.is_network_linked
+                                    || state.network_listener.is_some()
+                                    || state.is_printer_connected;
+                                if is_linked && ui.button("Disconnect").clicked() {
+                                    link::handle_disconnect_btn_click(&mut state);
+                                    ui.close_menu();
+                                }
+                            });
+                        }
+
                         cfg_if::cfg_if! {
                             if #[cfg(target_family = "wasm")] {
                                 ui.menu_button("View", |ui| {
@@ -171,6 +513,13 @@ async fn main() {
                     });
             }
 
+            apu_viewer::show_apu_window(ctx, &mut state);
+
+            if let Err(err) = gbs_player::show_gbs_window(ctx, &mut state) {
+                state.error = Some(err);
+                state.show_error = true;
+            }
+
             if let Some(event) = &state.last_gamepad_event {
                 egui::Window::new("Gamepad event").show(ctx, |ui| {
                     ui.label(event);
@@ -197,9 +546,17 @@ async fn main() {
         };
         let screen_width = screen_width();
 
+        // linked Game Boys are shown side by side
+        let screen_amount = if state.link.is_some() { 2.0 } else { 1.0 };
+        // a Super Game Boy draws a border around the screen
+        let sgb_framebuffer = state.gb.sgb_screen().filter(|_| state.link.is_none());
+        let (gb_screen_width, gb_screen_height) = match sgb_framebuffer {
+            Some(_) => (SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT),
+            None => (GB_SCREEN_WIDTH, GB_SCREEN_HEIGHT),
+        };
         let (w, h) = scale_image(
-            GB_SCREEN_WIDTH,
-            GB_SCREEN_HEIGHT,
+            gb_screen_width * screen_amount,
+            gb_screen_height,
             screen_width,
             screen_height,
         );
@@ -211,7 +568,28 @@ async fn main() {
             0.0
         };
         // draw gb screen
-        draw_rectangle(x, y + offset_y, w, h, WHITE);
+        let params = DrawTextureParams {
+            dest_size: Some(vec2(w / screen_amount, h)),
+            ..Default::default()
+        };
+        if let Some(framebuffer) = &sgb_framebuffer {
+            update_screen(&mut sgb_screen, framebuffer);
+            sgb_screen_texture.update(&sgb_screen);
+            draw_texture_ex(sgb_screen_texture, x, y + offset_y, WHITE, params.clone());
+        } else {
+            if state.gb.has_rom_loaded() {
+                update_screen(&mut screen, state.gb.framebuffer());
+                screen_texture.update(&screen);
+            }
+            draw_texture_ex(screen_texture, x, y + offset_y, WHITE, params.clone());
+        }
+
+        if let Some(link) = &state.link {
+            update_screen(&mut player_2_screen, link.gb.framebuffer());
+            player_2_screen_texture.update(&player_2_screen);
+            let x = x + w / screen_amount;
+            draw_texture_ex(player_2_screen_texture, x, y + offset_y, WHITE, params);
+        }
 
         // draw egui
         egui_macroquad::draw();
@@ -220,6 +598,81 @@ async fn main() {
     }
 }
 
+/// Copies the framebuffer of the emulator to an RGBA image
+fn update_screen(screen: &mut Image, framebuffer: &[u16]) {
+    for (pixel, &color) in screen.bytes.chunks_exact_mut(4).zip(framebuffer) {
+        let [r, g, b] = rgb555_to_rgb888(color);
+        pixel.copy_from_slice(&[r, g, b, 0xFF]);
+    }
+}
+
+/// CGB games run on a CGB, DMG games too unless they're shown in grayscale or on a
+/// Super Game Boy
+pub fn preferred_model(header: &Header, dmg_palette: DmgPalette) -> Model {
+    match dmg_palette {
+        _ if header.supports_cgb() => Model::Cgb,
+        DmgPalette::Grayscale => Model::Dmg,
+        DmgPalette::SuperGameBoy => Model::Sgb,
+        _ => Model::Cgb,
+    }
+}
+
+/// Runs the frames of emulation for a screen refresh, and plays their audio
+fn run_emulation(state: &mut State) -> Result<()> {
+    state.gb.set_sample_rate(state.audio.emulator_sample_rate());
+    for _ in 0..state.audio.frames_to_run() {
+        run_emulation_frame(state)?;
+    }
+
+    let samples = state.gb.take_audio_samples();
+    state.audio.play(samples);
+    // only the first Game Boy is heard when linked
+    if let Some(link) = &mut state.link {
+        link.gb.take_audio_samples();
+    }
+
+    Ok(())
+}
+
+/// Runs a frame of emulation, or goes back in time while the rewind key is held
+fn run_emulation_frame(state: &mut State) -> Result<()> {
+    if state.gb.rewind.is_some() && is_key_down(REWIND_KEY) {
+        state.gb.rewind()?;
+        return Ok(());
+    }
+
+    for (key, button) in KEY_MAPPING {
+        state.gb.set_button(button, is_key_down(key));
+    }
+
+    if let Some(link) = &mut state.link {
+        for (key, button) in PLAYER_2_KEY_MAPPING {
+            link.gb.set_button(button, is_key_down(key));
+        }
+
+        link.cable.run_frame(&mut state.gb, &mut link.gb)?;
+        return Ok(());
+    }
+
+    // the other players use the joypads of the Super Game Boy when not linked
+    let other_players = [
+        (2, PLAYER_2_KEY_MAPPING),
+        (3, PLAYER_3_KEY_MAPPING),
+        (4, PLAYER_4_KEY_MAPPING),
+    ];
+    for (player, key_mapping) in other_players {
+        let mut buttons = Buttons::new();
+        for (key, button) in key_mapping {
+            buttons.set(button, is_key_down(key));
+        }
+        state.gb.set_player_buttons(player, buttons);
+    }
+
+    state.gb.run_frame()?;
+
+    Ok(())
+}
+
 #[cfg(not(target_family = "wasm"))]
 fn handle_open_file_btn_click(state: &mut State) -> Result<()> {
     let last_folder_path = state.last_used_dir.clone().map(PathBuf::from);
@@ -242,12 +695,19 @@ fn handle_open_file_btn_click(state: &mut State) -> Result<()> {
         }
 
         let rom = std::fs::read(&rom_path)?;
+        if is_gbs(&rom) {
+            return gbs_player::open_gbs(state, &rom);
+        }
+
         let header = Header::read_rom_header(&rom)?;
+        state
+            .gb
+            .set_model(preferred_model(&header, state.dmg_palette));
+        state.rom_title = header.title.clone();
         let rom_title = header.title.unwrap_or_else(|| String::from("<NO TITLE>"));
         let file_name: &str = Path::new(&rom_path)
             .file_name()
-            .map(|file_name| file_name.to_str())
-            .flatten()
+            .and_then(|file_name| file_name.to_str())
             .unwrap_or(&rom_path);
         let cartridge_type = header.cartridge_type;
         let file_size = rom.len();
@@ -262,6 +722,10 @@ fn handle_open_file_btn_click(state: &mut State) -> Result<()> {
             "
         );
 
+        state.gb.load_rom(rom)?;
+        state.gbs_player = None;
+        state.is_running = true;
+
         state.rom_info_description = Some(description);
         state.show_rom_info_window = true; // Show ROM information window
         state.show_error = false;
@@ -281,6 +745,7 @@ fn open_file(last_folder: &Option<PathBuf>) -> Result<Option<String>> {
     let path = FileDialog::new()
         .set_location(&start_path)
         .add_filter("GB/GBC ROM", &["gb", "gbc"])
+        .add_filter("GBS music", &["gbs"])
         .add_filter("All files", &["*"])
         .show_open_single_file()?
         .map(|path| {
@@ -0,0 +1,96 @@
+use gb_emu_common::movie::bk2::{export_bk2, import_bk2};
+use gb_emu_common::movie::{Movie, MovieStart};
+use gb_emu_common::rewind::RewindConfig;
+use native_dialog::FileDialog;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Result, State};
+
+/// Starts recording a movie from power-on
+pub fn handle_record_movie_btn_click(state: &mut State) -> Result<()> {
+    // seed the real time clock with the current time, so it can be reproduced later
+    let rtc_seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    state.gb.record_movie(MovieStart::PowerOn, rtc_seed)?;
+    // rewinding would desync the movie, like with a link
+    state.gb.disable_rewind();
+    state.is_running = true;
+    Ok(())
+}
+
+pub fn handle_play_movie_btn_click(state: &mut State) -> Result<()> {
+    let path = FileDialog::new()
+        .set_location(&start_location(state))
+        .add_filter("Movie", &["gbm", "bk2"])
+        .add_filter("All files", &["*"])
+        .show_open_single_file()?;
+
+    if let Some(path) = path {
+        let data = std::fs::read(&path)?;
+        let movie = if is_bk2(&path) {
+            import_bk2(&data, state.gb.rom_hash)?
+        } else {
+            Movie::from_bytes(&data)?
+        };
+
+        state.gb.play_movie(movie)?;
+        state.gb.disable_rewind();
+        state.is_running = true;
+    }
+
+    Ok(())
+}
+
+/// Stops the current movie, asking where to save it if it was being recorded
+pub fn handle_stop_movie_btn_click(state: &mut State) -> Result<()> {
+    let is_recording = state
+        .gb
+        .movie
+        .as_ref()
+        .is_some_and(|movie| movie.is_recording());
+
+    let movie = state.gb.stop_movie();
+    if state.link.is_none() && !state.is_network_linked {
+        state.gb.enable_rewind(RewindConfig::new());
+    }
+    let movie = match movie {
+        Some(movie) if is_recording => movie,
+        _ => return Ok(()),
+    };
+
+    let path = FileDialog::new()
+        .set_location(&start_location(state))
+        .add_filter("Movie", &["gbm"])
+        .add_filter("BizHawk movie", &["bk2"])
+        .show_save_single_file()?;
+
+    if let Some(path) = path {
+        let data = if is_bk2(&path) {
+            let game_name = state.rom_title.clone().unwrap_or_default();
+            export_bk2(&movie, &game_name)?
+        } else {
+            movie.to_bytes()
+        };
+
+        std::fs::write(&path, data)?;
+    }
+
+    Ok(())
+}
+
+fn start_location(state: &State) -> PathBuf {
+    state
+        .last_used_dir
+        .clone()
+        .map(PathBuf::from)
+        .unwrap_or_default()
+}
+
+fn is_bk2(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("bk2"))
+}
@@ -0,0 +1,78 @@
+use gb_emu_common::audio_recorder::{stem_path, AudioRecorder, WavOutput};
+use native_dialog::FileDialog;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::{Result, State};
+
+/// Asks where to save the audio and starts recording it, with a file for each channel
+/// too if `with_stems` is set
+pub fn handle_record_audio_btn_click(state: &mut State, with_stems: bool) -> Result<()> {
+    let location = state
+        .last_used_dir
+        .clone()
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let path = FileDialog::new()
+        .set_location(&location)
+        .add_filter("WAV audio", &["wav"])
+        .show_save_single_file()?;
+
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    let stems = if with_stems {
+        Some([
+            create(&stem_path(&path, 0))?,
+            create(&stem_path(&path, 1))?,
+            create(&stem_path(&path, 2))?,
+            create(&stem_path(&path, 3))?,
+        ])
+    } else {
+        None
+    };
+
+    let recorder = AudioRecorder::new(create(&path)?, stems, state.audio.sample_rate())?;
+    state.gb.start_audio_recording(recorder);
+    Ok(())
+}
+
+pub fn handle_stop_recording_btn_click(state: &mut State) -> Result<()> {
+    state.gb.stop_audio_recording()?;
+    Ok(())
+}
+
+/// Asks where to save the VGM log and starts logging the sound registers
+pub fn handle_log_vgm_btn_click(state: &mut State) -> Result<()> {
+    let location = state
+        .last_used_dir
+        .clone()
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let path = FileDialog::new()
+        .set_location(&location)
+        .add_filter("VGM music", &["vgm"])
+        .show_save_single_file()?;
+
+    if let Some(path) = path {
+        state.gb.start_vgm_logging();
+        state.vgm_path = Some(path);
+    }
+
+    Ok(())
+}
+
+pub fn handle_stop_vgm_log_btn_click(state: &mut State) -> Result<()> {
+    if let (Some(path), Some(vgm)) = (state.vgm_path.take(), state.gb.stop_vgm_logging()) {
+        fs::write(path, vgm)?;
+    }
+
+    Ok(())
+}
+
+fn create(path: &Path) -> Result<Box<dyn WavOutput>> {
+    Ok(Box::new(BufWriter::new(File::create(path)?)))
+}
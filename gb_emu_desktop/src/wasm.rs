@@ -1,4 +1,5 @@
 use gb_emu_common::cartridge::header::Header;
+use gb_emu_common::gbs::is_gbs;
 use js_sys::Uint8Array;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -6,7 +7,7 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{Event, File, FileReader, HtmlInputElement};
 
-use crate::{State, Result};
+use crate::{gbs_player, preferred_model, State, Result};
 
 type JsResult<T> = std::result::Result<T, JsValue>;
 
@@ -65,8 +66,17 @@ pub fn handle_web_events(
     // Clear event
     events.file_event = FileEvent::None;
 
-    if let FileEvent::Open(rom) = &file_event {
+    if let FileEvent::Open(rom) = file_event {
+        if is_gbs(&rom) {
+            state.is_waiting_file_callback = false;
+            return gbs_player::open_gbs(state, &rom);
+        }
+
         let header = Header::read_rom_header(&rom)?;
+        state
+            .gb
+            .set_model(preferred_model(&header, state.dmg_palette));
+        state.rom_title = header.title.clone();
         let rom_title = header.title.unwrap_or_else(|| String::from("NO TITLE"));
         let cartridge_type = header.cartridge_type;
         let file_size = rom.len();
@@ -80,6 +90,9 @@ pub fn handle_web_events(
             "
         );
     
+        state.gb.load_rom(rom)?;
+        state.gbs_player = None;
+        state.is_running = true;
         state.rom_info_description = Some(description);
         state.show_rom_info_window = true;
         state.is_waiting_file_callback = false;
@@ -148,7 +161,7 @@ pub fn open_file_chooser(events: Rc<RefCell<WebEvents>>) -> JsResult<()> {
         .dyn_into::<HtmlInputElement>()?;
 
     input.set_type("file");
-    input.set_accept(".gb,.gbc"); // Accept ".gb" and ".gbc" files
+    input.set_accept(".gb,.gbc,.gbs"); // Accept ROMs and GBS music files
 
     let input_clone = input.clone();
     let closure = Closure::wrap(